@@ -0,0 +1,1160 @@
+//! The `Command::new` chain from before the command table, vendored so the
+//! baseline benchmark keeps measuring the old parser.
+use irc_async::proto::{ChannelExt, Command, MessageParseError, Mode};
+
+/// Constructs a new Command.
+#[allow(clippy::complexity)]
+pub fn command(
+    cmd: &str,
+    args: Vec<&str>,
+    suffix: Option<&str>,
+) -> Result<Command, MessageParseError> {
+    Ok(if cmd.eq_ignore_ascii_case("PASS") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::PASS(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::PASS(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("NICK") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::NICK(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::NICK(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("USER") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 3 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::USER(args[0].to_owned(), args[1].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 4 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::USER(args[0].to_owned(), args[1].to_owned(), args[3].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("OPER") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::OPER(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::OPER(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("MODE") {
+        match suffix {
+            Some(suffix) => raw(cmd, args, Some(suffix)),
+            None => {
+                if args[0].is_channel_name() {
+                    let arg = args[1..].join(" ");
+                    Command::ChannelMODE(args[0].to_owned(), Mode::from_channel_mode_string(&arg)?)
+                } else {
+                    let arg = args[1..].join(" ");
+                    Command::UserMODE(args[0].to_owned(), Mode::from_user_mode_string(&arg)?)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("SERVICE") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 5 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::SERVICE(
+                        args[0].to_owned(),
+                        args[1].to_owned(),
+                        args[2].to_owned(),
+                        args[3].to_owned(),
+                        args[4].to_owned(),
+                        suffix.to_owned(),
+                    )
+                }
+            }
+            None => {
+                if args.len() != 6 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::SERVICE(
+                        args[0].to_owned(),
+                        args[1].to_owned(),
+                        args[2].to_owned(),
+                        args[3].to_owned(),
+                        args[4].to_owned(),
+                        args[5].to_owned(),
+                    )
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("QUIT") {
+        if !args.is_empty() {
+            raw(cmd, args, suffix)
+        } else {
+            match suffix {
+                Some(suffix) => Command::QUIT(Some(suffix.to_owned())),
+                None => Command::QUIT(None),
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("SQUIT") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::SQUIT(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::SQUIT(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("JOIN") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::JOIN(suffix.to_owned(), None, None)
+                } else if args.len() == 1 {
+                    Command::JOIN(args[0].to_owned(), Some(suffix.to_owned()), None)
+                } else if args.len() == 2 {
+                    Command::JOIN(
+                        args[0].to_owned(),
+                        Some(args[1].to_owned()),
+                        Some(suffix.to_owned()),
+                    )
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::JOIN(args[0].to_owned(), None, None)
+                } else if args.len() == 2 {
+                    Command::JOIN(args[0].to_owned(), Some(args[1].to_owned()), None)
+                } else if args.len() == 3 {
+                    Command::JOIN(
+                        args[0].to_owned(),
+                        Some(args[1].to_owned()),
+                        Some(args[2].to_owned()),
+                    )
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("PART") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::PART(suffix.to_owned(), None)
+                } else if args.len() == 1 {
+                    Command::PART(args[0].to_owned(), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::PART(args[0].to_owned(), None)
+                } else if args.len() == 2 {
+                    Command::PART(args[0].to_owned(), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("TOPIC") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::TOPIC(suffix.to_owned(), None)
+                } else if args.len() == 1 {
+                    Command::TOPIC(args[0].to_owned(), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::TOPIC(args[0].to_owned(), None)
+                } else if args.len() == 2 {
+                    Command::TOPIC(args[0].to_owned(), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("NAMES") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::NAMES(Some(suffix.to_owned()), None)
+                } else if args.len() == 1 {
+                    Command::NAMES(Some(args[0].to_owned()), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.is_empty() {
+                    Command::NAMES(None, None)
+                } else if args.len() == 1 {
+                    Command::NAMES(Some(args[0].to_owned()), None)
+                } else if args.len() == 2 {
+                    Command::NAMES(Some(args[0].to_owned()), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("LIST") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::LIST(Some(suffix.to_owned()), None)
+                } else if args.len() == 1 {
+                    Command::LIST(Some(args[0].to_owned()), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.is_empty() {
+                    Command::LIST(None, None)
+                } else if args.len() == 1 {
+                    Command::LIST(Some(args[0].to_owned()), None)
+                } else if args.len() == 2 {
+                    Command::LIST(Some(args[0].to_owned()), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("INVITE") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::INVITE(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::INVITE(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("KICK") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 2 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::KICK(
+                        args[0].to_owned(),
+                        args[1].to_owned(),
+                        Some(suffix.to_owned()),
+                    )
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::KICK(args[0].to_owned(), args[1].to_owned(), None)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("PRIVMSG") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::PRIVMSG(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => raw(cmd, args, suffix),
+        }
+    } else if cmd.eq_ignore_ascii_case("NOTICE") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::NOTICE(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => raw(cmd, args, suffix),
+        }
+    } else if cmd.eq_ignore_ascii_case("MOTD") {
+        if !args.is_empty() {
+            raw(cmd, args, suffix)
+        } else {
+            match suffix {
+                Some(suffix) => Command::MOTD(Some(suffix.to_owned())),
+                None => Command::MOTD(None),
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("LUSERS") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::LUSERS(Some(suffix.to_owned()), None)
+                } else if args.len() == 1 {
+                    Command::LUSERS(Some(args[0].to_owned()), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.is_empty() {
+                    Command::LUSERS(None, None)
+                } else if args.len() == 1 {
+                    Command::LUSERS(Some(args[0].to_owned()), None)
+                } else if args.len() == 2 {
+                    Command::LUSERS(Some(args[0].to_owned()), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("VERSION") {
+        if !args.is_empty() {
+            raw(cmd, args, suffix)
+        } else {
+            match suffix {
+                Some(suffix) => Command::VERSION(Some(suffix.to_owned())),
+                None => Command::VERSION(None),
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("STATS") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::STATS(Some(suffix.to_owned()), None)
+                } else if args.len() == 1 {
+                    Command::STATS(Some(args[0].to_owned()), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.is_empty() {
+                    Command::STATS(None, None)
+                } else if args.len() == 1 {
+                    Command::STATS(Some(args[0].to_owned()), None)
+                } else if args.len() == 2 {
+                    Command::STATS(Some(args[0].to_owned()), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("LINKS") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::LINKS(None, Some(suffix.to_owned()))
+                } else if args.len() == 1 {
+                    Command::LINKS(Some(args[0].to_owned()), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.is_empty() {
+                    Command::LINKS(None, None)
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("TIME") {
+        if !args.is_empty() {
+            raw(cmd, args, suffix)
+        } else {
+            match suffix {
+                Some(suffix) => Command::TIME(Some(suffix.to_owned())),
+                None => Command::TIME(None),
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("CONNECT") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 2 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::CONNECT(
+                        args[0].to_owned(),
+                        args[1].to_owned(),
+                        Some(suffix.to_owned()),
+                    )
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::CONNECT(args[0].to_owned(), args[1].to_owned(), None)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("TRACE") {
+        if !args.is_empty() {
+            raw(cmd, args, suffix)
+        } else {
+            match suffix {
+                Some(suffix) => Command::TRACE(Some(suffix.to_owned())),
+                None => Command::TRACE(None),
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("ADMIN") {
+        if !args.is_empty() {
+            raw(cmd, args, suffix)
+        } else {
+            match suffix {
+                Some(suffix) => Command::ADMIN(Some(suffix.to_owned())),
+                None => Command::ADMIN(None),
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("INFO") {
+        if !args.is_empty() {
+            raw(cmd, args, suffix)
+        } else {
+            match suffix {
+                Some(suffix) => Command::INFO(Some(suffix.to_owned())),
+                None => Command::INFO(None),
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("SERVLIST") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::SERVLIST(Some(suffix.to_owned()), None)
+                } else if args.len() == 1 {
+                    Command::SERVLIST(Some(args[0].to_owned()), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.is_empty() {
+                    Command::SERVLIST(None, None)
+                } else if args.len() == 1 {
+                    Command::SERVLIST(Some(args[0].to_owned()), None)
+                } else if args.len() == 2 {
+                    Command::SERVLIST(Some(args[0].to_owned()), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("SQUERY") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::SQUERY(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::SQUERY(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("WHO") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::WHO(Some(suffix.to_owned()), None)
+                } else if args.len() == 1 {
+                    Command::WHO(Some(args[0].to_owned()), Some(&suffix[..] == "o"))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.is_empty() {
+                    Command::WHO(None, None)
+                } else if args.len() == 1 {
+                    Command::WHO(Some(args[0].to_owned()), None)
+                } else if args.len() == 2 {
+                    Command::WHO(Some(args[0].to_owned()), Some(&args[1][..] == "o"))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("WHOIS") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::WHOIS(None, suffix.to_owned())
+                } else if args.len() == 1 {
+                    Command::WHOIS(Some(args[0].to_owned()), suffix.to_owned())
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::WHOIS(None, args[0].to_owned())
+                } else if args.len() == 2 {
+                    Command::WHOIS(Some(args[0].to_owned()), args[1].to_owned())
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("WHOWAS") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::WHOWAS(suffix.to_owned(), None, None)
+                } else if args.len() == 1 {
+                    Command::WHOWAS(args[0].to_owned(), None, Some(suffix.to_owned()))
+                } else if args.len() == 2 {
+                    Command::WHOWAS(
+                        args[0].to_owned(),
+                        Some(args[1].to_owned()),
+                        Some(suffix.to_owned()),
+                    )
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::WHOWAS(args[0].to_owned(), None, None)
+                } else if args.len() == 2 {
+                    Command::WHOWAS(args[0].to_owned(), None, Some(args[1].to_owned()))
+                } else if args.len() == 3 {
+                    Command::WHOWAS(
+                        args[0].to_owned(),
+                        Some(args[1].to_owned()),
+                        Some(args[2].to_owned()),
+                    )
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("KILL") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::KILL(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::KILL(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("PING") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::PING(suffix.to_owned(), None)
+                } else if args.len() == 1 {
+                    Command::PING(args[0].to_owned(), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::PING(args[0].to_owned(), None)
+                } else if args.len() == 2 {
+                    Command::PING(args[0].to_owned(), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("PONG") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::PONG(suffix.to_owned(), None)
+                } else if args.len() == 1 {
+                    Command::PONG(args[0].to_owned(), Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::PONG(args[0].to_owned(), None)
+                } else if args.len() == 2 {
+                    Command::PONG(args[0].to_owned(), Some(args[1].to_owned()))
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("ERROR") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::ERROR(suffix.to_owned())
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => raw(cmd, args, suffix),
+        }
+    } else if cmd.eq_ignore_ascii_case("AWAY") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::AWAY(Some(suffix.to_owned()))
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => raw(cmd, args, suffix),
+        }
+    } else if cmd.eq_ignore_ascii_case("REHASH") {
+        if args.is_empty() {
+            Command::REHASH
+        } else {
+            raw(cmd, args, suffix)
+        }
+    } else if cmd.eq_ignore_ascii_case("DIE") {
+        if args.is_empty() {
+            Command::DIE
+        } else {
+            raw(cmd, args, suffix)
+        }
+    } else if cmd.eq_ignore_ascii_case("RESTART") {
+        if args.is_empty() {
+            Command::RESTART
+        } else {
+            raw(cmd, args, suffix)
+        }
+    } else if cmd.eq_ignore_ascii_case("SUMMON") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::SUMMON(suffix.to_owned(), None, None)
+                } else if args.len() == 1 {
+                    Command::SUMMON(args[0].to_owned(), Some(suffix.to_owned()), None)
+                } else if args.len() == 2 {
+                    Command::SUMMON(
+                        args[0].to_owned(),
+                        Some(args[1].to_owned()),
+                        Some(suffix.to_owned()),
+                    )
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::SUMMON(args[0].to_owned(), None, None)
+                } else if args.len() == 2 {
+                    Command::SUMMON(args[0].to_owned(), Some(args[1].to_owned()), None)
+                } else if args.len() == 3 {
+                    Command::SUMMON(
+                        args[0].to_owned(),
+                        Some(args[1].to_owned()),
+                        Some(args[2].to_owned()),
+                    )
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("USERS") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::USERS(Some(suffix.to_owned()))
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::USERS(Some(args[0].to_owned()))
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("WALLOPS") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::WALLOPS(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::WALLOPS(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("USERHOST") {
+        if suffix.is_none() {
+            Command::USERHOST(args.into_iter().map(|s| s.to_owned()).collect())
+        } else {
+            raw(cmd, args, suffix)
+        }
+    } else if cmd.eq_ignore_ascii_case("ISON") {
+        if suffix.is_none() {
+            Command::USERHOST(args.into_iter().map(|s| s.to_owned()).collect())
+        } else {
+            raw(cmd, args, suffix)
+        }
+    } else if cmd.eq_ignore_ascii_case("SAJOIN") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::SAJOIN(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::SAJOIN(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("SAMODE") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() == 1 {
+                    Command::SAMODE(args[0].to_owned(), suffix.to_owned(), None)
+                } else if args.len() == 2 {
+                    Command::SAMODE(
+                        args[0].to_owned(),
+                        args[1].to_owned(),
+                        Some(suffix.to_owned()),
+                    )
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 2 {
+                    Command::SAMODE(args[0].to_owned(), args[1].to_owned(), None)
+                } else if args.len() == 3 {
+                    Command::SAMODE(
+                        args[0].to_owned(),
+                        args[1].to_owned(),
+                        Some(args[2].to_owned()),
+                    )
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("SANICK") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::SANICK(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::SANICK(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("SAPART") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::SAPART(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::SAPART(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("SAQUIT") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() != 1 {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::SAQUIT(args[0].to_owned(), suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 2 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::SAQUIT(args[0].to_owned(), args[1].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("NICKSERV") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::NICKSERV(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::NICKSERV(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("CHANSERV") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::CHANSERV(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::CHANSERV(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("OPERSERV") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::OPERSERV(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::OPERSERV(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("BOTSERV") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::BOTSERV(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::BOTSERV(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("HOSTSERV") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::HOSTSERV(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::HOSTSERV(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("MEMOSERV") {
+        match suffix {
+            Some(suffix) => {
+                if !args.is_empty() {
+                    raw(cmd, args, Some(suffix))
+                } else {
+                    Command::MEMOSERV(suffix.to_owned())
+                }
+            }
+            None => {
+                if args.len() != 1 {
+                    raw(cmd, args, suffix)
+                } else {
+                    Command::MEMOSERV(args[0].to_owned())
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("CAP") {
+        if args.len() == 1 {
+            if let Ok(cmd) = args[0].parse() {
+                match suffix {
+                    Some(suffix) => Command::CAP(None, cmd, None, Some(suffix.to_owned())),
+                    None => Command::CAP(None, cmd, None, None),
+                }
+            } else {
+                raw(cmd, args, suffix)
+            }
+        } else if args.len() == 2 {
+            if let Ok(cmd) = args[0].parse() {
+                match suffix {
+                    Some(suffix) => {
+                        Command::CAP(None, cmd, Some(args[1].to_owned()), Some(suffix.to_owned()))
+                    }
+                    None => Command::CAP(None, cmd, Some(args[1].to_owned()), None),
+                }
+            } else if let Ok(cmd) = args[1].parse() {
+                match suffix {
+                    Some(suffix) => {
+                        Command::CAP(Some(args[0].to_owned()), cmd, None, Some(suffix.to_owned()))
+                    }
+                    None => Command::CAP(Some(args[0].to_owned()), cmd, None, None),
+                }
+            } else {
+                raw(cmd, args, suffix)
+            }
+        } else if args.len() == 3 {
+            if let Ok(cmd) = args[1].parse() {
+                match suffix {
+                    Some(suffix) => Command::CAP(
+                        Some(args[0].to_owned()),
+                        cmd,
+                        Some(args[2].to_owned()),
+                        Some(suffix.to_owned()),
+                    ),
+                    None => Command::CAP(
+                        Some(args[0].to_owned()),
+                        cmd,
+                        Some(args[2].to_owned()),
+                        None,
+                    ),
+                }
+            } else {
+                raw(cmd, args, suffix)
+            }
+        } else {
+            raw(cmd, args, suffix)
+        }
+    } else if cmd.eq_ignore_ascii_case("AUTHENTICATE") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::AUTHENTICATE(suffix.to_owned())
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::AUTHENTICATE(args[0].to_owned())
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("ACCOUNT") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::ACCOUNT(suffix.to_owned())
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::ACCOUNT(args[0].to_owned())
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("METADATA") {
+        if args.len() == 2 {
+            match suffix {
+                Some(_) => raw(cmd, args, suffix),
+                None => match args[1].parse() {
+                    Ok(c) => Command::METADATA(args[0].to_owned(), Some(c), None, None),
+                    Err(_) => raw(cmd, args, suffix),
+                },
+            }
+        } else if args.len() > 2 {
+            match args[1].parse() {
+                Ok(c) => Command::METADATA(
+                    args[0].to_owned(),
+                    Some(c),
+                    Some(args.into_iter().skip(1).map(|s| s.to_owned()).collect()),
+                    suffix.map(|s| s.to_owned()),
+                ),
+                Err(_) => {
+                    if args.len() == 3 && suffix.is_some() {
+                        Command::METADATA(
+                            args[0].to_owned(),
+                            None,
+                            Some(args.into_iter().skip(1).map(|s| s.to_owned()).collect()),
+                            suffix.map(|s| s.to_owned()),
+                        )
+                    } else {
+                        raw(cmd, args, suffix)
+                    }
+                }
+            }
+        } else {
+            raw(cmd, args, suffix)
+        }
+    } else if cmd.eq_ignore_ascii_case("MONITOR") {
+        if args.len() == 1 {
+            Command::MONITOR(args[0].to_owned(), suffix.map(|s| s.to_owned()))
+        } else {
+            raw(cmd, args, suffix)
+        }
+    } else if cmd.eq_ignore_ascii_case("BATCH") {
+        match suffix {
+            Some(suffix) => {
+                if args.is_empty() {
+                    Command::BATCH(suffix.to_owned(), None, None)
+                } else if args.len() == 1 {
+                    Command::BATCH(args[0].to_owned(), Some(suffix.parse().unwrap()), None)
+                } else if args.len() > 1 {
+                    Command::BATCH(
+                        args[0].to_owned(),
+                        Some(args[1].parse().unwrap()),
+                        Some(
+                            vec![suffix.to_owned()]
+                                .into_iter()
+                                .chain(args.into_iter().skip(2).map(|s| s.to_owned()))
+                                .collect(),
+                        ),
+                    )
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 1 {
+                    Command::BATCH(args[0].to_owned(), None, None)
+                } else if args.len() == 2 {
+                    Command::BATCH(args[0].to_owned(), Some(args[1].parse().unwrap()), None)
+                } else if args.len() > 2 {
+                    Command::BATCH(
+                        args[0].to_owned(),
+                        Some(args[1].parse().unwrap()),
+                        Some(args.iter().skip(2).map(|&s| s.to_owned()).collect()),
+                    )
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if cmd.eq_ignore_ascii_case("CHGHOST") {
+        match suffix {
+            Some(suffix) => {
+                if args.len() == 1 {
+                    Command::CHGHOST(args[0].to_owned(), suffix.to_owned())
+                } else {
+                    raw(cmd, args, Some(suffix))
+                }
+            }
+            None => {
+                if args.len() == 2 {
+                    Command::CHGHOST(args[0].to_owned(), args[1].to_owned())
+                } else {
+                    raw(cmd, args, suffix)
+                }
+            }
+        }
+    } else if let Ok(resp) = cmd.parse() {
+        Command::Response(
+            resp,
+            args.into_iter().map(|s| s.to_owned()).collect(),
+            suffix.map(|s| s.to_owned()),
+        )
+    } else {
+        raw(cmd, args, suffix)
+    })
+}
+
+fn raw(cmd: &str, args: Vec<&str>, suffix: Option<&str>) -> Command {
+    Command::Raw(
+        cmd.to_owned(),
+        args.into_iter().map(|s| s.to_owned()).collect(),
+        suffix.map(|s| s.to_owned()),
+    )
+}
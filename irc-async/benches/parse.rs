@@ -0,0 +1,138 @@
+#[path = "baseline/command.rs"]
+mod baseline;
+
+use bytes::BytesMut;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use irc_async::proto::message::Tag;
+use irc_async::proto::{Command, IrcCodec, Message, MessageRef};
+use tokio_util::codec::Decoder;
+
+const LINES: &[(&str, &str)] = &[
+    ("ping", "PING :irc.example.net\r\n"),
+    (
+        "privmsg",
+        ":nick!user@host.example.com PRIVMSG #channel :Hello, everyone! How is it going?\r\n",
+    ),
+    (
+        "tagged",
+        "@time=2019-12-01T12:34:56.789Z;msgid=abcdef0123456789;account=nick \
+         :nick!user@host.example.com PRIVMSG #channel :Hello, everyone!\r\n",
+    ),
+    (
+        "names",
+        ":irc.example.net 353 me = #channel :@op +voice alice bob carol dave eve mallory trent\r\n",
+    ),
+];
+
+/// The parser `Message::from_str` used before `MessageRef`, kept as a baseline. Like the old
+/// `Message`, the prefix stays a plain string and the command goes through the old
+/// `Command::new` chain.
+fn parse_baseline(s: &str) -> (Option<Vec<Tag>>, Option<String>, Command) {
+    let mut state = s;
+
+    let tags = if state.starts_with('@') {
+        let tags = state.find(' ').map(|i| &state[1..i]);
+        state = state.find(' ').map_or("", |i| &state[i + 1..]);
+        tags.map(|ts| {
+            ts.split(';')
+                .filter(|s| !s.is_empty())
+                .map(|s: &str| {
+                    let mut iter = s.splitn(2, '=');
+                    let (fst, snd) = (iter.next(), iter.next());
+                    Tag(fst.unwrap_or("").to_owned(), snd.map(|s| s.to_owned()))
+                })
+                .collect::<Vec<_>>()
+        })
+    } else {
+        None
+    };
+
+    let prefix = if state.starts_with(':') {
+        let prefix = state.find(' ').map(|i| &state[1..i]);
+        state = state.find(' ').map_or("", |i| &state[i + 1..]);
+        prefix
+    } else {
+        None
+    };
+
+    let line_ending_len = if state.ends_with("\r\n") {
+        "\r\n"
+    } else if state.ends_with('\r') {
+        "\r"
+    } else if state.ends_with('\n') {
+        "\n"
+    } else {
+        ""
+    }
+    .len();
+
+    let suffix = if state.contains(" :") {
+        let suffix = state
+            .find(" :")
+            .map(|i| &state[i + 2..state.len() - line_ending_len]);
+        state = state.find(" :").map_or("", |i| &state[..=i]);
+        suffix
+    } else {
+        state = &state[..state.len() - line_ending_len];
+        None
+    };
+
+    let command = match state.find(' ').map(|i| &state[..i]) {
+        Some(cmd) => {
+            state = state.find(' ').map_or("", |i| &state[i + 1..]);
+            cmd
+        }
+        None => {
+            let cmd = state;
+            state = "";
+            cmd
+        }
+    };
+
+    let args: Vec<_> = state.splitn(14, ' ').filter(|s| !s.is_empty()).collect();
+
+    (
+        tags,
+        prefix.map(|s| s.to_owned()),
+        baseline::command(command, args, suffix).unwrap(),
+    )
+}
+
+fn parse(c: &mut Criterion) {
+    for (name, line) in LINES {
+        let mut group = c.benchmark_group(*name);
+        group.bench_function("baseline", |b| b.iter(|| parse_baseline(black_box(line))));
+        group.bench_function("Message::from_str", |b| {
+            b.iter(|| black_box(line).parse::<Message>().unwrap())
+        });
+        group.bench_function("MessageRef::parse", |b| {
+            b.iter(|| MessageRef::parse(black_box(line)).unwrap())
+        });
+        group.bench_function("MessageRef::from_bytes + command", |b| {
+            b.iter(|| {
+                let message = MessageRef::from_bytes(black_box(line.as_bytes())).unwrap();
+                (message.command(), message.args().next())
+            })
+        });
+        group.bench_function("IrcCodec::decode", |b| {
+            let mut codec = IrcCodec::default();
+            b.iter(|| {
+                let mut buf = BytesMut::from(black_box(line.as_bytes()));
+                codec.decode(&mut buf).unwrap().unwrap()
+            })
+        });
+        group.bench_function("IrcCodec::decode_line + command", |b| {
+            let mut codec = IrcCodec::default();
+            b.iter(|| {
+                let mut buf = BytesMut::from(black_box(line.as_bytes()));
+                let line = codec.decode_line(&mut buf).unwrap().unwrap();
+                let message = line.message().unwrap();
+                message.command().len() + message.args().count()
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
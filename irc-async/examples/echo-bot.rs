@@ -1,6 +1,5 @@
-use futures::future::{self, FutureExt};
-use futures::stream::StreamExt;
-use irc_async::{Client, ClientError, Config};
+use futures::future::{self, FutureExt, TryFutureExt};
+use irc_async::{Client, ClientError, Config, Dispatcher, EventKind};
 
 type Result<T> = std::result::Result<T, ClientError>;
 
@@ -8,22 +7,37 @@ async fn run() -> Result<()> {
     let config = Config {
         host: "127.0.0.1".into(),
         port: 4444,
-        ssl: false,
         nick: "hello".into(),
+        ctcp_replies: true,
+        ..Default::default()
     };
-    let (mut client, fut) = Client::with_config(config).await?;
+    let (mut client, fut, handle) = Client::with_config(config).await?;
     client.register().await?;
 
-    let handler = async {
-        while let Some(Ok(message)) = client.next().await {
-            println!("message: {:?}", message);
-            client.send(message).await.unwrap();
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.on(move |event| {
+        match event.kind {
+            EventKind::Registered { .. } => handle.join("#echo", None).boxed(),
+            // say everything back to whoever said it, except ourselves
+            EventKind::Privmsg { .. } if event.from_self => future::ok(()).boxed(),
+            EventKind::Privmsg {
+                ref target,
+                is_channel,
+                ref text,
+            } => {
+                let target = match event.nick() {
+                    Some(nick) if !is_channel => nick,
+                    _ => target,
+                };
+                handle.privmsg(target, text).boxed()
+            }
+            _ => future::ok(()).boxed(),
         }
-    };
-
-    future::join(fut, handler).map(|(first, _)| first).await?;
+    });
 
-    Ok(())
+    future::try_join(fut, dispatcher.run(&mut client))
+        .map_ok(|_| ())
+        .await
 }
 
 #[tokio::main]
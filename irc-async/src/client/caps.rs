@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::proto::{CapSubCommand, Capability, Command};
+
+/// The capabilities the client asks for whenever the server offers them. `echo-message` is only
+/// asked for if the config wants it.
+const WANTED: &[Capability] = &[
+    Capability::AccountTag,
+    Capability::Batch,
+    Capability::LabeledResponse,
+    Capability::MessageTags,
+    Capability::Multiline,
+];
+
+/// The state of IRCv3 capability negotiation.
+#[derive(Default)]
+pub struct Caps {
+    /// Whether to ask for `echo-message`.
+    echo_message: bool,
+
+    /// Whether the client is still waiting for the end of negotiation before registering.
+    negotiating: bool,
+
+    /// Capabilities offered so far in a multi-line `CAP LS` reply.
+    offered: Vec<String>,
+
+    /// The values of offered capabilities that have one.
+    values: HashMap<String, String>,
+
+    /// Capabilities the server acknowledged.
+    enabled: HashSet<String>,
+}
+
+impl Caps {
+    /// Creates the negotiation state, asking for `echo-message` too if `echo_message` is set.
+    pub fn new(echo_message: bool) -> Self {
+        Caps {
+            echo_message,
+            ..Caps::default()
+        }
+    }
+
+    /// Starts negotiation, returning the command to send.
+    pub fn start(&mut self) -> Command {
+        self.negotiating = true;
+        Command::CAP(None, CapSubCommand::LS, None, Some("302".to_owned()))
+    }
+
+    /// Returns true if the server acknowledged the capability.
+    pub fn is_enabled(&self, cap: &Capability) -> bool {
+        self.enabled.contains(cap.as_ref())
+    }
+
+    /// Gets the value the server offered the capability with, if any.
+    pub fn value(&self, cap: &Capability) -> Option<&str> {
+        self.values.get(cap.as_ref()).map(String::as_str)
+    }
+
+    /// Handles a `CAP` reply from the server, returning the command to answer with, if any.
+    pub fn handle(
+        &mut self,
+        sub: CapSubCommand,
+        more: Option<&str>,
+        caps: Option<&str>,
+    ) -> Option<Command> {
+        let caps = caps.unwrap_or("").split(' ').filter(|cap| !cap.is_empty());
+        // values of offered capabilities follow an equals sign
+        if let CapSubCommand::LS | CapSubCommand::NEW = sub {
+            for cap in caps.clone() {
+                let mut parts = cap.splitn(2, '=');
+                if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                    let _ = self.values.insert(name.to_owned(), value.to_owned());
+                }
+            }
+        }
+        let caps = caps.map(|cap| cap.split('=').next().unwrap_or(cap));
+        match sub {
+            CapSubCommand::LS => {
+                self.offered.extend(caps.map(str::to_owned));
+                // a `*` before the list means there are more lines to come
+                if more == Some("*") {
+                    return None;
+                }
+                let offered = std::mem::take(&mut self.offered);
+                self.request(offered)
+            }
+            CapSubCommand::NEW => self.request(caps.map(str::to_owned).collect()),
+            CapSubCommand::ACK => {
+                for cap in caps {
+                    if let Some(cap) = cap.strip_prefix('-') {
+                        let _ = self.enabled.remove(cap);
+                    } else {
+                        let _ = self.enabled.insert(cap.to_owned());
+                    }
+                }
+                self.end()
+            }
+            CapSubCommand::NAK => self.end(),
+            CapSubCommand::DEL => {
+                for cap in caps {
+                    let _ = self.enabled.remove(cap);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Requests the wanted capabilities among the offered ones, or ends negotiation if there are
+    /// none.
+    fn request(&mut self, offered: Vec<String>) -> Option<Command> {
+        let echo_message = Some(&Capability::EchoMessage).filter(|_| self.echo_message);
+        let wanted = WANTED
+            .iter()
+            .chain(echo_message)
+            .map(|cap| cap.as_ref())
+            .filter(|cap| {
+                offered.iter().any(|offered| offered == cap) && !self.enabled.contains(*cap)
+            })
+            .collect::<Vec<_>>();
+        if wanted.is_empty() {
+            self.end()
+        } else {
+            Some(Command::CAP(
+                None,
+                CapSubCommand::REQ,
+                None,
+                Some(wanted.join(" ")),
+            ))
+        }
+    }
+
+    /// Ends negotiation, if it is still in progress.
+    fn end(&mut self) -> Option<Command> {
+        if self.negotiating {
+            self.negotiating = false;
+            Some(Command::CAP(None, CapSubCommand::END, None, None))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Caps;
+    use crate::proto::{CapSubCommand, Command};
+
+    fn requested(caps: &mut Caps) -> Option<String> {
+        let _ = caps.start();
+        let offered = "batch echo-message message-tags sasl";
+        match caps.handle(CapSubCommand::LS, None, Some(offered)) {
+            Some(Command::CAP(None, CapSubCommand::REQ, None, wanted)) => wanted,
+            command => panic!("unexpected {:?}", command),
+        }
+    }
+
+    #[test]
+    fn echo_message() {
+        assert_eq!(
+            requested(&mut Caps::default()),
+            Some("batch message-tags".to_owned())
+        );
+        assert_eq!(
+            requested(&mut Caps::new(true)),
+            Some("batch message-tags echo-message".to_owned())
+        );
+    }
+}
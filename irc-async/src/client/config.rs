@@ -1,4 +1,12 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::client::proxy::Proxy;
+use crate::client::tls::TlsConfig;
+use crate::proto::Encoding;
+
 /// Configuration for the IRC client
+#[derive(Clone)]
 pub struct Config {
     /// The hostname to connect to
     pub host: String,
@@ -6,9 +14,81 @@ pub struct Config {
     /// The port of the IRC server
     pub port: u16,
 
+    /// Other servers of the network, as hosts and ports, to try in order if the first can't be
+    /// reached
+    pub fallback_servers: Vec<(String, u16)>,
+
+    /// The local address to connect from, if not the one the system picks
+    pub bind_address: Option<IpAddr>,
+
+    /// How long to wait for each connection attempt, if not as long as the system does
+    pub connect_timeout: Option<Duration>,
+
+    /// Whether to race connection attempts to IPv6 and IPv4 addresses, rather than trying each
+    /// address only once the one before it failed
+    pub happy_eyeballs: bool,
+
+    /// The proxy to connect through, if any. TLS runs through the proxy, to the server.
+    pub proxy: Option<Proxy>,
+
     /// Whether or not to enable SSL
     pub ssl: bool,
 
+    /// Client certificates, extra certificate authorities and pinning for SSL connections
+    pub tls: TlsConfig,
+
+    /// The path to connect to IRC over a WebSocket at, like `/webirc`, if the server offers IRC
+    /// over WebSockets rather than directly. With `ssl`, the WebSocket is secure.
+    pub websocket_path: Option<String>,
+
     /// The nick to connect with
     pub nick: String,
+
+    /// The encoding to decode lines with when they aren't valid UTF-8
+    pub fallback_encoding: Encoding,
+
+    /// How often to send the server a `PING`, to notice if the connection died and measure lag.
+    /// `None` to never send one.
+    pub ping_interval: Option<Duration>,
+
+    /// How long to wait for the server to answer a `PING` before giving up on the connection, and
+    /// for it to close the connection after a `QUIT`. DCC transfers and chats give up on the other
+    /// side after this long too.
+    pub ping_timeout: Duration,
+
+    /// Whether to answer CTCP `VERSION`, `PING`, `TIME` and `CLIENTINFO` queries automatically.
+    /// Only queries sent to the client's nick are answered, at most one every two seconds.
+    pub ctcp_replies: bool,
+
+    /// Whether to ask the server for `echo-message`, so that the messages and notices the client
+    /// sends come back from the server, with the tags it gave them, like `msgid`. Echoes arrive
+    /// as incoming messages from the client's own nick.
+    pub echo_message: bool,
+}
+
+/// Connects to port 6667 of `localhost` as `irc-async`, without TLS or a proxy, racing IPv6 and
+/// IPv4 connections, and sends a `PING` every minute, giving up on the connection if the server
+/// doesn't answer within 30 seconds. CTCP queries aren't answered, and sent messages aren't echoed
+/// back.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            host: "localhost".to_owned(),
+            port: 6667,
+            fallback_servers: Vec::new(),
+            bind_address: None,
+            connect_timeout: None,
+            happy_eyeballs: true,
+            proxy: None,
+            ssl: false,
+            tls: TlsConfig::default(),
+            websocket_path: None,
+            nick: "irc-async".to_owned(),
+            fallback_encoding: Encoding::default(),
+            ping_interval: Some(Duration::from_secs(60)),
+            ping_timeout: Duration::from_secs(30),
+            ctcp_replies: false,
+            echo_message: false,
+        }
+    }
 }
@@ -0,0 +1,277 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use futures::future::{self, Either, Future};
+use futures::stream::{FuturesUnordered, StreamExt};
+use net2::TcpBuilder;
+use tokio::net::TcpStream;
+use tokio::{task, time};
+
+use crate::client::stream::ClientStream;
+use crate::client::websocket::WebSocketStream;
+use crate::client::{ClientError, Config, Result};
+
+/// How long to wait for a connection attempt before starting the next one alongside it, with
+/// Happy Eyeballs. The delay [RFC 8305](https://tools.ietf.org/html/rfc8305) recommends.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connects to the server in the config, or to its fallback servers in order if it can't, and
+/// starts TLS and the WebSocket handshake if they're enabled.
+pub(crate) async fn connect(config: &Config) -> Result<ClientStream> {
+    let servers = std::iter::once((&config.host, config.port)).chain(
+        config
+            .fallback_servers
+            .iter()
+            .map(|(host, port)| (host, *port)),
+    );
+    let mut last_err = None;
+    for (host, port) in servers {
+        match connect_server(config, host, port).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.expect("there is always at least one server"))
+}
+
+async fn connect_server(config: &Config, host: &str, port: u16) -> Result<ClientStream> {
+    let stream = connect_proxied(config, host, port).await?;
+
+    let stream = if config.ssl {
+        ClientStream::Tls(config.tls.connect(host, stream).await?)
+    } else {
+        ClientStream::Plain(stream)
+    };
+    match config.websocket_path {
+        Some(ref path) => {
+            let stream = WebSocketStream::connect(stream, host, port, path).await?;
+            Ok(ClientStream::WebSocket(Box::new(stream)))
+        }
+        None => Ok(stream),
+    }
+}
+
+/// Opens a TCP connection to a host, through the proxy in the config if there is one.
+pub(crate) async fn connect_proxied(config: &Config, host: &str, port: u16) -> Result<TcpStream> {
+    match config.proxy {
+        Some(ref proxy) => {
+            let mut stream = connect_tcp(config, &proxy.host, proxy.port).await?;
+            proxy.handshake(&mut stream, host, port).await?;
+            Ok(stream)
+        }
+        None => Ok(connect_tcp(config, host, port).await?),
+    }
+}
+
+/// Opens a TCP connection to a host, trying its addresses the way the config says to.
+async fn connect_tcp(config: &Config, host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut addrs = resolve(host, port).await?;
+    if let Some(bind_address) = config.bind_address {
+        // a socket can only connect to addresses of the family it's bound in
+        addrs.retain(|addr| addr.is_ipv4() == bind_address.is_ipv4());
+    }
+    let stagger = if config.happy_eyeballs {
+        Some(CONNECTION_ATTEMPT_DELAY)
+    } else {
+        None
+    };
+    connect_any(addrs, stagger, |addr| {
+        let attempt = connect_from(addr, config.bind_address);
+        async move {
+            match config.connect_timeout {
+                Some(timeout) => time::timeout(timeout, attempt)
+                    .await
+                    .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+                None => attempt.await,
+            }
+        }
+    })
+    .await
+}
+
+/// Looks up the addresses of a host on a blocking thread, since the system resolver blocks.
+async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let host = host.to_owned();
+    task::spawn_blocking(move || Ok((&host[..], port).to_socket_addrs()?.collect())).await?
+}
+
+/// Connects to an address, from the bind address if there is one.
+async fn connect_from(addr: SocketAddr, bind_address: Option<IpAddr>) -> io::Result<TcpStream> {
+    match bind_address {
+        Some(bind_address) => {
+            let builder = match addr {
+                SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+                SocketAddr::V6(_) => TcpBuilder::new_v6()?,
+            };
+            let _ = builder.bind(SocketAddr::new(bind_address, 0))?;
+            TcpStream::connect_std(builder.to_tcp_stream()?, &addr).await
+        }
+        None => TcpStream::connect(addr).await,
+    }
+}
+
+/// Orders addresses so that IPv6 and IPv4 alternate, starting with the family of the first one,
+/// and otherwise keeping the order the resolver gave.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = match addrs.first() {
+        Some(addr) => addr.is_ipv6(),
+        None => return addrs,
+    };
+    let (first, second): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    let mut addrs = Vec::new();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => return addrs,
+            (a, b) => addrs.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Connects to the first address that accepts, with `connect`. Without a stagger delay, each
+/// address is tried once the one before it failed. With one, addresses from alternating families
+/// are tried as in [Happy Eyeballs](https://tools.ietf.org/html/rfc8305): each attempt starts
+/// when the one before it failed, or once it has run for the delay without connecting, and the
+/// first attempt to connect wins.
+pub(crate) async fn connect_any<F, Fut, T>(
+    addrs: Vec<SocketAddr>,
+    stagger: Option<Duration>,
+    connect: F,
+) -> io::Result<T>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let addrs = match stagger {
+        Some(_) => interleave(addrs),
+        None => addrs,
+    };
+    let mut pending = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_err = None;
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(addr) => attempts.push(connect(addr)),
+                None => {
+                    return Err(last_err.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to")
+                    }))
+                }
+            }
+        }
+        let result = match stagger {
+            Some(delay) if pending.len() > 0 => {
+                match future::select(attempts.next(), time::delay_for(delay)).await {
+                    Either::Left((result, _)) => result,
+                    // taking too long, so start the next attempt too
+                    Either::Right(_) => None,
+                }
+            }
+            _ => attempts.next().await,
+        };
+        match result {
+            Some(Ok(stream)) => return Ok(stream),
+            Some(Err(err)) => last_err = Some(err),
+            None => (),
+        }
+        if let Some(addr) = pending.next() {
+            attempts.push(connect(addr));
+        }
+    }
+}
+
+impl From<task::JoinError> for ClientError {
+    fn from(err: task::JoinError) -> Self {
+        ClientError::Io(err.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use futures::future;
+    use tokio::time;
+
+    use super::{connect_any, interleave};
+
+    fn v4(port: u16) -> SocketAddr {
+        (Ipv4Addr::LOCALHOST, port).into()
+    }
+
+    fn v6(port: u16) -> SocketAddr {
+        (Ipv6Addr::LOCALHOST, port).into()
+    }
+
+    #[test]
+    fn interleave_families() {
+        assert_eq!(
+            interleave(vec![v6(1), v6(2), v6(3), v4(4), v4(5)]),
+            vec![v6(1), v4(4), v6(2), v4(5), v6(3)]
+        );
+        assert_eq!(
+            interleave(vec![v4(1), v4(2), v6(3)]),
+            vec![v4(1), v6(3), v4(2)]
+        );
+        assert_eq!(interleave(Vec::new()), Vec::new());
+    }
+
+    #[tokio::test]
+    async fn sequential() {
+        let tried = Mutex::new(Vec::new());
+        let result = connect_any(vec![v4(1), v4(2), v4(3)], None, |addr| {
+            tried.lock().unwrap().push(addr.port());
+            future::ready(match addr.port() {
+                2 => Ok(addr),
+                _ => Err(io::Error::from(io::ErrorKind::ConnectionRefused)),
+            })
+        })
+        .await;
+        assert_eq!(result.unwrap(), v4(2));
+        assert_eq!(*tried.lock().unwrap(), vec![1, 2]);
+
+        let result = connect_any(vec![v4(1)], None, |_| {
+            future::ready(Err::<(), _>(io::ErrorKind::ConnectionRefused.into()))
+        })
+        .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+        let result = connect_any(Vec::new(), None, |_| future::ready(Ok(()))).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs() {
+        // the IPv6 address never answers, so the IPv4 one wins once it's tried
+        let result = connect_any(
+            vec![v6(1), v6(2), v4(3)],
+            Some(Duration::from_millis(10)),
+            |addr| async move {
+                if addr.is_ipv6() {
+                    future::pending::<()>().await;
+                }
+                time::delay_for(Duration::from_millis(5)).await;
+                Ok(addr)
+            },
+        )
+        .await;
+        assert_eq!(result.unwrap(), v4(3));
+
+        // failures start the next attempt without waiting
+        let result = connect_any(vec![v6(1), v4(2)], Some(Duration::from_secs(60)), |addr| {
+            future::ready(match addr.port() {
+                1 => Err(io::ErrorKind::ConnectionRefused.into()),
+                _ => Ok(addr),
+            })
+        });
+        let result = time::timeout(Duration::from_secs(1), result).await;
+        assert_eq!(result.unwrap().unwrap(), v4(2));
+    }
+}
@@ -0,0 +1,509 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::pin::Pin;
+
+use futures::future::{Future, FutureExt};
+use futures::stream::{Stream, StreamExt};
+
+use crate::client::Result;
+use crate::proto::{
+    message::Tag, ChannelExt, ChannelMode, Command, Ctcp, ISupport, Message, Mode, Response,
+    Source, UserMode,
+};
+
+/// Something that happened on the server, interpreted from a message so that every consumer
+/// reads messages the same way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    /// Who caused the event: a user, the server, or nobody for events the client makes up itself.
+    pub source: Option<Source>,
+    /// The tags of the message the event came from.
+    pub tags: Vec<Tag>,
+    /// Whether the source is our own nick, like for the echoes of the messages we send with
+    /// `echo-message`. Only a [`Dispatcher`](Dispatcher) knows our nick, so events made straight
+    /// from a message never are.
+    pub from_self: bool,
+    /// What happened.
+    pub kind: EventKind,
+}
+
+/// What happened in an [`Event`](Event).
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventKind {
+    /// The server accepted our registration, with `RPL_WELCOME`.
+    Registered {
+        /// The nick we're registered with, which the server may have changed.
+        nick: String,
+    },
+    /// A message to a channel or to us.
+    Privmsg {
+        /// The channel or nick the message was sent to.
+        target: String,
+        /// Whether the target is a channel.
+        is_channel: bool,
+        /// The text of the message.
+        text: String,
+    },
+    /// A CTCP `ACTION` to a channel or to us, like `/me waves`.
+    Action {
+        /// The channel or nick the action was sent to.
+        target: String,
+        /// Whether the target is a channel.
+        is_channel: bool,
+        /// The text of the action, without the nick.
+        text: String,
+    },
+    /// A notice to a channel or to us.
+    Notice {
+        /// The channel or nick the notice was sent to.
+        target: String,
+        /// Whether the target is a channel.
+        is_channel: bool,
+        /// The text of the notice.
+        text: String,
+    },
+    /// The source joined a channel.
+    Join {
+        /// The channel joined.
+        channel: String,
+    },
+    /// The source left a channel.
+    Part {
+        /// The channel left.
+        channel: String,
+        /// The parting message, if there was one.
+        message: Option<String>,
+    },
+    /// The source removed someone from a channel.
+    Kick {
+        /// The channel they were removed from.
+        channel: String,
+        /// The nick of who was removed.
+        nick: String,
+        /// The reason given, if there was one.
+        reason: Option<String>,
+    },
+    /// The source left the server.
+    Quit {
+        /// The quit message, if there was one.
+        message: Option<String>,
+    },
+    /// The source changed their nick.
+    NickChange {
+        /// The new nick.
+        nick: String,
+    },
+    /// The topic of a channel was changed by the source, or the server told us what it is
+    /// with `RPL_TOPIC`.
+    Topic {
+        /// The channel.
+        channel: String,
+        /// The topic, or `None` if it was cleared.
+        topic: Option<String>,
+    },
+    /// The source changed the modes of a channel.
+    ModeChange {
+        /// The channel.
+        channel: String,
+        /// The modes that were set or unset.
+        modes: Vec<Mode<ChannelMode>>,
+    },
+    /// The modes of our nick were changed.
+    UserModeChange {
+        /// Our nick.
+        nick: String,
+        /// The modes that were set or unset.
+        modes: Vec<Mode<UserMode>>,
+    },
+    /// The source invited someone to a channel.
+    Invite {
+        /// The nick of who was invited, which is usually ours.
+        nick: String,
+        /// The channel.
+        channel: String,
+    },
+    /// The connection to the server closed. This is always the last event.
+    Disconnected {
+        /// Why, from the server's `ERROR` or the error that closed the connection, if known.
+        reason: Option<String>,
+    },
+    /// Any other message, uninterpreted.
+    Other(Command),
+}
+
+impl Event {
+    /// Gets the nick of the user who caused the event, if it came from a user.
+    pub fn nick(&self) -> Option<&str> {
+        self.source.as_ref().and_then(Source::nick)
+    }
+
+    fn disconnected(reason: Option<String>) -> Self {
+        Event {
+            source: None,
+            tags: Vec::new(),
+            from_self: false,
+            kind: EventKind::Disconnected { reason },
+        }
+    }
+}
+
+impl From<Message> for Event {
+    fn from(message: Message) -> Self {
+        let ctcp = Ctcp::from_command(&message.command);
+        let kind = match message.command {
+            Command::Response(Response::RPL_WELCOME, ref args, _) if !args.is_empty() => {
+                EventKind::Registered {
+                    nick: args[0].clone(),
+                }
+            }
+            Command::PRIVMSG(target, text) => match ctcp {
+                Some(Ctcp::Action(text)) => EventKind::Action {
+                    is_channel: target.is_channel_name(),
+                    target,
+                    text,
+                },
+                Some(_) => EventKind::Other(Command::PRIVMSG(target, text)),
+                None => EventKind::Privmsg {
+                    is_channel: target.is_channel_name(),
+                    target,
+                    text,
+                },
+            },
+            Command::NOTICE(target, text) => match ctcp {
+                Some(_) => EventKind::Other(Command::NOTICE(target, text)),
+                None => EventKind::Notice {
+                    is_channel: target.is_channel_name(),
+                    target,
+                    text,
+                },
+            },
+            Command::JOIN(channel, _, _) => EventKind::Join { channel },
+            Command::PART(channel, message) => EventKind::Part { channel, message },
+            Command::KICK(channel, nick, reason) => EventKind::Kick {
+                channel,
+                nick,
+                reason,
+            },
+            Command::QUIT(message) => EventKind::Quit { message },
+            Command::NICK(nick) => EventKind::NickChange { nick },
+            Command::TOPIC(channel, topic) => EventKind::Topic {
+                channel,
+                topic: topic.filter(|topic| !topic.is_empty()),
+            },
+            Command::Response(Response::RPL_TOPIC, ref args, ref topic) if args.len() >= 2 => {
+                EventKind::Topic {
+                    channel: args[1].clone(),
+                    topic: topic.clone().filter(|topic| !topic.is_empty()),
+                }
+            }
+            Command::ChannelMODE(channel, modes) => EventKind::ModeChange { channel, modes },
+            Command::UserMODE(nick, modes) => EventKind::UserModeChange { nick, modes },
+            Command::INVITE(nick, channel) => EventKind::Invite { nick, channel },
+            command => EventKind::Other(command),
+        };
+        Event {
+            source: message.prefix,
+            tags: message.tags.unwrap_or_default(),
+            from_self: false,
+            kind,
+        }
+    }
+}
+
+type Handler = Box<dyn FnMut(&Event) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send>;
+
+/// Runs handlers on the events from a client, in the order they were registered.
+///
+/// # Example
+/// ```no_run
+/// # use futures::future::{self, FutureExt};
+/// # use irc_async::{Client, Config, Dispatcher, EventKind};
+/// # async fn run(config: Config) -> Result<(), irc_async::ClientError> {
+/// let (mut client, fut, handle) = Client::with_config(config).await?;
+/// let mut dispatcher = Dispatcher::new();
+/// dispatcher.on(move |event| match event.kind {
+///     EventKind::Privmsg { ref target, ref text, is_channel: true }
+///         if text == "!ping" && !event.from_self =>
+///     {
+///         handle.privmsg(target, "pong").boxed()
+///     }
+///     _ => future::ok(()).boxed(),
+/// });
+/// client.register().await?;
+/// future::try_join(fut, dispatcher.run(&mut client)).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: Vec<Handler>,
+    /// Our nick, once the server has welcomed us.
+    nick: Option<String>,
+    /// What the server supports, for comparing nicks with its `CASEMAPPING`.
+    isupport: ISupport,
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher without any handlers.
+    pub fn new() -> Self {
+        Dispatcher::default()
+    }
+
+    /// Registers a handler to run on every event. The future it returns is run to completion
+    /// before the next handler, and an error from it stops the dispatcher.
+    pub fn on<F, Fut>(&mut self, mut handler: F) -> &mut Self
+    where
+        F: FnMut(&Event) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.handlers
+            .push(Box::new(move |event| handler(event).boxed()));
+        self
+    }
+
+    /// Runs every handler on an event.
+    pub async fn dispatch(&mut self, event: &Event) -> Result<()> {
+        for handler in self.handlers.iter_mut() {
+            handler(event).await?;
+        }
+        Ok(())
+    }
+
+    /// Runs the handlers on the events from a stream of messages, like a `Client`, until it
+    /// ends. Finishes with a `Disconnected` event, and returns the error that ended the stream,
+    /// if any. Errors that only affected one message are skipped.
+    ///
+    /// Events are marked `from_self` going by the nick the server welcomed us with, and any nick
+    /// changes after.
+    pub async fn run<S>(&mut self, mut messages: S) -> Result<()>
+    where
+        S: Stream<Item = Result<Message>> + Unpin,
+    {
+        let mut reason = None;
+        while let Some(message) = messages.next().await {
+            let mut event = match message {
+                Ok(message) => Event::from(message),
+                // a single bad line doesn't end the connection
+                Err(ref err) if !err.is_fatal() => continue,
+                Err(err) => {
+                    self.dispatch(&Event::disconnected(Some(err.to_string())))
+                        .await?;
+                    return Err(err);
+                }
+            };
+            if let EventKind::Other(Command::ERROR(ref message)) = event.kind {
+                reason = Some(message.clone());
+            }
+            self.track_self(&mut event);
+            self.dispatch(&event).await?;
+        }
+        self.dispatch(&Event::disconnected(reason)).await
+    }
+
+    /// Marks whether the event came from our nick, and keeps track of what our nick is.
+    fn track_self(&mut self, event: &mut Event) {
+        let mapping = self.isupport.case_mapping();
+        event.from_self = match (event.nick(), &self.nick) {
+            (Some(source), Some(nick)) => mapping.eq(source, nick),
+            _ => false,
+        };
+        match event.kind {
+            EventKind::Registered { ref nick } => self.nick = Some(nick.clone()),
+            EventKind::NickChange { ref nick } if event.from_self => self.nick = Some(nick.clone()),
+            // the first argument is our nick
+            EventKind::Other(Command::Response(Response::RPL_ISUPPORT, ref args, _)) => {
+                self.isupport.update(args.get(1..).unwrap_or(&[]))
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Debug for Dispatcher {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("Dispatcher")
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use futures::future;
+    use futures::stream;
+
+    use super::{Dispatcher, Event, EventKind};
+    use crate::client::ClientError;
+    use crate::proto::{message::Tag, ChannelMode, Message, Mode, Source};
+
+    fn event(line: &str) -> Event {
+        Event::from(line.parse::<Message>().unwrap())
+    }
+
+    #[test]
+    fn messages() {
+        let privmsg = event("@msgid=1 :nick!user@host PRIVMSG #rust :hello");
+        assert_eq!(privmsg.nick(), Some("nick"));
+        assert_eq!(
+            privmsg.tags,
+            vec![Tag("msgid".to_owned(), Some("1".to_owned()))]
+        );
+        assert_eq!(
+            privmsg.kind,
+            EventKind::Privmsg {
+                target: "#rust".to_owned(),
+                is_channel: true,
+                text: "hello".to_owned(),
+            }
+        );
+        assert_eq!(
+            event(":nick!user@host PRIVMSG me :\x01ACTION waves\x01").kind,
+            EventKind::Action {
+                target: "me".to_owned(),
+                is_channel: false,
+                text: "waves".to_owned(),
+            }
+        );
+        assert_eq!(
+            event(":irc.test.net NOTICE * :hi").kind,
+            EventKind::Notice {
+                target: "*".to_owned(),
+                is_channel: false,
+                text: "hi".to_owned(),
+            }
+        );
+        // other CTCP queries are left alone
+        match event(":nick!user@host PRIVMSG me :\x01VERSION\x01").kind {
+            EventKind::Other(_) => (),
+            kind => panic!("unexpected {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn channels() {
+        assert_eq!(
+            event(":nick!user@host JOIN #rust").kind,
+            EventKind::Join {
+                channel: "#rust".to_owned()
+            }
+        );
+        assert_eq!(
+            event(":nick!user@host KICK #rust spammer :bye").kind,
+            EventKind::Kick {
+                channel: "#rust".to_owned(),
+                nick: "spammer".to_owned(),
+                reason: Some("bye".to_owned()),
+            }
+        );
+        assert_eq!(
+            event(":nick!user@host TOPIC #rust :").kind,
+            EventKind::Topic {
+                channel: "#rust".to_owned(),
+                topic: None,
+            }
+        );
+        assert_eq!(
+            event(":irc.test.net 332 me #rust :Rust").kind,
+            EventKind::Topic {
+                channel: "#rust".to_owned(),
+                topic: Some("Rust".to_owned()),
+            }
+        );
+        assert_eq!(
+            event(":nick!user@host MODE #rust +o me").kind,
+            EventKind::ModeChange {
+                channel: "#rust".to_owned(),
+                modes: vec![Mode::plus(ChannelMode::Oper, Some("me"))],
+            }
+        );
+        let welcome = event(":irc.test.net 001 me :Welcome");
+        assert_eq!(
+            welcome.source,
+            Some(Source::Server("irc.test.net".to_owned()))
+        );
+        assert_eq!(
+            welcome.kind,
+            EventKind::Registered {
+                nick: "me".to_owned()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn dispatch() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut dispatcher = Dispatcher::new();
+        let handler_seen = seen.clone();
+        let _ = dispatcher.on(move |event| {
+            handler_seen.lock().unwrap().push(event.kind.clone());
+            future::ok(())
+        });
+
+        let messages = vec![
+            Ok(":nick!user@host NICK other".parse().unwrap()),
+            Ok("ERROR :Closing Link".parse().unwrap()),
+        ];
+        dispatcher.run(stream::iter(messages)).await.unwrap();
+        assert_eq!(
+            seen.lock().unwrap().last(),
+            Some(&EventKind::Disconnected {
+                reason: Some("Closing Link".to_owned())
+            })
+        );
+        assert_eq!(
+            seen.lock().unwrap()[0],
+            EventKind::NickChange {
+                nick: "other".to_owned()
+            }
+        );
+
+        let failed = vec![Err(ClientError::from(io::Error::from(
+            io::ErrorKind::ConnectionReset,
+        )))];
+        assert!(dispatcher.run(stream::iter(failed)).await.is_err());
+        let seen = seen.lock().unwrap();
+        match seen.last() {
+            Some(EventKind::Disconnected { reason: Some(_) }) => (),
+            kind => panic!("unexpected {:?}", kind),
+        }
+    }
+
+    #[tokio::test]
+    async fn from_self() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut dispatcher = Dispatcher::new();
+        let handler_seen = seen.clone();
+        let _ = dispatcher.on(move |event| {
+            if let EventKind::Privmsg { ref text, .. } = event.kind {
+                handler_seen
+                    .lock()
+                    .unwrap()
+                    .push((text.clone(), event.from_self));
+            }
+            future::ok(())
+        });
+
+        let messages = vec![
+            ":irc.test.net 001 me :Welcome",
+            ":irc.test.net 005 me CASEMAPPING=rfc1459 :are supported by this server",
+            ":me!user@host PRIVMSG #rust :a",
+            ":other!user@host PRIVMSG #rust :b",
+            ":me!user@host NICK Me[away]",
+            ":me{away}!user@host PRIVMSG #rust :c",
+            ":me!user@host PRIVMSG #rust :d",
+        ];
+        let messages = messages.into_iter().map(|line| Ok(line.parse().unwrap()));
+        dispatcher.run(stream::iter(messages)).await.unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                ("a".to_owned(), true),
+                ("b".to_owned(), false),
+                ("c".to_owned(), true),
+                ("d".to_owned(), false),
+            ]
+        );
+    }
+}
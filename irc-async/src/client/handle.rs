@@ -0,0 +1,208 @@
+use futures::channel::mpsc::UnboundedSender;
+use futures::future::Future;
+use futures::sink::SinkExt;
+
+use crate::client::Result;
+use crate::proto::{ChannelMode, Command, Message, Mode, UserMode};
+
+/// A cheap, cloneable handle for sending messages to the server, which can be passed to other
+/// tasks while the `Client` is busy reading.
+///
+/// Each method returns a future that resolves once the message is queued for the client future
+/// to write out, and fails if the client has shut down.
+#[derive(Clone, Debug)]
+pub struct ClientHandle {
+    tx: UnboundedSender<Message>,
+}
+
+impl ClientHandle {
+    pub(crate) fn new(tx: UnboundedSender<Message>) -> Self {
+        ClientHandle { tx }
+    }
+
+    /// Send a Message to the server.
+    pub fn send(&self, message: Message) -> impl Future<Output = Result<()>> + Send + 'static {
+        let mut tx = self.tx.clone();
+        async move {
+            tx.send(message).await?;
+            Ok(())
+        }
+    }
+
+    /// Send a command to the server, without tags.
+    pub fn send_command(
+        &self,
+        command: Command,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send(Message::from(command))
+    }
+
+    /// Join a channel, with its key if it has one.
+    pub fn join(
+        &self,
+        channel: &str,
+        key: Option<&str>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::JOIN(
+            channel.to_owned(),
+            key.map(ToOwned::to_owned),
+            None,
+        ))
+    }
+
+    /// Leave a channel, with an optional parting message.
+    pub fn part(
+        &self,
+        channel: &str,
+        message: Option<&str>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::PART(
+            channel.to_owned(),
+            message.map(ToOwned::to_owned),
+        ))
+    }
+
+    /// Send a message to a channel or user.
+    ///
+    /// The text is sent as a single line, so it should be short enough for the server to relay
+    /// whole. `Client::split_message` splits longer text into several messages.
+    pub fn privmsg(
+        &self,
+        target: &str,
+        text: &str,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::PRIVMSG(target.to_owned(), text.to_owned()))
+    }
+
+    /// Send a notice to a channel or user, which shouldn't be answered automatically.
+    pub fn notice(
+        &self,
+        target: &str,
+        text: &str,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::NOTICE(target.to_owned(), text.to_owned()))
+    }
+
+    /// Set the topic of a channel, or ask the server for it if `topic` is `None`.
+    pub fn topic(
+        &self,
+        channel: &str,
+        topic: Option<&str>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::TOPIC(
+            channel.to_owned(),
+            topic.map(ToOwned::to_owned),
+        ))
+    }
+
+    /// Change the modes of a channel, or ask the server for them if `modes` is empty.
+    pub fn mode(
+        &self,
+        channel: &str,
+        modes: Vec<Mode<ChannelMode>>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::ChannelMODE(channel.to_owned(), modes))
+    }
+
+    /// Change the modes of the client's own nick.
+    pub fn user_mode(
+        &self,
+        nick: &str,
+        modes: Vec<Mode<UserMode>>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::UserMODE(nick.to_owned(), modes))
+    }
+
+    /// Ask the server about a user.
+    pub fn whois(&self, nick: &str) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::WHOIS(None, nick.to_owned()))
+    }
+
+    /// Remove a user from a channel, with an optional reason.
+    pub fn kick(
+        &self,
+        channel: &str,
+        nick: &str,
+        reason: Option<&str>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::KICK(
+            channel.to_owned(),
+            nick.to_owned(),
+            reason.map(ToOwned::to_owned),
+        ))
+    }
+
+    /// Invite a user to a channel.
+    pub fn invite(
+        &self,
+        nick: &str,
+        channel: &str,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::INVITE(nick.to_owned(), channel.to_owned()))
+    }
+
+    /// Mark the client as away with a message, or as back if `message` is `None`.
+    pub fn away(&self, message: Option<&str>) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::AWAY(message.map(ToOwned::to_owned)))
+    }
+
+    /// Change the client's nick.
+    pub fn nick(&self, nick: &str) -> impl Future<Output = Result<()>> + Send + 'static {
+        self.send_command(Command::NICK(nick.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::channel::mpsc;
+    use futures::stream::StreamExt;
+
+    use super::ClientHandle;
+    use crate::client::ClientError;
+    use crate::proto::{ChannelMode, Mode};
+
+    #[tokio::test]
+    async fn commands() {
+        let (tx, rx) = mpsc::unbounded();
+        let handle = ClientHandle::new(tx);
+        let other = handle.clone();
+        handle.join("#rust", None).await.unwrap();
+        other.privmsg("#rust", "hello there").await.unwrap();
+        handle.notice("flubber", "hi").await.unwrap();
+        handle.topic("#rust", Some("Rust")).await.unwrap();
+        let op = Mode::plus(ChannelMode::Oper, Some("flubber"));
+        handle.mode("#rust", vec![op]).await.unwrap();
+        handle.kick("#rust", "spammer", Some("bye")).await.unwrap();
+        handle.whois("flubber").await.unwrap();
+        handle.away(Some("lunch")).await.unwrap();
+        handle.part("#rust", None).await.unwrap();
+        drop((handle, other));
+
+        let lines: Vec<_> = rx.map(|message| message.to_string()).collect().await;
+        assert_eq!(
+            lines,
+            vec![
+                "JOIN #rust\r\n",
+                "PRIVMSG #rust :hello there\r\n",
+                "NOTICE flubber :hi\r\n",
+                "TOPIC #rust :Rust\r\n",
+                "MODE #rust +o flubber\r\n",
+                "KICK #rust spammer :bye\r\n",
+                "WHOIS flubber\r\n",
+                "AWAY :lunch\r\n",
+                "PART #rust\r\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn closed() {
+        let (tx, rx) = mpsc::unbounded();
+        let handle = ClientHandle::new(tx);
+        drop(rx);
+        match handle.join("#rust", None).await {
+            Err(ClientError::Send(_)) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
@@ -1,24 +1,64 @@
+mod caps;
 mod config;
+mod connect;
+mod event;
+mod handle;
+mod proxy;
+mod requests;
 mod stream;
+mod tls;
+mod websocket;
 
 use std::io;
-use std::net::ToSocketAddrs;
+use std::iter;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+use chrono::Local;
 use futures::channel::mpsc::{self, UnboundedSender};
-use futures::future::{self, Either, Future, FutureExt, TryFutureExt};
-use futures::sink::SinkExt;
-use futures::stream::{Stream, StreamExt};
-use native_tls::TlsConnector;
-use tokio::net::TcpStream;
-use tokio_tls::TlsConnector as TokioTlsConnector;
+use futures::channel::oneshot::{self, Canceled};
+use futures::future::{self, Either, Future, FutureExt};
+use futures::sink::{Sink, SinkExt};
+use futures::stream::{self as stream_ext, Stream, StreamExt};
+use tokio::time;
 use tokio_util::codec::{Decoder, LinesCodecError};
 
+use crate::client::caps::Caps;
+use crate::client::requests::{Requests, BATCH_TAG, LABEL_TAG};
 use crate::client::stream::ClientStream;
-use crate::proto::{Command, IrcCodec, IrcError, Message};
+use crate::proto::irc::check_len;
+use crate::proto::{
+    message::Tag, split::split_text, BatchSubCommand, Capability, ChannelExt, ChannelMode, Command,
+    Ctcp, ISupport, IrcCodec, IrcError, Message, Mode, MultilineLimits, Response, Source,
+};
+
+/// The batch type for sending several lines as one message with `draft/multiline`.
+const MULTILINE_BATCH: &str = "draft/multiline";
+
+/// The tag marking a line of a `draft/multiline` batch as the continuation of the line before.
+const MULTILINE_CONCAT_TAG: &str = "draft/multiline-concat";
+
+/// The longest host a server may give us, assumed until we learn our own.
+const MAX_HOST_LEN: usize = 63;
+
+/// The CTCP messages the client understands, for answering `CLIENTINFO`.
+const CTCP_SUPPORTED: &str = "ACTION CLIENTINFO PING TIME VERSION";
+
+/// The least time between automatic CTCP replies, so that a flood of queries can't make the server
+/// disconnect us for flooding in turn.
+const CTCP_REPLY_INTERVAL: Duration = Duration::from_secs(2);
 
 pub use self::config::Config;
+pub use self::event::{Dispatcher, Event, EventKind};
+pub use self::handle::ClientHandle;
+pub use self::proxy::{Proxy, ProxyError, ProxyKind};
+
+pub(crate) use self::connect::connect_proxied;
+pub use self::tls::{TlsConfig, TlsError};
+pub use self::websocket::WebSocketError;
 
 /// An error that could arise from running the client
 #[derive(Debug, Error)]
@@ -29,7 +69,15 @@ pub enum ClientError {
 
     /// Tls error
     #[error("tls error: {0}")]
-    Tls(#[from] native_tls::Error),
+    Tls(#[from] TlsError),
+
+    /// The proxy couldn't connect to the server
+    #[error("proxy error: {0}")]
+    Proxy(#[from] ProxyError),
+
+    /// The WebSocket handshake failed
+    #[error("websocket error: {0}")]
+    WebSocket(#[from] WebSocketError),
 
     /// Protocol error
     #[error("protocol error: {0}")]
@@ -42,81 +90,150 @@ pub enum ClientError {
     /// Line codec error
     #[error("line codec error: {0}")]
     LinesCodec(#[from] LinesCodecError),
+
+    /// The connection closed before a request got its reply
+    #[error("request canceled: {0}")]
+    Canceled(#[from] Canceled),
+
+    /// The server didn't answer a keepalive `PING` in time, so the connection is probably dead
+    #[error("ping timeout: no reply in {0:?}")]
+    PingTimeout(Duration),
+
+    /// The server didn't close the connection in time after a `QUIT`
+    #[error("quit timeout: the connection was still open after {0:?}")]
+    QuitTimeout(Duration),
+}
+
+impl ClientError {
+    /// Returns true if the error ends the connection, and false if it only affected one message,
+    /// like a line from the server that couldn't be parsed or a message too long to send. The
+    /// client's stream keeps going after errors that aren't fatal.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            ClientError::Proto(IrcError::InvalidMessage { .. })
+                | ClientError::Proto(IrcError::LineTooLong { .. })
+        )
+    }
 }
 
 type Result<T> = std::result::Result<T, ClientError>;
 
+/// State shared between the client and its incoming message stream.
+#[derive(Default)]
+struct Shared {
+    /// The current nick of the client.
+    nick: String,
+    /// The prefix the server relays our messages with, once we've seen it.
+    source: Option<Source>,
+    isupport: ISupport,
+    /// The maximum line length the codec allows, from `LINELEN`.
+    line_len: Arc<AtomicUsize>,
+    caps: Caps,
+    requests: Requests,
+    next_batch: u64,
+    /// Whether to answer CTCP queries automatically.
+    ctcp_replies: bool,
+    /// When the last automatic CTCP reply was sent.
+    last_ctcp_reply: Option<Instant>,
+    /// The token of the keepalive `PING` waiting for its `PONG`, and when it was sent.
+    keepalive: Option<(String, Instant)>,
+    next_keepalive: u64,
+    /// How long the server took to answer the last keepalive `PING`.
+    lag: Option<Duration>,
+}
+
 /// An async IRC client
 pub struct Client {
     config: Config,
     stream: Pin<Box<dyn Stream<Item = Result<Message>> + Send>>,
     tx: UnboundedSender<Message>,
+    shared: Arc<Mutex<Shared>>,
 }
 
 pub type ClientFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
 
 impl Client {
     /// Create a new client with the specified config
-    pub async fn with_config(
-        config: Config,
-    ) -> Result<(Self, ClientFuture, UnboundedSender<Message>)> {
-        let mut addrs = (config.host.as_ref(), config.port).to_socket_addrs()?;
-        let stream = TcpStream::connect(addrs.next().unwrap()).await?;
-
-        let stream = if config.ssl {
-            let connector: TokioTlsConnector = TlsConnector::new().unwrap().into();
-            let stream = connector.connect(&config.host, stream).await?;
-            ClientStream::Tls(stream)
-        } else {
-            ClientStream::Plain(stream)
-        };
+    ///
+    /// Along with the client, returns the future that writes messages to the server, which has to
+    /// be run for anything to be sent, and a handle for sending messages from other tasks.
+    pub async fn with_config(config: Config) -> Result<(Self, ClientFuture, ClientHandle)> {
+        let stream = connect::connect(&config).await?;
+        Ok(Client::with_stream(config, stream))
+    }
 
-        let stream = IrcCodec::default().framed(stream);
+    /// Create a new client talking to the server over a stream that is already connected.
+    fn with_stream(config: Config, stream: ClientStream) -> (Self, ClientFuture, ClientHandle) {
+        let codec = IrcCodec::new(config.fallback_encoding);
+        let line_len = codec.line_len();
+        let stream = codec.framed(stream);
         let (sink, stream) = stream.split();
         let (tx, filter_rx) = mpsc::unbounded();
+        let (error_tx, error_rx) = mpsc::unbounded();
         let filter_tx = tx.clone();
+        let shared = Arc::new(Mutex::new(Shared {
+            nick: config.nick.clone(),
+            line_len,
+            ctcp_replies: config.ctcp_replies,
+            caps: Caps::new(config.echo_message),
+            ..Shared::default()
+        }));
+        let filter_shared = shared.clone();
+        let end_shared = shared.clone();
 
-        let stream = stream.filter_map(move |message| {
-            if let Ok(Message {
-                command: Command::PING(code, _),
-                ..
-            }) = message
-            {
-                let mut filter_tx = filter_tx.clone();
-                Either::Left(async move {
-                    match filter_tx
-                        .send(Message {
-                            tags: None,
-                            prefix: None,
-                            command: Command::PONG(code, None),
-                        })
-                        .await
-                    {
-                        Ok(_) => None,
-                        Err(err) => Some(Err(ClientError::from(err))),
-                    }
+        let stream = stream
+            .filter_map(move |message| {
+                future::ready(match message {
+                    Ok(message) => handle_incoming(&filter_shared, &filter_tx, message),
+                    Err(err) => Some(Err(ClientError::from(err))),
                 })
-            } else {
-                Either::Right(future::ready(Some(message.map_err(ClientError::from))))
-            }
-        });
+            })
+            .map(Some)
+            .chain(stream_ext::once(future::lazy(move |_| {
+                // nothing will answer requests anymore
+                end_shared.lock().unwrap().requests.cancel();
+                None
+            })));
+        // messages that couldn't be sent are reported alongside incoming ones, until the
+        // connection closes
+        let stream = stream_ext::select(stream, error_rx.map(|err| Some(Err(err))))
+            .take_while(|message| future::ready(message.is_some()))
+            .filter_map(future::ready);
 
-        let fut = filter_rx
-            .map(Ok)
-            .forward(sink)
-            .map_err(ClientError::from)
-            .boxed();
+        let fut = write(filter_rx, sink, error_tx).boxed();
+        let fut = match config.ping_interval {
+            Some(interval) => {
+                let keepalive =
+                    keepalive(shared.clone(), tx.clone(), interval, config.ping_timeout);
+                future::select(fut, keepalive.boxed())
+                    .map(|either| match either {
+                        Either::Left((result, _)) | Either::Right((result, _)) => result,
+                    })
+                    .boxed()
+            }
+            None => fut,
+        };
 
         let client = Client {
             config,
             stream: stream.boxed(),
-            tx: tx.clone(),
+            tx,
+            shared,
         };
-        Ok((client, fut, tx))
+        let handle = client.handle();
+        (client, fut, handle)
     }
 
-    /// Send the client registration information to the server
+    /// Send the client registration information to the server, negotiating capabilities first
     pub async fn register(&mut self) -> Result<()> {
+        let cap = self.shared.lock().unwrap().caps.start();
+        self.send(Message {
+            tags: None,
+            prefix: None,
+            command: cap,
+        })
+        .await?;
         self.send(Message {
             tags: None,
             prefix: None,
@@ -141,6 +258,482 @@ impl Client {
         self.tx.flush().await?;
         Ok(())
     }
+
+    /// Leave the server with a `QUIT`, and wait until it closes the connection, for at most the
+    /// ping timeout in the config.
+    ///
+    /// Anything already sent is still written out first, but nothing can be sent after the
+    /// `QUIT`, so the client future resolves once it's written. That future has to keep running
+    /// until this resolves. Messages that arrive in the meantime are handled as usual, but not
+    /// passed on.
+    pub async fn quit(&mut self, message: Option<String>) -> Result<()> {
+        self.send(Message {
+            tags: None,
+            prefix: None,
+            command: Command::QUIT(message),
+        })
+        .await?;
+        // ends the stream the client future writes out, once the QUIT has been written
+        self.tx.close_channel();
+        let stream = &mut self.stream;
+        let closed = async move {
+            while let Some(message) = stream.next().await {
+                match message {
+                    Err(err) if err.is_fatal() => return Err(err),
+                    _ => (),
+                }
+            }
+            Ok(())
+        };
+        let timeout = self.config.ping_timeout;
+        time::timeout(timeout, closed)
+            .await
+            .unwrap_or(Err(ClientError::QuitTimeout(timeout)))
+    }
+
+    /// Gets a handle for sending messages to the server, which can be cloned and passed to other
+    /// tasks.
+    pub fn handle(&self) -> ClientHandle {
+        ClientHandle::new(self.tx.clone())
+    }
+
+    /// Returns true if the server acknowledged the capability.
+    pub fn has_capability(&self, cap: &Capability) -> bool {
+        self.shared.lock().unwrap().caps.is_enabled(cap)
+    }
+
+    /// Gets the current nick of the client.
+    pub fn nick(&self) -> String {
+        self.shared.lock().unwrap().nick.clone()
+    }
+
+    /// Gets the config the client connected with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Gets how long the server took to answer the last keepalive `PING`, if it has answered one.
+    pub fn lag(&self) -> Option<Duration> {
+        self.shared.lock().unwrap().lag
+    }
+
+    /// Gets the features the server advertised in `RPL_ISUPPORT`.
+    pub fn isupport(&self) -> ISupport {
+        self.shared.lock().unwrap().isupport.clone()
+    }
+
+    /// Send a Message to the server, and collect the server's replies to it.
+    ///
+    /// With the `labeled-response` capability, the message is tagged with a label, and the replies
+    /// are exactly the messages the server labels in response. Otherwise, the replies are the
+    /// numerics, standard replies and echoes of the client's own commands which arrive before the
+    /// server answers a `PING` sent right after the message. The replies are still passed on to
+    /// the client stream.
+    pub fn request(
+        &self,
+        message: Message,
+    ) -> impl Future<Output = Result<Vec<Message>>> + Send + 'static {
+        self.request_all(vec![message])
+    }
+
+    /// Send several Messages to the server, such as the ones from `split_message`, and collect
+    /// the server's replies to all of them.
+    ///
+    /// Only a single message or a single batch can be labeled, so the replies to anything else are
+    /// collected until the server answers a `PING`, like without `labeled-response`.
+    ///
+    /// Fails with `IrcError::LineTooLong`, without sending anything, if any of the messages is too
+    /// long for the server.
+    pub fn request_all(
+        &self,
+        mut messages: Vec<Message>,
+    ) -> impl Future<Output = Result<Vec<Message>>> + Send + 'static {
+        let (tx, rx) = oneshot::channel();
+        let sent = {
+            // keep the lock while sending so requests are registered in the order they're sent
+            let mut shared = self.shared.lock().unwrap();
+            // the codec would drop a line that's too long, leaving the request unanswered
+            let max = shared.line_len.load(Ordering::Relaxed);
+            let too_long = messages
+                .iter()
+                .map(|message| check_len(message, max))
+                .find_map(|result| result.err());
+            if let Some(err) = too_long {
+                Err(ClientError::from(err))
+            } else {
+                // messages in a batch, and the end of it, belong to the message that started it
+                let units = messages
+                    .iter()
+                    .filter(|message| match message.command {
+                        Command::BATCH(ref reference, ..) if reference.starts_with('-') => false,
+                        _ => !has_tag(message, BATCH_TAG),
+                    })
+                    .count();
+                if units == 1 && shared.caps.is_enabled(&Capability::LabeledResponse) {
+                    let label = shared.requests.labeled(tx);
+                    messages[0]
+                        .tags
+                        .get_or_insert_with(Vec::new)
+                        .push(Tag(LABEL_TAG.to_owned(), Some(label)));
+                } else {
+                    let token = shared.requests.fallback(tx);
+                    messages.push(Message {
+                        tags: None,
+                        prefix: None,
+                        command: Command::PING(token, None),
+                    });
+                }
+                let mut sent = Ok(());
+                for message in messages {
+                    if let Err(err) = self.tx.unbounded_send(message) {
+                        sent = Err(err.into_send_error());
+                        break;
+                    }
+                }
+                sent.map_err(ClientError::from)
+            }
+        };
+        async move {
+            sent?;
+            Ok(rx.await?)
+        }
+    }
+
+    /// Splits text into as many messages as it takes to send it to the target, with `command`
+    /// being `Command::PRIVMSG` or `Command::NOTICE`.
+    ///
+    /// Lines are split on line breaks, and again after spaces or between characters where they
+    /// would go over the server's line length along with the prefix the server relays them with.
+    /// Blank lines are left out. With the `draft/multiline` capability, the lines are sent in
+    /// batches the server delivers as one message, with the tags on the start of each batch;
+    /// otherwise each line gets the tags.
+    pub fn split_message<F>(
+        &self,
+        command: F,
+        target: &str,
+        text: &str,
+        tags: Option<Vec<Tag>>,
+    ) -> Vec<Message>
+    where
+        F: Fn(String, String) -> Command,
+    {
+        let mut shared = self.shared.lock().unwrap();
+        let prefix_len = match shared.source {
+            Some(ref source) => source.to_string().len(),
+            // nick!~user@host, where the user is our nick
+            None => shared.nick.len() + 2 + self.config.nick.len() + 1 + MAX_HOST_LEN,
+        };
+        let message = |tags, text: &str| Message {
+            tags,
+            prefix: None,
+            command: command(target.to_owned(), text.to_owned()),
+        };
+        // `:prefix ` and the message with no text, including the `\r\n`
+        let overhead = prefix_len + 2 + message(None, "").to_string().len();
+        let mut max_len = shared.isupport.line_len().saturating_sub(overhead);
+
+        let multiline = match shared.caps.value(&Capability::Multiline) {
+            Some(value)
+                if shared.caps.is_enabled(&Capability::Multiline)
+                    && shared.caps.is_enabled(&Capability::Batch) =>
+            {
+                MultilineLimits::parse(value)
+            }
+            _ => None,
+        };
+        let limits = match multiline {
+            Some(limits) => limits,
+            None => {
+                return split_text(text, max_len)
+                    .into_iter()
+                    .filter(|line| !line.text.is_empty())
+                    .map(|line| message(tags.clone(), line.text))
+                    .collect()
+            }
+        };
+        max_len = max_len.min(limits.max_bytes);
+
+        let lines = split_text(text, max_len);
+        let mut messages = Vec::new();
+        for batch in limits.batches(&lines) {
+            if batch.iter().all(|line| line.text.is_empty()) {
+                continue;
+            }
+            // a batch of one line is just the line
+            if let [line] = batch {
+                messages.push(message(tags.clone(), line.text));
+                continue;
+            }
+            shared.next_batch += 1;
+            let reference = format!("multiline-{}", shared.next_batch);
+            messages.push(Message {
+                tags: tags.clone(),
+                prefix: None,
+                command: Command::BATCH(
+                    format!("+{}", reference),
+                    Some(BatchSubCommand::CUSTOM(MULTILINE_BATCH.to_owned())),
+                    Some(vec![target.to_owned()]),
+                ),
+            });
+            for (i, line) in batch.iter().enumerate() {
+                let mut line_tags = vec![Tag(BATCH_TAG.to_owned(), Some(reference.clone()))];
+                if line.concat && i > 0 {
+                    line_tags.push(Tag(MULTILINE_CONCAT_TAG.to_owned(), None));
+                }
+                messages.push(message(Some(line_tags), line.text));
+            }
+            messages.push(Message {
+                tags: None,
+                prefix: None,
+                command: Command::BATCH(format!("-{}", reference), None, None),
+            });
+        }
+        messages
+    }
+}
+
+/// Builds the automatic reply to a CTCP query, if it gets one.
+fn ctcp_reply(query: &Ctcp) -> Option<Ctcp> {
+    match *query {
+        Ctcp::Version(None) => Some(Ctcp::Version(Some(format!(
+            "irc-async {}",
+            env!("CARGO_PKG_VERSION")
+        )))),
+        Ctcp::Ping(ref params) => Some(Ctcp::Ping(params.clone())),
+        Ctcp::Time(None) => Some(Ctcp::Time(Some(Local::now().to_rfc2822()))),
+        Ctcp::ClientInfo(None) => Some(Ctcp::ClientInfo(Some(CTCP_SUPPORTED.to_owned()))),
+        _ => None,
+    }
+}
+
+/// Puts parsed channel modes back together as they came, with the mode letters first and their
+/// arguments after.
+fn mode_string(modes: &[Mode<ChannelMode>]) -> String {
+    let mut letters = String::new();
+    let mut args = Vec::new();
+    let mut last = None;
+    for mode in modes {
+        let (plus, mode, arg) = match mode {
+            Mode::Plus(mode, arg) => (true, mode, arg),
+            Mode::Minus(mode, arg) => (false, mode, arg),
+        };
+        if last != Some(plus) {
+            letters.push(if plus { '+' } else { '-' });
+            last = Some(plus);
+        }
+        letters.push(mode.to_char());
+        args.extend(arg.as_deref());
+    }
+    iter::once(letters.as_str())
+        .chain(args)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns true if the message has the tag.
+fn has_tag(message: &Message, key: &str) -> bool {
+    message.tags.iter().flatten().any(|Tag(tag, _)| tag == key)
+}
+
+/// Handles the messages the client answers by itself, returning the message to pass on.
+fn handle_incoming(
+    shared: &Mutex<Shared>,
+    tx: &UnboundedSender<Message>,
+    mut message: Message,
+) -> Option<Result<Message>> {
+    let mut shared = shared.lock().unwrap();
+    let from_self = match message.source_nickname() {
+        Some(source) => shared.isupport.case_mapping().eq(source, &shared.nick),
+        None => false,
+    };
+    if from_self {
+        if let Some(
+            source @ Source::User {
+                user: Some(_),
+                host: Some(_),
+                ..
+            },
+        ) = &message.prefix
+        {
+            shared.source = Some(source.clone());
+        }
+    }
+    let reply = match message.command {
+        // servers usually welcome us by our full prefix
+        Command::Response(Response::RPL_WELCOME, _, Some(ref text)) => {
+            let source = text.rsplit(' ').next().map(Source::from);
+            if let Some(source @ Source::User { .. }) = source {
+                if source.host().is_some() {
+                    shared.source = Some(source);
+                }
+            }
+            None
+        }
+        Command::Response(Response::RPL_ISUPPORT, ref args, _) => {
+            // the first argument is our nick
+            shared.isupport.update(args.get(1..).unwrap_or(&[]));
+            shared
+                .line_len
+                .store(shared.isupport.line_len(), Ordering::Relaxed);
+            None
+        }
+        // modes were parsed with the default `CHANMODES` when the line was decoded, which gets
+        // letters the server uses for something else wrong, like `q` for quiets
+        Command::ChannelMODE(ref channel, ref modes) => {
+            if let Ok(modes) =
+                Mode::from_channel_mode_string_with(&mode_string(modes), &shared.isupport)
+            {
+                message.command = Command::ChannelMODE(channel.clone(), modes);
+            }
+            None
+        }
+        // modes that couldn't be parsed without knowing the server's `CHANMODES`
+        Command::Raw(ref cmd, ref args, ref suffix)
+            if cmd.eq_ignore_ascii_case("MODE")
+                && !args.is_empty()
+                && args[0].is_channel_name() =>
+        {
+            let modes = args[1..]
+                .iter()
+                .chain(suffix)
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            if let Ok(modes) =
+                Mode::from_channel_mode_string_with(&modes.join(" "), &shared.isupport)
+            {
+                message.command = Command::ChannelMODE(args[0].clone(), modes);
+            }
+            None
+        }
+        Command::PING(ref code, _) => Some(Command::PONG(code.clone(), None)),
+        Command::PONG(ref server, ref token) => {
+            let token = token.as_ref().unwrap_or(server);
+            match shared.keepalive.take() {
+                Some((ref pending, sent)) if pending == token => {
+                    shared.lag = Some(sent.elapsed());
+                    return None;
+                }
+                keepalive => shared.keepalive = keepalive,
+            }
+            None
+        }
+        // only queries sent to us, not to a channel, are answered
+        Command::PRIVMSG(ref target, ref text)
+            if shared.ctcp_replies
+                && !from_self
+                && shared.isupport.case_mapping().eq(target, &shared.nick) =>
+        {
+            let limited = matches!(
+                shared.last_ctcp_reply,
+                Some(last) if last.elapsed() < CTCP_REPLY_INTERVAL
+            );
+            let reply = match (message.source_nickname(), text.parse()) {
+                (Some(nick), Ok(query)) if !limited => {
+                    ctcp_reply(&query).map(|reply| reply.reply(nick))
+                }
+                _ => None,
+            };
+            if reply.is_some() {
+                shared.last_ctcp_reply = Some(Instant::now());
+            }
+            reply
+        }
+        Command::CAP(_, sub, ref more, ref caps) => {
+            shared.caps.handle(sub, more.as_deref(), caps.as_deref())
+        }
+        Command::NICK(ref nick) if from_self => {
+            shared.nick = nick.clone();
+            if let Some(Source::User {
+                nick: ref mut source_nick,
+                ..
+            }) = shared.source
+            {
+                *source_nick = nick.clone();
+            }
+            None
+        }
+        _ => None,
+    };
+    if let Some(command) = reply {
+        let reply = Message {
+            tags: None,
+            prefix: None,
+            command,
+        };
+        if let Err(err) = tx.unbounded_send(reply) {
+            return Some(Err(err.into_send_error().into()));
+        }
+    }
+
+    let Shared {
+        ref nick,
+        ref isupport,
+        ref mut requests,
+        ..
+    } = *shared;
+    match message.command {
+        Command::PING(..) => None,
+        _ if requests.handle(&message, nick, isupport.case_mapping()) => None,
+        _ => Some(Ok(message)),
+    }
+}
+
+/// Writes out messages until the client is done sending. A message that can't be encoded, like one
+/// too long for the server, is skipped and reported to `errors` rather than ending the
+/// connection.
+async fn write<S>(
+    mut messages: mpsc::UnboundedReceiver<Message>,
+    mut sink: S,
+    errors: UnboundedSender<ClientError>,
+) -> Result<()>
+where
+    S: Sink<Message, Error = IrcError> + Unpin,
+{
+    while let Some(message) = messages.next().await {
+        match sink.send(message).await {
+            Err(err @ IrcError::LineTooLong { .. }) => {
+                let _ = errors.unbounded_send(err.into());
+            }
+            result => result?,
+        }
+    }
+    sink.close().await?;
+    Ok(())
+}
+
+/// Sends a `PING` every `interval`, and fails if the server doesn't answer one within `timeout`.
+async fn keepalive(
+    shared: Arc<Mutex<Shared>>,
+    tx: UnboundedSender<Message>,
+    interval: Duration,
+    timeout: Duration,
+) -> Result<()> {
+    loop {
+        time::delay_for(interval).await;
+        let token = {
+            let mut shared = shared.lock().unwrap();
+            shared.next_keepalive += 1;
+            let token = format!("keepalive{}", shared.next_keepalive);
+            shared.keepalive = Some((token.clone(), Instant::now()));
+            token
+        };
+        let ping = Message {
+            tags: None,
+            prefix: None,
+            command: Command::PING(token.clone(), None),
+        };
+        if let Err(err) = tx.unbounded_send(ping) {
+            return Err(err.into_send_error().into());
+        }
+
+        time::delay_for(timeout).await;
+        match shared.lock().unwrap().keepalive {
+            Some((ref pending, _)) if *pending == token => {
+                return Err(ClientError::PingTimeout(timeout))
+            }
+            _ => (),
+        }
+    }
 }
 
 impl Stream for Client {
@@ -150,3 +743,308 @@ impl Stream for Client {
         Stream::poll_next(Pin::new(&mut self.get_mut().stream), context)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{self, Ipv4Addr};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::channel::mpsc;
+    use futures::future::{self, Either};
+    use futures::stream::StreamExt;
+    use tokio::net::TcpStream;
+
+    use super::{handle_incoming, keepalive, Client, ClientError, ClientStream, Config, Shared};
+    use crate::proto::{
+        CapSubCommand, Capability, ChannelMode, ChannelState, Command, IrcError, Message, Mode,
+    };
+
+    fn config() -> Config {
+        Config {
+            host: "irc.test.net".to_owned(),
+            happy_eyeballs: false,
+            nick: "flubber".to_owned(),
+            ping_interval: None,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn quit() {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut lines = Vec::new();
+            for line in BufReader::new(socket.try_clone().unwrap()).lines() {
+                let line = line.unwrap();
+                lines.push(line.clone());
+                if line.starts_with("QUIT") {
+                    break;
+                }
+            }
+            socket
+                .write_all(b"ERROR :Closing Link: flubber (Quit: bye)\r\n")
+                .unwrap();
+            lines
+        });
+        stream.set_nonblocking(true).unwrap();
+        let stream = ClientStream::Plain(TcpStream::from_std(stream).unwrap());
+
+        let (mut client, fut, _) = Client::with_stream(config(), stream);
+        let fut = tokio::spawn(fut);
+        let sent = Message {
+            tags: None,
+            prefix: None,
+            command: Command::PRIVMSG("#test".to_owned(), "hello".to_owned()),
+        };
+        client.tx.unbounded_send(sent).unwrap();
+        client.quit(Some("bye".to_owned())).await.unwrap();
+        fut.await.unwrap().unwrap();
+        assert!(client
+            .send(Message::from(Command::PING("x".to_owned(), None)))
+            .await
+            .is_err());
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["PRIVMSG #test :hello", "QUIT :bye"]
+        );
+    }
+
+    #[tokio::test]
+    async fn quit_timeout() {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let server = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            for line in BufReader::new(socket.try_clone().unwrap()).lines() {
+                if line.unwrap().starts_with("QUIT") {
+                    break;
+                }
+            }
+            // keep the connection open until the client gives up
+            let _ = done_rx.recv();
+        });
+        stream.set_nonblocking(true).unwrap();
+        let stream = ClientStream::Plain(TcpStream::from_std(stream).unwrap());
+
+        let timeout = Duration::from_millis(50);
+        let config = Config {
+            ping_timeout: timeout,
+            ..config()
+        };
+        let (mut client, fut, _) = Client::with_stream(config, stream);
+        let fut = tokio::spawn(fut);
+        match client.quit(None).await {
+            Err(ClientError::QuitTimeout(after)) => assert_eq!(after, timeout),
+            result => panic!("unexpected {:?}", result),
+        }
+        fut.await.unwrap().unwrap();
+        done_tx.send(()).unwrap();
+        server.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn bad_lines() {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            socket
+                .write_all(b":a :b\r\n:a!b@c PRIVMSG flubber :hi\r\n")
+                .unwrap();
+            let mut lines = Vec::new();
+            for line in BufReader::new(socket.try_clone().unwrap()).lines() {
+                let line = line.unwrap();
+                lines.push(line.clone());
+                if line.starts_with("QUIT") {
+                    break;
+                }
+            }
+            lines
+        });
+        stream.set_nonblocking(true).unwrap();
+        let stream = ClientStream::Plain(TcpStream::from_std(stream).unwrap());
+
+        let (mut client, fut, handle) = Client::with_stream(config(), stream);
+        let fut = tokio::spawn(fut);
+        handle.privmsg("#test", &"x".repeat(600)).await.unwrap();
+        handle.privmsg("#test", "short").await.unwrap();
+
+        // neither the unparseable line nor the one too long to send ends the connection
+        let (mut invalid, mut too_long, mut received) = (false, false, false);
+        while !(invalid && too_long && received) {
+            match client.next().await.unwrap() {
+                Err(ClientError::Proto(IrcError::InvalidMessage { .. })) => invalid = true,
+                Err(ClientError::Proto(IrcError::LineTooLong { .. })) => too_long = true,
+                Ok(message) => {
+                    let hi = Command::PRIVMSG("flubber".to_owned(), "hi".to_owned());
+                    assert_eq!(message.command, hi);
+                    received = true;
+                }
+                Err(err) => panic!("unexpected {:?}", err),
+            }
+        }
+        client.quit(None).await.unwrap();
+        fut.await.unwrap().unwrap();
+        assert_eq!(server.join().unwrap(), vec!["PRIVMSG #test :short", "QUIT"]);
+    }
+
+    #[tokio::test]
+    async fn request_too_long() {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let mut lines = Vec::new();
+            for line in BufReader::new(socket).lines() {
+                let line = line.unwrap();
+                lines.push(line.clone());
+                if line.starts_with("QUIT") {
+                    break;
+                }
+            }
+            lines
+        });
+        stream.set_nonblocking(true).unwrap();
+        let stream = ClientStream::Plain(TcpStream::from_std(stream).unwrap());
+
+        let (mut client, fut, _) = Client::with_stream(config(), stream);
+        let fut = tokio::spawn(fut);
+        let _ = client.shared.lock().unwrap().caps.handle(
+            CapSubCommand::ACK,
+            None,
+            Some("labeled-response"),
+        );
+        assert!(client.has_capability(&Capability::LabeledResponse));
+
+        // the request fails straight away, rather than waiting for replies that never come
+        let long = Message::from(Command::PRIVMSG("#test".to_owned(), "x".repeat(600)));
+        let request = tokio::time::timeout(Duration::from_secs(5), client.request(long));
+        match request.await.unwrap() {
+            Err(ClientError::Proto(IrcError::LineTooLong { .. })) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+        client.quit(None).await.unwrap();
+        fut.await.unwrap().unwrap();
+        assert_eq!(server.join().unwrap(), vec!["QUIT"]);
+    }
+
+    #[test]
+    fn ctcp_replies() {
+        let shared = Mutex::new(Shared {
+            nick: "flubber".to_owned(),
+            ctcp_replies: true,
+            ..Shared::default()
+        });
+        let (tx, mut rx) = mpsc::unbounded();
+        let query = |target: &str| -> Message {
+            format!(":a!b@c PRIVMSG {} :\u{1}VERSION\u{1}", target)
+                .parse()
+                .unwrap()
+        };
+
+        // queries to a channel aren't answered
+        assert!(handle_incoming(&shared, &tx, query("#test")).is_some());
+        assert!(rx.try_next().is_err());
+
+        assert!(handle_incoming(&shared, &tx, query("Flubber")).is_some());
+        match rx.try_next().unwrap().unwrap().command {
+            Command::NOTICE(ref target, ref text) => {
+                assert_eq!(target, "a");
+                assert!(text.starts_with("\u{1}VERSION irc-async"));
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+
+        // nor are queries straight after another
+        assert!(handle_incoming(&shared, &tx, query("flubber")).is_some());
+        assert!(rx.try_next().is_err());
+    }
+
+    #[test]
+    fn channel_modes() {
+        let shared = Mutex::new(Shared {
+            nick: "flubber".to_owned(),
+            ..Shared::default()
+        });
+        let (tx, _rx) = mpsc::unbounded();
+        let incoming = |line: &str| {
+            let message = line.parse().unwrap();
+            handle_incoming(&shared, &tx, message).unwrap().unwrap()
+        };
+        // what Solanum servers, like Libera's, advertise
+        let _ = incoming(
+            ":irc.test.net 005 flubber CHANMODES=eIbq,k,flj,CFLMPQScgimnprstuz PREFIX=(ov)@+ \
+             :are supported by this server",
+        );
+
+        let isupport = shared.lock().unwrap().isupport.clone();
+        let mut channel = ChannelState::default();
+        channel.add_member("nick", &isupport);
+        match incoming(":op!b@c MODE #test +qo *!*@spam nick").command {
+            Command::ChannelMODE(ref target, ref modes) => {
+                assert_eq!(target, "#test");
+                assert_eq!(
+                    modes,
+                    &[
+                        Mode::plus(ChannelMode::Unknown('q'), Some("*!*@spam")),
+                        Mode::plus(ChannelMode::Oper, Some("nick")),
+                    ]
+                );
+                channel.apply(modes, &isupport);
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+        assert_eq!(
+            channel.list(&ChannelMode::Unknown('q')),
+            &["*!*@spam".to_owned()]
+        );
+        assert_eq!(channel.prefix("nick", &isupport), Some('@'));
+    }
+
+    #[tokio::test]
+    async fn ping_timeout() {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (tx, mut rx) = mpsc::unbounded();
+        let interval = Duration::from_millis(10);
+        let timeout = Duration::from_millis(20);
+        match keepalive(shared, tx, interval, timeout).await {
+            Err(ClientError::PingTimeout(after)) => assert_eq!(after, timeout),
+            result => panic!("unexpected {:?}", result),
+        }
+        let ping = rx.next().await.unwrap();
+        assert_eq!(ping.command, Command::PING("keepalive1".to_owned(), None));
+    }
+
+    #[tokio::test]
+    async fn lag() {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (tx, mut rx) = mpsc::unbounded();
+        let interval = Duration::from_millis(10);
+        let keepalive = keepalive(shared.clone(), tx.clone(), interval, Duration::from_secs(5));
+
+        let server = async {
+            let ping = rx.next().await.unwrap();
+            tokio::time::delay_for(Duration::from_millis(5)).await;
+            let pong: Message = match ping.command {
+                Command::PING(token, None) => format!(":irc.test.net PONG irc.test.net :{}", token)
+                    .parse()
+                    .unwrap(),
+                command => panic!("unexpected {:?}", command),
+            };
+            // the PONG isn't passed on
+            assert!(handle_incoming(&shared, &tx, pong).is_none());
+        };
+        match future::select(Box::pin(keepalive), Box::pin(server)).await {
+            Either::Right(_) => (),
+            Either::Left((result, _)) => panic!("unexpected {:?}", result),
+        }
+        assert!(shared.lock().unwrap().lag.unwrap() >= Duration::from_millis(5));
+        assert!(shared.lock().unwrap().keepalive.is_none());
+    }
+}
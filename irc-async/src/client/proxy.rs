@@ -0,0 +1,417 @@
+use std::io;
+use std::net::IpAddr;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The SOCKS protocol version we speak.
+const SOCKS_VERSION: u8 = 5;
+
+/// The version of the SOCKS5 username and password subnegotiation.
+const SOCKS_AUTH_VERSION: u8 = 1;
+
+/// The SOCKS5 authentication methods.
+const SOCKS_NO_AUTH: u8 = 0;
+const SOCKS_PASSWORD_AUTH: u8 = 2;
+const SOCKS_NO_ACCEPTABLE_METHOD: u8 = 0xFF;
+
+/// The SOCKS5 command to open a TCP connection.
+const SOCKS_CONNECT: u8 = 1;
+
+/// The SOCKS5 address types.
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+
+/// The longest HTTP response header we read.
+const MAX_HTTP_HEADER_LEN: usize = 8192;
+
+/// A proxy to connect to servers through
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proxy {
+    /// The protocol the proxy speaks
+    pub kind: ProxyKind,
+
+    /// The host of the proxy
+    pub host: String,
+
+    /// The port of the proxy
+    pub port: u16,
+
+    /// The username and password to log in to the proxy with, if it needs them
+    pub credentials: Option<(String, String)>,
+}
+
+/// The protocols a proxy can speak
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProxyKind {
+    /// SOCKS5, with username and password authentication if there are credentials
+    Socks5,
+    /// HTTP, using `CONNECT`, with basic authentication if there are credentials
+    HttpConnect,
+}
+
+/// An error from asking a proxy for a connection
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    /// IO error talking to the proxy
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The proxy doesn't speak the protocol it's configured with
+    #[error("the proxy sent an invalid reply")]
+    InvalidReply,
+
+    /// The proxy wants a way of logging in we don't support, or wants credentials we don't have
+    #[error("the proxy accepts none of our authentication methods")]
+    NoAcceptableAuth,
+
+    /// The proxy rejected the credentials
+    #[error("the proxy rejected our credentials")]
+    AuthFailed,
+
+    /// A SOCKS5 proxy refused to connect
+    #[error("the proxy couldn't connect: {message}")]
+    Socks {
+        /// The reply code the proxy sent
+        code: u8,
+        /// What the reply code means
+        message: &'static str,
+    },
+
+    /// An HTTP proxy refused to connect, with its status line
+    #[error("the proxy couldn't connect: {0}")]
+    Http(String),
+
+    /// The host is too long to send to a SOCKS5 proxy
+    #[error("the host name is too long for SOCKS5")]
+    HostTooLong,
+}
+
+impl Proxy {
+    /// Asks the proxy, over a connection to it, to connect to a server, which the connection then
+    /// reaches. The proxy resolves the host, so it may be a name only the proxy can resolve.
+    pub async fn handshake<S>(
+        &self,
+        stream: &mut S,
+        host: &str,
+        port: u16,
+    ) -> Result<(), ProxyError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        match self.kind {
+            ProxyKind::Socks5 => self.socks5_handshake(stream, host, port).await,
+            ProxyKind::HttpConnect => self.http_handshake(stream, host, port).await,
+        }
+    }
+
+    async fn socks5_handshake<S>(
+        &self,
+        stream: &mut S,
+        host: &str,
+        port: u16,
+    ) -> Result<(), ProxyError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let greeting: &[u8] = match self.credentials {
+            Some(_) => &[SOCKS_VERSION, 2, SOCKS_NO_AUTH, SOCKS_PASSWORD_AUTH],
+            None => &[SOCKS_VERSION, 1, SOCKS_NO_AUTH],
+        };
+        stream.write_all(greeting).await?;
+        let mut choice = [0; 2];
+        let _ = stream.read_exact(&mut choice).await?;
+        match (choice, &self.credentials) {
+            ([SOCKS_VERSION, SOCKS_NO_AUTH], _) => (),
+            ([SOCKS_VERSION, SOCKS_PASSWORD_AUTH], Some((username, password))) => {
+                let mut request = vec![SOCKS_AUTH_VERSION];
+                for field in &[username, password] {
+                    if field.len() > 255 {
+                        return Err(ProxyError::AuthFailed);
+                    }
+                    request.push(field.len() as u8);
+                    request.extend_from_slice(field.as_bytes());
+                }
+                stream.write_all(&request).await?;
+                let mut status = [0; 2];
+                let _ = stream.read_exact(&mut status).await?;
+                if status[1] != 0 {
+                    return Err(ProxyError::AuthFailed);
+                }
+            }
+            ([SOCKS_VERSION, SOCKS_NO_ACCEPTABLE_METHOD], _) => {
+                return Err(ProxyError::NoAcceptableAuth)
+            }
+            // a method we didn't offer
+            _ => return Err(ProxyError::InvalidReply),
+        }
+
+        let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(SOCKS_IPV4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(SOCKS_IPV6);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) if host.len() > 255 => return Err(ProxyError::HostTooLong),
+            Err(_) => {
+                request.extend_from_slice(&[SOCKS_DOMAIN, host.len() as u8]);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0; 4];
+        let _ = stream.read_exact(&mut reply).await?;
+        match reply {
+            [SOCKS_VERSION, 0, _, _] => (),
+            [SOCKS_VERSION, code, _, _] => {
+                return Err(ProxyError::Socks {
+                    code,
+                    message: socks_reply_message(code),
+                })
+            }
+            _ => return Err(ProxyError::InvalidReply),
+        }
+        // skip the address the proxy connected from, and its port
+        let len = match reply[3] {
+            SOCKS_IPV4 => 4,
+            SOCKS_IPV6 => 16,
+            SOCKS_DOMAIN => usize::from(stream.read_u8().await?),
+            _ => return Err(ProxyError::InvalidReply),
+        };
+        let _ = stream.read_exact(&mut vec![0; len + 2]).await?;
+        Ok(())
+    }
+
+    async fn http_handshake<S>(
+        &self,
+        stream: &mut S,
+        host: &str,
+        port: u16,
+    ) -> Result<(), ProxyError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let authority = match host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+            _ => format!("{}:{}", host, port),
+        };
+        let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+        if let Some((ref username, ref password)) = self.credentials {
+            let token = base64::encode(&format!("{}:{}", username, password));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        let header = read_http_header(stream).await?;
+        let status_line = header.lines().next().unwrap_or_default();
+        let mut parts = status_line.split(' ');
+        match (parts.next(), parts.next()) {
+            (Some(version), Some(status)) if version.starts_with("HTTP/") => {
+                match status.parse::<u16>() {
+                    Ok(200..=299) => Ok(()),
+                    Ok(407) => Err(ProxyError::AuthFailed),
+                    Ok(_) => Err(ProxyError::Http(status_line.to_owned())),
+                    Err(_) => Err(ProxyError::InvalidReply),
+                }
+            }
+            _ => Err(ProxyError::InvalidReply),
+        }
+    }
+}
+
+/// Reads the header of an HTTP response, up to and including the blank line that ends it.
+pub(crate) async fn read_http_header<S>(stream: &mut S) -> io::Result<String>
+where
+    S: AsyncRead + Unpin,
+{
+    // read a byte at a time, so nothing the server sends after the header is lost
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= MAX_HTTP_HEADER_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "HTTP header too long",
+            ));
+        }
+        header.push(stream.read_u8().await?);
+    }
+    Ok(String::from_utf8_lossy(&header).into_owned())
+}
+
+/// Describes a SOCKS5 reply code.
+fn socks_reply_message(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{self, Ipv4Addr};
+    use std::thread;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::{Proxy, ProxyError, ProxyKind};
+
+    /// Runs a proxy on a thread that follows a script, checking each request it gets and answering
+    /// with the reply that goes with it, and then echoes a line as if it were the server. Returns
+    /// a connection to it.
+    fn run_proxy(
+        script: &'static [(&'static [u8], &'static [u8])],
+    ) -> (TcpStream, thread::JoinHandle<()>) {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let proxy = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for (expected, reply) in script {
+                let mut request = vec![0; expected.len()];
+                stream.read_exact(&mut request).unwrap();
+                assert_eq!(&request, expected);
+                stream.write_all(reply).unwrap();
+            }
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            if reader.read_line(&mut line).is_ok() {
+                let _ = stream.write_all(line.as_bytes());
+            }
+        });
+        stream.set_nonblocking(true).unwrap();
+        (TcpStream::from_std(stream).unwrap(), proxy)
+    }
+
+    fn config(kind: ProxyKind, credentials: Option<(&str, &str)>) -> Proxy {
+        Proxy {
+            kind,
+            host: "localhost".to_owned(),
+            port: 1080,
+            credentials: credentials.map(|(user, pass)| (user.to_owned(), pass.to_owned())),
+        }
+    }
+
+    async fn ping(stream: &mut TcpStream) -> String {
+        stream.write_all(b"PING :x\r\n").await.unwrap();
+        let mut reply = String::new();
+        let _ = stream.read_to_string(&mut reply).await.unwrap();
+        reply
+    }
+
+    #[tokio::test]
+    async fn socks5() {
+        let (mut stream, proxy) = run_proxy(&[
+            (b"\x05\x01\x00", b"\x05\x00"),
+            (
+                b"\x05\x01\x00\x03\x0circ.test.net\x1a\x0b",
+                b"\x05\x00\x00\x01\x7f\x00\x00\x01\x04\x38",
+            ),
+        ]);
+        config(ProxyKind::Socks5, None)
+            .handshake(&mut stream, "irc.test.net", 6667)
+            .await
+            .unwrap();
+        assert_eq!(ping(&mut stream).await, "PING :x\r\n");
+        proxy.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn socks5_auth() {
+        let (mut stream, proxy) = run_proxy(&[
+            (b"\x05\x02\x00\x02", b"\x05\x02"),
+            (b"\x01\x04user\x04pass", b"\x01\x00"),
+            (
+                b"\x05\x01\x00\x01\x7f\x00\x00\x01\x1a\x0b",
+                b"\x05\x00\x00\x03\x04host\x04\x38",
+            ),
+        ]);
+        config(ProxyKind::Socks5, Some(("user", "pass")))
+            .handshake(&mut stream, "127.0.0.1", 6667)
+            .await
+            .unwrap();
+        assert_eq!(ping(&mut stream).await, "PING :x\r\n");
+        proxy.join().unwrap();
+
+        let (mut stream, proxy) = run_proxy(&[
+            (b"\x05\x02\x00\x02", b"\x05\x02"),
+            (b"\x01\x04user\x05wrong", b"\x01\x01"),
+        ]);
+        match config(ProxyKind::Socks5, Some(("user", "wrong")))
+            .handshake(&mut stream, "127.0.0.1", 6667)
+            .await
+        {
+            Err(ProxyError::AuthFailed) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+        drop(stream);
+        proxy.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn socks5_refused() {
+        let (mut stream, proxy) = run_proxy(&[
+            (b"\x05\x01\x00", b"\x05\x00"),
+            (
+                b"\x05\x01\x00\x03\x0circ.test.net\x1a\x0b",
+                b"\x05\x05\x00\x01\x00\x00\x00\x00\x00\x00",
+            ),
+        ]);
+        let result = config(ProxyKind::Socks5, None)
+            .handshake(&mut stream, "irc.test.net", 6667)
+            .await;
+        match result {
+            Err(ProxyError::Socks { code: 5, .. }) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "the proxy couldn't connect: connection refused"
+        );
+        drop(stream);
+        proxy.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn http_connect() {
+        let (mut stream, proxy) = run_proxy(&[(
+            b"CONNECT irc.test.net:6697 HTTP/1.1\r\nHost: irc.test.net:6697\r\n\
+              Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n",
+            b"HTTP/1.1 200 Connection established\r\n\r\n",
+        )]);
+        config(ProxyKind::HttpConnect, Some(("user", "pass")))
+            .handshake(&mut stream, "irc.test.net", 6697)
+            .await
+            .unwrap();
+        assert_eq!(ping(&mut stream).await, "PING :x\r\n");
+        proxy.join().unwrap();
+
+        let (mut stream, proxy) = run_proxy(&[(
+            b"CONNECT [::1]:6697 HTTP/1.1\r\nHost: [::1]:6697\r\n\r\n",
+            b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n",
+        )]);
+        match config(ProxyKind::HttpConnect, None)
+            .handshake(&mut stream, "::1", 6697)
+            .await
+        {
+            Err(ProxyError::Http(status)) => assert_eq!(status, "HTTP/1.1 403 Forbidden"),
+            result => panic!("unexpected {:?}", result),
+        }
+        drop(stream);
+        proxy.join().unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::{HashMap, VecDeque};
+
+use futures::channel::oneshot;
+
+use crate::proto::{message::Tag, BatchSubCommand, CaseMapping, Command, Message};
+
+/// The tag the server copies from a request onto its reply with `labeled-response`.
+pub const LABEL_TAG: &str = "label";
+
+/// The tag marking a message as part of a batch.
+pub const BATCH_TAG: &str = "batch";
+
+/// The batch type the server uses to group several replies to a labeled request.
+const LABELED_BATCH: &str = "LABELED-RESPONSE";
+
+/// A request waiting for its replies.
+struct Pending {
+    replies: Vec<Message>,
+    tx: oneshot::Sender<Vec<Message>>,
+}
+
+impl Pending {
+    fn new(tx: oneshot::Sender<Vec<Message>>) -> Self {
+        Pending {
+            replies: Vec::new(),
+            tx,
+        }
+    }
+
+    fn finish(self) {
+        // the requester may have stopped waiting, which is fine
+        let _ = self.tx.send(self.replies);
+    }
+}
+
+/// Replies being collected for requests made with `Client::request`.
+///
+/// With the `labeled-response` capability, the server tags its replies with the label of the
+/// request. Otherwise, each request is followed by a `PING`; servers handle commands in order, so
+/// the replies to a request are whatever numerics and echoes arrive before the matching `PONG`.
+#[derive(Default)]
+pub struct Requests {
+    next_id: u64,
+
+    /// Requests sent with a label, by label.
+    labeled: HashMap<String, Pending>,
+
+    /// The labels of the `labeled-response` batches being received, by batch reference.
+    batches: HashMap<String, String>,
+
+    /// Requests followed by a `PING`, in the order they were sent, with the `PING` token.
+    fallback: VecDeque<(String, Pending)>,
+}
+
+impl Requests {
+    /// Registers a request to be sent with a label, returning the label.
+    pub fn labeled(&mut self, tx: oneshot::Sender<Vec<Message>>) -> String {
+        let label = self.next_id();
+        let _ = self.labeled.insert(label.clone(), Pending::new(tx));
+        label
+    }
+
+    /// Registers a request to be followed by a `PING`, returning the `PING` token.
+    pub fn fallback(&mut self, tx: oneshot::Sender<Vec<Message>>) -> String {
+        let token = self.next_id();
+        self.fallback.push_back((token.clone(), Pending::new(tx)));
+        token
+    }
+
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("irc-async-{}", self.next_id)
+    }
+
+    /// Collects a message if it is a reply to a request. Returns true if the message was only
+    /// meant for the client, and shouldn't be passed on.
+    pub fn handle(&mut self, message: &Message, nick: &str, mapping: CaseMapping) -> bool {
+        if let Some(label) = tag(message, LABEL_TAG) {
+            let pending = match self.labeled.remove(label) {
+                Some(pending) => pending,
+                None => return false,
+            };
+            return match message.command {
+                Command::BATCH(ref reference, Some(BatchSubCommand::CUSTOM(ref kind)), _)
+                    if reference.starts_with('+') && kind == LABELED_BATCH =>
+                {
+                    let label = label.to_owned();
+                    let _ = self
+                        .batches
+                        .insert(reference[1..].to_owned(), label.clone());
+                    let _ = self.labeled.insert(label, pending);
+                    true
+                }
+                // an empty reply
+                Command::Raw(ref command, ..) if command == "ACK" => {
+                    pending.finish();
+                    true
+                }
+                _ => {
+                    let _ = pending.tx.send(vec![message.clone()]);
+                    false
+                }
+            };
+        }
+
+        if let Command::BATCH(ref reference, ..) = message.command {
+            if let Some(reference) = reference.strip_prefix('-') {
+                if let Some(label) = self.batches.remove(reference) {
+                    if let Some(pending) = self.labeled.remove(&label) {
+                        pending.finish();
+                    }
+                    return true;
+                }
+            }
+        }
+        let batches = &self.batches;
+        if let Some(label) = tag(message, BATCH_TAG).and_then(|batch| batches.get(batch)) {
+            if let Some(pending) = self.labeled.get_mut(label) {
+                pending.replies.push(message.clone());
+            }
+            return false;
+        }
+
+        if let Command::PONG(ref server, ref token) = message.command {
+            let token = token.as_ref().unwrap_or(server);
+            if let Some(index) = self.fallback.iter().position(|(t, _)| t == token) {
+                // anything still waiting before this one didn't get its own PONG either
+                for (_, pending) in self.fallback.drain(..=index) {
+                    pending.finish();
+                }
+                return true;
+            }
+        }
+        if is_reply(message, nick, mapping) {
+            if let Some((_, pending)) = self.fallback.front_mut() {
+                pending.replies.push(message.clone());
+            }
+        }
+        false
+    }
+
+    /// Cancels all requests, such as when the connection closes.
+    pub fn cancel(&mut self) {
+        self.labeled.clear();
+        self.batches.clear();
+        self.fallback.clear();
+    }
+}
+
+/// Looks up the value of a tag on a message.
+fn tag<'a>(message: &'a Message, key: &str) -> Option<&'a str> {
+    message
+        .tags
+        .as_ref()?
+        .iter()
+        .find(|Tag(k, _)| k == key)
+        .and_then(|Tag(_, v)| v.as_deref())
+}
+
+/// Guesses whether a message is a reply to a command the client sent: numerics, standard replies
+/// and the server echoing the client's own commands, like `JOIN`.
+fn is_reply(message: &Message, nick: &str, mapping: CaseMapping) -> bool {
+    match message.command {
+        Command::Response(..) | Command::StandardReply(_) => true,
+        Command::PING(..) | Command::PONG(..) => false,
+        _ => match message.source_nickname() {
+            Some(source) => mapping.eq(source, nick),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::channel::oneshot;
+
+    use super::Requests;
+    use crate::proto::{CaseMapping, Message};
+
+    fn message(line: &str) -> Message {
+        line.parse().unwrap()
+    }
+
+    #[test]
+    fn fallback_collects_until_pong() {
+        let mut requests = Requests::default();
+        let (tx, mut rx) = oneshot::channel();
+        let token = requests.fallback(tx);
+
+        let join = message(":Nick!user@host JOIN #channel");
+        assert!(!requests.handle(&join, "nick", CaseMapping::default()));
+        assert!(!requests.handle(
+            &message(":other!user@host JOIN #channel"),
+            "nick",
+            CaseMapping::default()
+        ));
+        assert!(!requests.handle(
+            &message(":server 332 nick #channel :Topic"),
+            "nick",
+            CaseMapping::default()
+        ));
+        assert_eq!(rx.try_recv().unwrap(), None);
+
+        let pong = message(&format!(":server PONG server :{}", token));
+        assert!(requests.handle(&pong, "nick", CaseMapping::default()));
+        let replies = rx.try_recv().unwrap().unwrap();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0], join);
+    }
+
+    #[test]
+    fn labeled_batch() {
+        let mut requests = Requests::default();
+        let (tx, mut rx) = oneshot::channel();
+        let label = requests.labeled(tx);
+
+        let start = format!("@label={} :server BATCH +ref LABELED-RESPONSE", label);
+        assert!(requests.handle(&message(&start), "nick", CaseMapping::default()));
+        let reply = message("@batch=ref :server 311 nick other user host * :Real Name");
+        assert!(!requests.handle(&reply, "nick", CaseMapping::default()));
+        assert!(!requests.handle(
+            &message(":server 372 nick :unrelated"),
+            "nick",
+            CaseMapping::default()
+        ));
+        assert!(requests.handle(
+            &message(":server BATCH -ref"),
+            "nick",
+            CaseMapping::default()
+        ));
+        assert_eq!(rx.try_recv().unwrap().unwrap(), vec![reply]);
+    }
+
+    #[test]
+    fn labeled_ack() {
+        let mut requests = Requests::default();
+        let (tx, mut rx) = oneshot::channel();
+        let label = requests.labeled(tx);
+
+        let ack = message(&format!("@label={} :server ACK", label));
+        assert!(requests.handle(&ack, "nick", CaseMapping::default()));
+        assert_eq!(rx.try_recv().unwrap().unwrap(), vec![]);
+    }
+}
@@ -4,11 +4,14 @@ use std::task::{Context, Poll};
 
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
-use tokio_tls::TlsStream;
+
+use crate::client::tls::TlsStream;
+use crate::client::websocket::WebSocketStream;
 
 pub enum ClientStream {
     Plain(TcpStream),
     Tls(TlsStream<TcpStream>),
+    WebSocket(Box<WebSocketStream<ClientStream>>),
 }
 
 impl AsyncRead for ClientStream {
@@ -22,6 +25,7 @@ impl AsyncRead for ClientStream {
             ClientStream::Tls(stream) => {
                 TlsStream::<TcpStream>::poll_read(Pin::new(stream), context, buf)
             }
+            ClientStream::WebSocket(stream) => Pin::new(stream.as_mut()).poll_read(context, buf),
         }
     }
 }
@@ -37,6 +41,7 @@ impl AsyncWrite for ClientStream {
             ClientStream::Tls(stream) => {
                 TlsStream::<TcpStream>::poll_write(Pin::new(stream), context, buf)
             }
+            ClientStream::WebSocket(stream) => Pin::new(stream.as_mut()).poll_write(context, buf),
         }
     }
 
@@ -46,6 +51,7 @@ impl AsyncWrite for ClientStream {
             ClientStream::Tls(stream) => {
                 TlsStream::<TcpStream>::poll_flush(Pin::new(stream), context)
             }
+            ClientStream::WebSocket(stream) => Pin::new(stream.as_mut()).poll_flush(context),
         }
     }
 
@@ -58,6 +64,7 @@ impl AsyncWrite for ClientStream {
             ClientStream::Tls(stream) => {
                 TlsStream::<TcpStream>::poll_shutdown(Pin::new(stream), context)
             }
+            ClientStream::WebSocket(stream) => Pin::new(stream.as_mut()).poll_shutdown(context),
         }
     }
 }
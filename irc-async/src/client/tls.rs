@@ -0,0 +1,384 @@
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use futures::future;
+use native_tls::{Certificate, HandshakeError, Identity, TlsConnector};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// TLS options for connecting to a server with `ssl` enabled
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// A PKCS #12 archive with a client certificate and its private key, to identify with, for
+    /// CertFP or SASL `EXTERNAL`. It isn't sent when `pinned_fingerprint` is set: the pin can only
+    /// be checked once the handshake is done, and the certificate would go to any server before.
+    pub client_cert: Option<Vec<u8>>,
+
+    /// The password the client certificate archive is encrypted with
+    pub client_cert_password: String,
+
+    /// Certificate authorities to trust besides the system's, as PEM bundles or DER certificates
+    pub ca_certs: Vec<Vec<u8>>,
+
+    /// The SHA-256 fingerprint of the server's certificate, in hex with or without colons. When
+    /// set, the server is trusted if its certificate matches, whoever signed it, and no client
+    /// certificate is sent.
+    pub pinned_fingerprint: Option<String>,
+}
+
+/// An error from setting up or verifying a TLS connection
+#[derive(Debug, Error)]
+pub enum TlsError {
+    /// The TLS library failed, or rejected the server's certificate
+    #[error("{0}")]
+    Native(#[from] native_tls::Error),
+
+    /// The server sent no certificate to check the pinned fingerprint against
+    #[error("the server sent no certificate")]
+    NoCertificate,
+
+    /// The server's certificate doesn't match the pinned fingerprint
+    #[error("certificate fingerprint {actual} doesn't match the pinned {expected}")]
+    FingerprintMismatch {
+        /// The pinned fingerprint
+        expected: String,
+        /// The fingerprint of the server's certificate
+        actual: String,
+    },
+}
+
+impl TlsConfig {
+    /// Builds a connector that presents the client certificate and trusts the extra certificate
+    /// authorities, or accepts any certificate if there is a pinned fingerprint.
+    fn connector(&self) -> Result<TlsConnector, TlsError> {
+        let mut builder = TlsConnector::builder();
+        for bundle in &self.ca_certs {
+            for cert in parse_certificates(bundle)? {
+                let _ = builder.add_root_certificate(cert);
+            }
+        }
+        if self.pinned_fingerprint.is_some() {
+            // the fingerprint is checked instead, once the handshake is done, so the server is
+            // unknown for as long as the client certificate would be sent
+            let _ = builder.danger_accept_invalid_certs(true);
+        } else if let Some(ref archive) = self.client_cert {
+            let identity = Identity::from_pkcs12(archive, &self.client_cert_password)?;
+            let _ = builder.identity(identity);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Connects over TLS on top of `stream`, checking the server's certificate against the pinned
+    /// fingerprint if there is one.
+    pub async fn connect<S>(&self, domain: &str, stream: S) -> Result<TlsStream<S>, TlsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let connector = self.connector()?;
+        let mut handshake = Some(connector.connect(domain, SyncStream::new(stream)));
+        let stream = future::poll_fn(|context| {
+            let result = match handshake.take().expect("polled after the handshake ended") {
+                Err(HandshakeError::WouldBlock(mut mid)) => {
+                    // try again, now that the stream can wake this task up
+                    mid.get_mut().set_waker(context);
+                    mid.handshake()
+                }
+                result => result,
+            };
+            match result {
+                Ok(stream) => Poll::Ready(Ok(stream)),
+                Err(HandshakeError::Failure(err)) => Poll::Ready(Err(err)),
+                Err(HandshakeError::WouldBlock(mid)) => {
+                    handshake = Some(Err(HandshakeError::WouldBlock(mid)));
+                    Poll::Pending
+                }
+            }
+        })
+        .await?;
+
+        if let Some(ref expected) = self.pinned_fingerprint {
+            let cert = stream.peer_certificate()?.ok_or(TlsError::NoCertificate)?;
+            let actual = fingerprint(&cert.to_der()?);
+            if normalize_fingerprint(expected) != actual {
+                return Err(TlsError::FingerprintMismatch {
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        Ok(TlsStream(stream))
+    }
+}
+
+/// Computes the SHA-256 fingerprint of a DER-encoded certificate, in lowercase hex.
+pub fn fingerprint(der: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(der))
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|&c| c != ':')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Parses every certificate in a PEM bundle, or a single DER certificate.
+fn parse_certificates(data: &[u8]) -> Result<Vec<Certificate>, native_tls::Error> {
+    const END: &str = "-----END CERTIFICATE-----";
+    match std::str::from_utf8(data) {
+        Ok(pem) if pem.contains(END) => pem
+            .split_terminator(END)
+            .filter(|cert| cert.contains("-----BEGIN CERTIFICATE-----"))
+            .map(|cert| Certificate::from_pem(format!("{}{}", cert, END).as_bytes()))
+            .collect(),
+        _ => Ok(vec![Certificate::from_der(data)?]),
+    }
+}
+
+/// Adapts an async stream to the blocking `Read` and `Write` native-tls uses. Operations poll the
+/// stream with the waker of the task that polled last, and fail with `WouldBlock` until it's
+/// ready.
+#[derive(Debug)]
+struct SyncStream<S> {
+    inner: S,
+    waker: Option<Waker>,
+}
+
+impl<S> SyncStream<S> {
+    fn new(inner: S) -> Self {
+        SyncStream { inner, waker: None }
+    }
+
+    fn set_waker(&mut self, context: &Context) {
+        match self.waker {
+            Some(ref waker) if waker.will_wake(context.waker()) => (),
+            _ => self.waker = Some(context.waker().clone()),
+        }
+    }
+
+    fn poll<T>(
+        &mut self,
+        f: impl FnOnce(Pin<&mut S>, &mut Context) -> Poll<io::Result<T>>,
+    ) -> io::Result<T>
+    where
+        S: Unpin,
+    {
+        let waker = match self.waker {
+            Some(ref waker) => waker,
+            None => return Err(io::ErrorKind::WouldBlock.into()),
+        };
+        match f(Pin::new(&mut self.inner), &mut Context::from_waker(waker)) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> Read for SyncStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.poll(|stream, context| stream.poll_read(context, buf))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Write for SyncStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.poll(|stream, context| stream.poll_write(context, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.poll(|stream, context| stream.poll_flush(context))
+    }
+}
+
+/// A TLS connection over an async stream
+///
+/// Unlike tokio-tls, this keeps the native-tls stream within reach, which checking the pinned
+/// fingerprint needs for the server's certificate.
+#[derive(Debug)]
+pub struct TlsStream<S>(native_tls::TlsStream<SyncStream<S>>);
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
+    /// Runs a blocking operation on the connection, turning `WouldBlock` into `Pending`.
+    fn poll<T>(
+        &mut self,
+        context: &Context,
+        f: impl FnOnce(&mut native_tls::TlsStream<SyncStream<S>>) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        self.0.get_mut().set_waker(context);
+        match f(&mut self.0) {
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll(context, |stream| stream.read(buf))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll(context, |stream| stream.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        self.get_mut().poll(context, |stream| stream.flush())
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll(context, |stream| stream.shutdown()) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.0.get_mut().inner).poll_shutdown(context),
+            poll => poll,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{self, Ipv4Addr};
+    use std::thread;
+
+    use native_tls::{Identity, TlsAcceptor};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::{fingerprint, parse_certificates, TlsConfig, TlsError};
+    use crate::client::{Client, ClientError, Config};
+
+    const CA: &[u8] = include_bytes!("../../tests/tls/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("../../tests/tls/server.pem");
+    const SERVER_IDENTITY: &[u8] = include_bytes!("../../tests/tls/server.p12");
+    const SERVER_FINGERPRINT: &str =
+        "4f:dd:dc:f0:f5:fe:20:85:a5:cd:67:a3:6c:e2:72:80:8b:3b:17:f2:65:80:70:68:19:ad:4b:a8:c4:a1:67:ef";
+
+    /// Starts a TLS server on loopback that answers one line with `pong`, and connects to it with
+    /// the config.
+    async fn ping(config: &TlsConfig) -> Result<String, TlsError> {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = thread::spawn(move || {
+            let identity = Identity::from_pkcs12(SERVER_IDENTITY, "test").unwrap();
+            let acceptor = TlsAcceptor::new(identity).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            // the client hangs up on certificates it rejects, during the handshake or after it
+            if let Ok(mut stream) = acceptor.accept(stream) {
+                let mut buf = [0; 5];
+                if stream.read_exact(&mut buf).is_ok() {
+                    stream.write_all(b"pong\n").unwrap();
+                }
+            }
+        });
+
+        stream.set_nonblocking(true).unwrap();
+        let stream = TcpStream::from_std(stream).unwrap();
+        let result = match config.connect("localhost", stream).await {
+            Ok(mut stream) => {
+                stream.write_all(b"ping\n").await.unwrap();
+                let mut reply = String::new();
+                let _ = stream.read_to_string(&mut reply).await.unwrap();
+                Ok(reply)
+            }
+            Err(err) => Err(err),
+        };
+        server.join().unwrap();
+        result
+    }
+
+    #[tokio::test]
+    async fn untrusted() {
+        match ping(&TlsConfig::default()).await {
+            Err(TlsError::Native(_)) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_ca() {
+        let config = TlsConfig {
+            ca_certs: vec![CA.to_vec()],
+            ..TlsConfig::default()
+        };
+        assert_eq!(ping(&config).await.unwrap(), "pong\n");
+    }
+
+    #[tokio::test]
+    async fn pinned() {
+        let config = TlsConfig {
+            pinned_fingerprint: Some(SERVER_FINGERPRINT.to_uppercase()),
+            ..TlsConfig::default()
+        };
+        assert_eq!(ping(&config).await.unwrap(), "pong\n");
+
+        let config = TlsConfig {
+            pinned_fingerprint: Some("00".repeat(32)),
+            ..TlsConfig::default()
+        };
+        match ping(&config).await {
+            Err(TlsError::FingerprintMismatch { actual, .. }) => {
+                assert_eq!(actual, SERVER_FINGERPRINT.replace(':', ""))
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn pin_checked_before_registration() {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let identity = Identity::from_pkcs12(SERVER_IDENTITY, "test").unwrap();
+            let acceptor = TlsAcceptor::new(identity).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            if let Ok(mut stream) = acceptor.accept(stream) {
+                let _ = stream.read_to_end(&mut received);
+            }
+            received
+        });
+
+        let config = Config {
+            host: "localhost".to_owned(),
+            port,
+            ssl: true,
+            tls: TlsConfig {
+                client_cert: Some(SERVER_IDENTITY.to_vec()),
+                client_cert_password: "test".to_owned(),
+                pinned_fingerprint: Some("00".repeat(32)),
+                ..TlsConfig::default()
+            },
+            happy_eyeballs: false,
+            nick: "flubber".to_owned(),
+            ..Config::default()
+        };
+        match Client::with_config(config).await {
+            Err(ClientError::Tls(TlsError::FingerprintMismatch { .. })) => (),
+            Err(err) => panic!("unexpected {}", err),
+            Ok(_) => panic!("connected despite the mismatched pin"),
+        }
+        assert!(server.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn bundle() {
+        let bundle = [CA, SERVER_CERT].concat();
+        let certs = parse_certificates(&bundle).unwrap();
+        assert_eq!(certs.len(), 2);
+        let der = certs[1].to_der().unwrap();
+        assert_eq!(fingerprint(&der), SERVER_FINGERPRINT.replace(':', ""));
+        assert_eq!(parse_certificates(&der).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,517 @@
+use std::convert::TryInto;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::{Buf, BufMut, BytesMut};
+use futures::ready;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::client::proxy::read_http_header;
+
+/// The subprotocol for IRC messages in text frames, which have to be UTF-8.
+const TEXT_PROTOCOL: &str = "text.ircv3.net";
+
+/// The subprotocol for IRC messages in binary frames, which can be in any encoding.
+const BINARY_PROTOCOL: &str = "binary.ircv3.net";
+
+/// The GUID servers append to the handshake key before hashing it, from RFC 6455.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The frame opcodes.
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// The largest message we accept from the server. IRC lines are far shorter, even with tags.
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// How many bytes of frames to buffer before waiting for the connection to take them.
+const MAX_WRITE_BUFFER: usize = 64 * 1024;
+
+/// An error from opening a WebSocket connection
+#[derive(Debug, Error)]
+pub enum WebSocketError {
+    /// IO error during the handshake
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The server didn't switch to the WebSocket protocol, with its status line
+    #[error("the server refused the WebSocket handshake: {0}")]
+    Refused(String),
+
+    /// The server's handshake reply was invalid
+    #[error("invalid WebSocket handshake: {0}")]
+    InvalidHandshake(&'static str),
+}
+
+/// An IRC connection over a WebSocket, as in the [IRCv3 WebSocket
+/// specification](https://ircv3.net/specs/extensions/websocket).
+///
+/// It reads and writes lines like a plain connection, so `IrcCodec` can run on top of it: each
+/// frame the server sends is read as one line, and each line written is sent as one frame, without
+/// the line ending.
+#[derive(Debug)]
+pub struct WebSocketStream<S> {
+    inner: S,
+    /// Whether messages are sent in binary frames rather than text ones.
+    binary: bool,
+    /// Bytes read from the connection that don't make up a whole frame yet.
+    read_buf: BytesMut,
+    /// The fragments of the message being received.
+    message: Vec<u8>,
+    /// Lines received, waiting to be read.
+    lines: BytesMut,
+    /// Bytes written that don't make up a whole line yet.
+    line_buf: BytesMut,
+    /// Frames waiting to be sent.
+    write_buf: BytesMut,
+    /// Whether a close frame has been sent.
+    closing: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> WebSocketStream<S> {
+    /// Performs the opening handshake over a connection to the server, asking for `path`, and
+    /// preferring binary frames.
+    pub async fn connect(
+        mut stream: S,
+        host: &str,
+        port: u16,
+        path: &str,
+    ) -> Result<Self, WebSocketError> {
+        let key = base64::encode(&rand::random::<[u8; 16]>());
+        let request = format!(
+            "GET {} HTTP/1.1\r\n\
+             Host: {}:{}\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\n\
+             Sec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Protocol: {}, {}\r\n\r\n",
+            path, host, port, key, BINARY_PROTOCOL, TEXT_PROTOCOL
+        );
+        stream.write_all(request.as_bytes()).await?;
+
+        let header = read_http_header(&mut stream).await?;
+        let mut lines = header.lines();
+        let status_line = lines.next().unwrap_or_default();
+        if status_line.split(' ').nth(1) != Some("101") {
+            return Err(WebSocketError::Refused(status_line.to_owned()));
+        }
+        let mut accept = None;
+        let mut protocol = None;
+        for line in lines {
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let value = parts.next().unwrap_or_default().trim();
+            match &name[..] {
+                "sec-websocket-accept" => accept = Some(value),
+                "sec-websocket-protocol" => protocol = Some(value),
+                _ => (),
+            }
+        }
+        if accept != Some(&accept_key(&key)[..]) {
+            return Err(WebSocketError::InvalidHandshake(
+                "wrong Sec-WebSocket-Accept",
+            ));
+        }
+        let binary = match protocol {
+            Some(BINARY_PROTOCOL) => true,
+            // servers that pick no subprotocol use text frames
+            Some(TEXT_PROTOCOL) | None => false,
+            Some(_) => {
+                return Err(WebSocketError::InvalidHandshake(
+                    "the server chose a subprotocol we didn't offer",
+                ))
+            }
+        };
+        Ok(WebSocketStream::new(stream, binary))
+    }
+}
+
+impl<S> WebSocketStream<S> {
+    fn new(inner: S, binary: bool) -> Self {
+        WebSocketStream {
+            inner,
+            binary,
+            read_buf: BytesMut::new(),
+            message: Vec::new(),
+            lines: BytesMut::new(),
+            line_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+            closing: false,
+        }
+    }
+
+    /// Handles a frame from the server, returning whether the connection is closed.
+    fn handle_frame(&mut self, frame: Frame) -> io::Result<bool> {
+        match frame.opcode {
+            OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                if self.message.len() + frame.payload.len() > MAX_MESSAGE_LEN {
+                    return Err(invalid_data("WebSocket message too long"));
+                }
+                self.message.extend_from_slice(&frame.payload);
+                if frame.fin {
+                    self.lines.extend_from_slice(&self.message);
+                    self.lines.extend_from_slice(b"\r\n");
+                    self.message.clear();
+                }
+            }
+            OPCODE_PING => encode_frame(
+                OPCODE_PONG,
+                &frame.payload,
+                Some(rand::random()),
+                &mut self.write_buf,
+            ),
+            OPCODE_PONG => (),
+            OPCODE_CLOSE => {
+                if !self.closing {
+                    // echo the status code, as the protocol asks
+                    let code = frame.payload.get(..2).unwrap_or_default();
+                    encode_frame(
+                        OPCODE_CLOSE,
+                        code,
+                        Some(rand::random()),
+                        &mut self.write_buf,
+                    );
+                    self.closing = true;
+                }
+                return Ok(true);
+            }
+            _ => return Err(invalid_data("unknown WebSocket opcode")),
+        }
+        Ok(false)
+    }
+
+    /// Frames each whole line written so far.
+    fn frame_lines(&mut self) {
+        while let Some(end) = self.line_buf.iter().position(|&b| b == b'\n') {
+            let mut line = self.line_buf.split_to(end + 1);
+            line.truncate(end);
+            if line.ends_with(b"\r") {
+                line.truncate(end - 1);
+            }
+            let opcode = if self.binary {
+                OPCODE_BINARY
+            } else {
+                OPCODE_TEXT
+            };
+            encode_frame(opcode, &line, Some(rand::random()), &mut self.write_buf);
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> WebSocketStream<S> {
+    /// Writes the frames waiting to be sent.
+    fn poll_write_frames(&mut self, context: &mut Context) -> Poll<io::Result<()>> {
+        while !self.write_buf.is_empty() {
+            let len = ready!(Pin::new(&mut self.inner).poll_write(context, &self.write_buf))?;
+            if len == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_buf.advance(len);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WebSocketStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if !this.lines.is_empty() {
+                let len = std::cmp::min(buf.len(), this.lines.len());
+                buf[..len].copy_from_slice(&this.lines.split_to(len));
+                return Poll::Ready(Ok(len));
+            }
+            if let Some(frame) = decode_frame(&mut this.read_buf)? {
+                let closed = this.handle_frame(frame)?;
+                // answer pings and closes even if nothing else is being written; if this fails,
+                // the next write fails too
+                let _ = this.poll_write_frames(context);
+                if closed {
+                    return Poll::Ready(Ok(0));
+                }
+                continue;
+            }
+            let mut chunk = [0; 4096];
+            let len = ready!(Pin::new(&mut this.inner).poll_read(context, &mut chunk))?;
+            if len == 0 {
+                return Poll::Ready(Ok(0));
+            }
+            this.read_buf.extend_from_slice(&chunk[..len]);
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WebSocketStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        context: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.write_buf.len() >= MAX_WRITE_BUFFER {
+            ready!(this.poll_write_frames(context))?;
+        }
+        this.line_buf.extend_from_slice(buf);
+        this.frame_lines();
+        // start sending, but the bytes are taken either way
+        let _ = this.poll_write_frames(context)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_frames(context))?;
+        Pin::new(&mut this.inner).poll_flush(context)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closing {
+            // 1000 is a normal closure
+            encode_frame(
+                OPCODE_CLOSE,
+                &1000u16.to_be_bytes(),
+                Some(rand::random()),
+                &mut this.write_buf,
+            );
+            this.closing = true;
+        }
+        ready!(this.poll_write_frames(context))?;
+        Pin::new(&mut this.inner).poll_shutdown(context)
+    }
+}
+
+/// A WebSocket frame.
+#[derive(Debug, PartialEq)]
+struct Frame {
+    /// Whether this is the last frame of its message.
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Takes a whole frame off the front of the buffer, if there is one.
+fn decode_frame(buf: &mut BytesMut) -> io::Result<Option<Frame>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let fin = buf[0] & 0x80 != 0;
+    let opcode = buf[0] & 0x0F;
+    let masked = buf[1] & 0x80 != 0;
+    let (len, mut start) = match buf[1] & 0x7F {
+        126 if buf.len() >= 4 => (u64::from(u16::from_be_bytes([buf[2], buf[3]])), 4),
+        127 if buf.len() >= 10 => (u64::from_be_bytes(buf[2..10].try_into().unwrap()), 10),
+        126 | 127 => return Ok(None),
+        len => (u64::from(len), 2),
+    };
+    if len > MAX_MESSAGE_LEN as u64 {
+        return Err(invalid_data("WebSocket frame too long"));
+    }
+    let len = len as usize;
+    let mask = if masked {
+        if buf.len() < start + 4 {
+            return Ok(None);
+        }
+        start += 4;
+        Some([
+            buf[start - 4],
+            buf[start - 3],
+            buf[start - 2],
+            buf[start - 1],
+        ])
+    } else {
+        None
+    };
+    if buf.len() < start + len {
+        return Ok(None);
+    }
+    let mut payload = buf.split_to(start + len).split_off(start).to_vec();
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload,
+    }))
+}
+
+/// Appends a whole frame to the buffer, masked if there's a mask, as frames from clients must be.
+fn encode_frame(opcode: u8, payload: &[u8], mask: Option<[u8; 4]>, buf: &mut BytesMut) {
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    buf.put_u8(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => buf.put_u8(mask_bit | len as u8),
+        len if len <= 0xFFFF => {
+            buf.put_u8(mask_bit | 126);
+            buf.put_u16(len as u16);
+        }
+        len => {
+            buf.put_u8(mask_bit | 127);
+            buf.put_u64(len as u64);
+        }
+    }
+    let mut payload = payload.to_vec();
+    if let Some(mask) = mask {
+        buf.extend_from_slice(&mask);
+        apply_mask(&mut payload, mask);
+    }
+    buf.extend_from_slice(&payload);
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Computes the `Sec-WebSocket-Accept` the server must answer a handshake key with.
+fn accept_key(key: &str) -> String {
+    base64::encode(&Sha1::digest(format!("{}{}", key, ACCEPT_GUID).as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{self, Ipv4Addr};
+    use std::thread;
+
+    use bytes::BytesMut;
+    use futures::sink::SinkExt;
+    use futures::stream::StreamExt;
+    use tokio::net::TcpStream;
+    use tokio_util::codec::Decoder;
+
+    use super::{
+        accept_key, decode_frame, encode_frame, Frame, WebSocketStream, OPCODE_BINARY, OPCODE_PING,
+        OPCODE_PONG, OPCODE_TEXT,
+    };
+    use crate::proto::{Command, IrcCodec, Message};
+
+    #[test]
+    fn handshake_key() {
+        // the example from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn frames() {
+        let mut buf = BytesMut::new();
+        let long = vec![b'x'; 300];
+        encode_frame(OPCODE_TEXT, b"hello", Some([1, 2, 3, 4]), &mut buf);
+        encode_frame(OPCODE_BINARY, &long, None, &mut buf);
+        assert_eq!(&buf[..2], b"\x81\x85");
+
+        let mut partial = BytesMut::from(&buf[..8]);
+        assert_eq!(decode_frame(&mut partial).unwrap(), None);
+        assert_eq!(
+            decode_frame(&mut buf).unwrap(),
+            Some(Frame {
+                fin: true,
+                opcode: OPCODE_TEXT,
+                payload: b"hello".to_vec(),
+            })
+        );
+        assert_eq!(decode_frame(&mut buf).unwrap().unwrap().payload, long);
+        assert!(buf.is_empty());
+    }
+
+    /// Reads a frame from a client over a blocking connection.
+    fn read_frame(stream: &mut net::TcpStream, buf: &mut BytesMut) -> Frame {
+        loop {
+            if let Some(frame) = decode_frame(buf).unwrap() {
+                return frame;
+            }
+            let mut chunk = [0; 1024];
+            let len = stream.read(&mut chunk).unwrap();
+            assert!(len > 0, "connection closed");
+            buf.extend_from_slice(&chunk[..len]);
+        }
+    }
+
+    #[tokio::test]
+    async fn connect() {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let stream = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                let mut byte = [0];
+                stream.read_exact(&mut byte).unwrap();
+                request.push(byte[0]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            assert!(request.starts_with("GET /webirc HTTP/1.1\r\n"));
+            assert!(request.contains("binary.ircv3.net, text.ircv3.net"));
+            let key = request
+                .lines()
+                .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+                .unwrap();
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                 Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\
+                 Sec-WebSocket-Protocol: text.ircv3.net\r\n\r\n",
+                accept_key(key)
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+
+            let mut frames = BytesMut::new();
+            encode_frame(OPCODE_PING, b"keepalive", None, &mut frames);
+            encode_frame(OPCODE_TEXT, b"PING :irc.test.net", None, &mut frames);
+            stream.write_all(&frames).unwrap();
+
+            let mut buf = BytesMut::new();
+            let pong = read_frame(&mut stream, &mut buf);
+            assert_eq!(
+                (pong.opcode, &pong.payload[..]),
+                (OPCODE_PONG, &b"keepalive"[..])
+            );
+            let reply = read_frame(&mut stream, &mut buf);
+            assert_eq!(
+                (reply.opcode, &reply.payload[..]),
+                (OPCODE_TEXT, &b"PONG :irc.test.net"[..])
+            );
+        });
+
+        stream.set_nonblocking(true).unwrap();
+        let stream = TcpStream::from_std(stream).unwrap();
+        let stream = WebSocketStream::connect(stream, "localhost", 8097, "/webirc")
+            .await
+            .unwrap();
+        assert!(!stream.binary);
+        let mut framed = IrcCodec::default().framed(stream);
+        let message = framed.next().await.unwrap().unwrap();
+        assert_eq!(
+            message.command,
+            Command::PING("irc.test.net".to_owned(), None)
+        );
+        framed
+            .send(Message {
+                tags: None,
+                prefix: None,
+                command: Command::PONG("irc.test.net".to_owned(), None),
+            })
+            .await
+            .unwrap();
+        server.join().unwrap();
+    }
+}
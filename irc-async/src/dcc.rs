@@ -0,0 +1,274 @@
+//! Transferring files and chatting over the direct connections offered with
+//! [`Dcc`](crate::proto::Dcc) requests.
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::future::{self, Future};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+use tokio_util::codec::{Decoder, Framed, LinesCodec};
+
+use crate::client::{connect_proxied, ClientError, Config};
+
+/// How many bytes to read or write at once.
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// Downloads a file offered with `DCC SEND`, by connecting to the sender and writing what it
+/// sends to `writer`. The connection is made the way the client connects to servers with `config`,
+/// so it goes through the proxy if there is one.
+///
+/// The transfer starts at `position`, which is 0 unless the sender accepted a `DCC RESUME`, and
+/// ends once it reaches `size`, if the offer gave one, or when the sender closes the connection.
+/// Returns the position the transfer ended at, which is short of the size if the sender closed the
+/// connection early, so the rest can be resumed later.
+///
+/// Fails with `TimedOut` if connecting to the sender, or waiting for it to send more, takes longer
+/// than `config.ping_timeout`, so a sender that stops sending can't hold up the transfer forever.
+pub async fn receive<W>(
+    config: &Config,
+    address: SocketAddr,
+    position: u64,
+    size: Option<u64>,
+    writer: &mut W,
+) -> io::Result<u64>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut stream = connect(config, address).await?;
+    receive_on(&mut stream, position, size, writer, config.ping_timeout).await
+}
+
+/// Downloads a file like `receive`, over a connection to the sender that is already open, failing
+/// with `TimedOut` if the sender sends nothing for `idle_timeout`.
+pub async fn receive_on<S, W>(
+    stream: &mut S,
+    position: u64,
+    size: Option<u64>,
+    writer: &mut W,
+    idle_timeout: Duration,
+) -> io::Result<u64>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut position = position;
+    loop {
+        match size {
+            Some(size) if position >= size => break,
+            _ => (),
+        }
+        let len = timeout(idle_timeout, stream.read(&mut buf)).await?;
+        if len == 0 {
+            break;
+        }
+        writer.write_all(&buf[..len]).await?;
+        position += len as u64;
+        // acknowledge what we have so far, as the low 32 bits of the position
+        stream.write_all(&(position as u32).to_be_bytes()).await?;
+    }
+    writer.flush().await?;
+    // let the sender know we're done, even if it's waiting for an acknowledgement
+    stream.shutdown().await?;
+    Ok(position)
+}
+
+/// Sends a file offered with `DCC SEND` to whoever connects to `listener` first, reading it from
+/// `reader`.
+///
+/// The offer has to give the receiver an address that reaches the listener. `reader` should be at
+/// `position`, which is 0 unless the receiver asked to resume with `DCC RESUME`, and `size` is the
+/// size of the whole file. If `reader` ends before that, the connection is closed there, so the
+/// receiver can resume later. Returns once the receiver acknowledged the whole file, or closed the
+/// connection.
+pub async fn send<R>(
+    listener: &mut TcpListener,
+    reader: &mut R,
+    position: u64,
+    size: u64,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    let (mut stream, _) = listener.accept().await?;
+    send_on(&mut stream, reader, position, size).await
+}
+
+/// Sends a file like `send`, over a connection from the receiver that is already open.
+pub async fn send_on<S, R>(
+    stream: &mut S,
+    reader: &mut R,
+    position: u64,
+    size: u64,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    let (mut acks, mut sink) = io::split(stream);
+
+    let write = async {
+        let mut buf = vec![0; BUFFER_SIZE];
+        let mut position = position;
+        while position < size {
+            let len = reader.read(&mut buf).await?;
+            if len == 0 {
+                break;
+            }
+            sink.write_all(&buf[..len]).await?;
+            position += len as u64;
+        }
+        // the receiver can't tell the file ended early otherwise
+        sink.shutdown().await
+    };
+    // read acknowledgements as they come, so they can't fill up the connection
+    let read_acks = async {
+        let mut ack = [0; 4];
+        loop {
+            match acks.read_exact(&mut ack).await {
+                Ok(_) if u32::from_be_bytes(ack) == size as u32 => return Ok(()),
+                Ok(_) => (),
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    };
+    let _ = future::try_join(write, read_acks).await?;
+    Ok(())
+}
+
+/// Connects to a chat offered with `DCC CHAT`, which exchanges lines of text. Like `receive`, the
+/// connection goes through the proxy in `config` if there is one, and fails with `TimedOut` if it
+/// takes longer than `config.ping_timeout`.
+pub async fn chat(
+    config: &Config,
+    address: SocketAddr,
+) -> io::Result<Framed<TcpStream, LinesCodec>> {
+    let stream = connect(config, address).await?;
+    Ok(LinesCodec::new().framed(stream))
+}
+
+/// Opens a connection to the other side of an offer with the settings in `config`.
+async fn connect(config: &Config, address: SocketAddr) -> io::Result<TcpStream> {
+    let stream = async {
+        connect_proxied(config, &address.ip().to_string(), address.port())
+            .await
+            .map_err(|err| match err {
+                ClientError::Io(err) => err,
+                err => io::Error::other(err),
+            })
+    };
+    timeout(config.ping_timeout, stream).await
+}
+
+/// Runs an IO future, failing with `TimedOut` if it takes longer than `duration`.
+async fn timeout<T>(
+    duration: Duration,
+    future: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    time::timeout(duration, future)
+        .await
+        .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{self, Ipv4Addr};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::future;
+    use tokio::io;
+    use tokio::net::TcpStream;
+
+    use super::{receive, receive_on, send_on};
+    use crate::client::Config;
+
+    const IDLE: Duration = Duration::from_secs(5);
+
+    fn data() -> Vec<u8> {
+        (0..100_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Opens a connection over loopback, returning the sender's end and the receiver's end.
+    fn connect() -> (TcpStream, TcpStream) {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let receiver = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (sender, _) = listener.accept().unwrap();
+        let from_std = |stream: net::TcpStream| {
+            stream.set_nonblocking(true).unwrap();
+            TcpStream::from_std(stream).unwrap()
+        };
+        (from_std(sender), from_std(receiver))
+    }
+
+    #[tokio::test]
+    async fn send_and_receive() {
+        let data = data();
+        let size = data.len() as u64;
+        let (mut sender, mut receiver) = connect();
+
+        let mut received = Vec::new();
+        let (sent, position) = future::join(
+            send_on(&mut sender, &mut &data[..], 0, size),
+            receive_on(&mut receiver, 0, Some(size), &mut received, IDLE),
+        )
+        .await;
+        sent.unwrap();
+        assert_eq!(position.unwrap(), size);
+        assert_eq!(received, data);
+    }
+
+    #[tokio::test]
+    async fn resume() {
+        let data = data();
+        let size = data.len() as u64;
+
+        // the sender closes the connection partway through
+        let (mut sender, mut receiver) = connect();
+        let mut received = Vec::new();
+        let (sent, position) = future::join(
+            send_on(&mut sender, &mut &data[..40_000], 0, size),
+            receive_on(&mut receiver, 0, Some(size), &mut received, IDLE),
+        )
+        .await;
+        sent.unwrap();
+        assert_eq!(position.unwrap(), 40_000);
+
+        let (mut sender, mut receiver) = connect();
+        let (sent, position) = future::join(
+            send_on(&mut sender, &mut &data[40_000..], 40_000, size),
+            receive_on(&mut receiver, 40_000, Some(size), &mut received, IDLE),
+        )
+        .await;
+        sent.unwrap();
+        assert_eq!(position.unwrap(), size);
+        assert_eq!(received, data);
+    }
+
+    #[tokio::test]
+    async fn stalled() {
+        let listener = net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        // the sender accepts the connection, and then sends nothing
+        let sender = thread::spawn(move || {
+            let _socket = listener.accept().unwrap();
+            let _ = done_rx.recv();
+        });
+
+        let config = Config {
+            ping_timeout: Duration::from_millis(50),
+            ..Config::default()
+        };
+        let mut received = Vec::new();
+        let err = receive(&config, address, 0, Some(10), &mut received)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        done_tx.send(()).unwrap();
+        sender.join().unwrap();
+    }
+}
@@ -36,6 +36,10 @@
 extern crate thiserror;
 
 mod client;
+pub mod dcc;
 pub mod proto;
 
-pub use crate::client::{Client, ClientError, Config};
+pub use crate::client::{
+    Client, ClientError, ClientHandle, Config, Dispatcher, Event, EventKind, Proxy, ProxyError,
+    ProxyKind, TlsConfig, TlsError, WebSocketError,
+};
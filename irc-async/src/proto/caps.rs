@@ -36,6 +36,20 @@ pub enum Capability {
     ServerTime,
     /// [userhost-in-names](http://ircv3.net/specs/extensions/userhost-in-names-3.2.html)
     UserhostInNames,
+    /// [message-tags](https://ircv3.net/specs/extensions/message-tags)
+    MessageTags,
+    /// [setname](https://ircv3.net/specs/extensions/setname)
+    SetName,
+    /// [draft/message-redaction](https://ircv3.net/specs/extensions/message-redaction)
+    MessageRedaction,
+    /// [draft/read-marker](https://ircv3.net/specs/extensions/read-marker)
+    ReadMarker,
+    /// [draft/chathistory](https://ircv3.net/specs/extensions/chathistory)
+    ChatHistory,
+    /// [labeled-response](https://ircv3.net/specs/extensions/labeled-response)
+    LabeledResponse,
+    /// [draft/multiline](https://ircv3.net/specs/extensions/multiline)
+    Multiline,
     /// Custom IRCv3 capability extensions
     Custom(&'static str),
 }
@@ -67,6 +81,13 @@ impl AsRef<str> for Capability {
             Capability::InviteNotify => "invite-notify",
             Capability::ServerTime => "server-time",
             Capability::UserhostInNames => "userhost-in-names",
+            Capability::MessageTags => "message-tags",
+            Capability::SetName => "setname",
+            Capability::MessageRedaction => "draft/message-redaction",
+            Capability::ReadMarker => "draft/read-marker",
+            Capability::ChatHistory => "draft/chathistory",
+            Capability::LabeledResponse => "labeled-response",
+            Capability::Multiline => "draft/multiline",
             Capability::Custom(s) => s,
         }
     }
@@ -94,6 +115,13 @@ mod test {
         assert_eq!(InviteNotify.as_ref(), "invite-notify");
         assert_eq!(ServerTime.as_ref(), "server-time");
         assert_eq!(UserhostInNames.as_ref(), "userhost-in-names");
+        assert_eq!(MessageTags.as_ref(), "message-tags");
+        assert_eq!(SetName.as_ref(), "setname");
+        assert_eq!(MessageRedaction.as_ref(), "draft/message-redaction");
+        assert_eq!(ReadMarker.as_ref(), "draft/read-marker");
+        assert_eq!(ChatHistory.as_ref(), "draft/chathistory");
+        assert_eq!(LabeledResponse.as_ref(), "labeled-response");
+        assert_eq!(Multiline.as_ref(), "draft/multiline");
         assert_eq!(Custom("example").as_ref(), "example");
     }
 }
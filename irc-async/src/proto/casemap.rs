@@ -0,0 +1,265 @@
+//! Case-insensitive comparison of nicks and channel names, following the server's `CASEMAPPING`.
+use std::collections::btree_map::{self, BTreeMap};
+use std::str::FromStr;
+
+use crate::proto::MessageParseError;
+
+/// The rules a server uses to decide which nicks and channel names are the same, as advertised by
+/// `CASEMAPPING` in `RPL_ISUPPORT`.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// assert!(CaseMapping::Rfc1459.eq("#Rust[]", "#rust{}"));
+/// assert!(!CaseMapping::Ascii.eq("#Rust[]", "#rust{}"));
+/// assert_eq!(CaseMapping::StrictRfc1459.fold("Nick[^]"), "nick{^}");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CaseMapping {
+    /// Only the letters `A` to `Z` are folded to `a` to `z`.
+    Ascii,
+    /// Like `Ascii`, and `[]\~` are folded to `{}|^`. The default when servers don't say.
+    #[default]
+    Rfc1459,
+    /// Like `Ascii`, and `[]\` are folded to `{}|`.
+    StrictRfc1459,
+}
+
+impl CaseMapping {
+    /// Gets the name of the case mapping, as it appears in `CASEMAPPING`.
+    pub fn to_str(&self) -> &str {
+        match *self {
+            CaseMapping::Ascii => "ascii",
+            CaseMapping::Rfc1459 => "rfc1459",
+            CaseMapping::StrictRfc1459 => "strict-rfc1459",
+        }
+    }
+
+    /// Folds a character to its lowercase form.
+    pub fn fold_char(&self, c: char) -> char {
+        match (*self, c) {
+            (_, 'A'..='Z') => c.to_ascii_lowercase(),
+            (CaseMapping::Ascii, _) => c,
+            (_, '[') => '{',
+            (_, ']') => '}',
+            (_, '\\') => '|',
+            (CaseMapping::Rfc1459, '~') => '^',
+            _ => c,
+        }
+    }
+
+    /// Folds a nick or channel name to its lowercase form, such as to use it as a key.
+    pub fn fold(&self, s: &str) -> String {
+        s.chars().map(|c| self.fold_char(c)).collect()
+    }
+
+    /// Returns true if two nicks or channel names are the same.
+    pub fn eq(&self, a: &str, b: &str) -> bool {
+        a.len() == b.len()
+            && a.chars()
+                .map(|c| self.fold_char(c))
+                .eq(b.chars().map(|c| self.fold_char(c)))
+    }
+}
+
+impl FromStr for CaseMapping {
+    type Err = MessageParseError;
+
+    fn from_str(s: &str) -> Result<CaseMapping, Self::Err> {
+        match s {
+            "ascii" => Ok(CaseMapping::Ascii),
+            "rfc1459" => Ok(CaseMapping::Rfc1459),
+            "strict-rfc1459" => Ok(CaseMapping::StrictRfc1459),
+            _ => Err(MessageParseError::InvalidSubcommand {
+                cmd: "CASEMAPPING",
+                sub: s.to_owned(),
+            }),
+        }
+    }
+}
+
+/// A map keyed by nicks or channel names, where keys that only differ in case are the same.
+///
+/// The map remembers each key as it was first inserted.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// let mut channels = CaseMap::new(CaseMapping::Rfc1459);
+/// let _ = channels.insert("#Rust", 1);
+/// assert_eq!(channels.get("#rust"), Some(&1));
+/// assert_eq!(channels.key("#RUST"), Some("#Rust"));
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CaseMap<V> {
+    mapping: CaseMapping,
+    inner: BTreeMap<String, (String, V)>,
+}
+
+impl<V> Default for CaseMap<V> {
+    fn default() -> Self {
+        CaseMap::new(CaseMapping::default())
+    }
+}
+
+impl<V> CaseMap<V> {
+    /// Creates an empty map that compares keys with the case mapping.
+    pub fn new(mapping: CaseMapping) -> Self {
+        CaseMap {
+            mapping,
+            inner: BTreeMap::new(),
+        }
+    }
+
+    /// Gets the case mapping keys are compared with.
+    pub fn case_mapping(&self) -> CaseMapping {
+        self.mapping
+    }
+
+    /// Changes the case mapping keys are compared with, such as once the server advertises its
+    /// own. Only one of the entries whose keys become the same is kept.
+    pub fn set_case_mapping(&mut self, mapping: CaseMapping) {
+        if mapping == self.mapping {
+            return;
+        }
+        self.mapping = mapping;
+        let inner = std::mem::take(&mut self.inner);
+        for (key, value) in inner.into_values() {
+            let _ = self.insert(key, value);
+        }
+    }
+
+    /// Inserts a value, returning the value that was under the same key, if any.
+    pub fn insert<K: Into<String>>(&mut self, key: K, value: V) -> Option<V> {
+        let key = key.into();
+        match self.inner.entry(self.mapping.fold(&key)) {
+            btree_map::Entry::Occupied(mut entry) => {
+                Some(std::mem::replace(&mut entry.get_mut().1, value))
+            }
+            btree_map::Entry::Vacant(entry) => {
+                let _ = entry.insert((key, value));
+                None
+            }
+        }
+    }
+
+    /// Gets the value under a key.
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.inner
+            .get(&self.mapping.fold(key))
+            .map(|(_, value)| value)
+    }
+
+    /// Gets the value under a key mutably.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        self.inner
+            .get_mut(&self.mapping.fold(key))
+            .map(|(_, value)| value)
+    }
+
+    /// Gets the value under a key, inserting one if there is none.
+    pub fn get_or_insert_with<K, F>(&mut self, key: K, default: F) -> &mut V
+    where
+        K: Into<String>,
+        F: FnOnce() -> V,
+    {
+        let key = key.into();
+        let (_, value) = self
+            .inner
+            .entry(self.mapping.fold(&key))
+            .or_insert_with(|| (key, default()));
+        value
+    }
+
+    /// Gets the key as it was first inserted.
+    pub fn key(&self, key: &str) -> Option<&str> {
+        self.inner
+            .get(&self.mapping.fold(key))
+            .map(|(key, _)| key.as_str())
+    }
+
+    /// Removes the value under a key.
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        self.inner
+            .remove(&self.mapping.fold(key))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns true if there is a value under the key.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.inner.contains_key(&self.mapping.fold(key))
+    }
+
+    /// Gets the number of entries.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns true if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Iterates over the entries, with the keys as they were first inserted.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &V)> {
+        self.inner
+            .values()
+            .map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// Iterates over the keys as they were first inserted.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.inner.values().map(|(key, _)| key.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CaseMap, CaseMapping};
+
+    #[test]
+    fn fold() {
+        assert_eq!(CaseMapping::Ascii.fold("ABC[]\\~"), "abc[]\\~");
+        assert_eq!(CaseMapping::Rfc1459.fold("ABC[]\\~"), "abc{}|^");
+        assert_eq!(CaseMapping::StrictRfc1459.fold("ABC[]\\~"), "abc{}|~");
+        assert_eq!(CaseMapping::Rfc1459.fold("ÉTÉ"), "ÉtÉ");
+    }
+
+    #[test]
+    fn eq() {
+        assert!(CaseMapping::Rfc1459.eq("Nick~", "nick^"));
+        assert!(!CaseMapping::StrictRfc1459.eq("Nick~", "nick^"));
+        assert!(!CaseMapping::Ascii.eq("nick", "nick2"));
+    }
+
+    #[test]
+    fn parse() {
+        for mapping in &[
+            CaseMapping::Ascii,
+            CaseMapping::Rfc1459,
+            CaseMapping::StrictRfc1459,
+        ] {
+            assert_eq!(mapping.to_str().parse::<CaseMapping>().unwrap(), *mapping);
+        }
+        assert!("rfc7613".parse::<CaseMapping>().is_err());
+    }
+
+    #[test]
+    fn map() {
+        let mut map = CaseMap::new(CaseMapping::Ascii);
+        assert_eq!(map.insert("#Chan[]", 1), None);
+        assert_eq!(map.insert("#chan{}", 2), None);
+        assert_eq!(map.insert("#CHAN[]", 3), Some(1));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.key("#chan[]"), Some("#Chan[]"));
+
+        map.set_case_mapping(CaseMapping::Rfc1459);
+        assert_eq!(map.len(), 1);
+        assert!(map.remove("#CHAN{}").is_some());
+        assert!(map.is_empty());
+    }
+}
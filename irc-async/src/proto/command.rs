@@ -1,7 +1,12 @@
 //! Enumeration of all available client commands.
+use std::borrow::Cow;
 use std::str::FromStr;
 
-use crate::proto::{ChannelExt, ChannelMode, MessageParseError, Mode, Response, UserMode};
+use crate::proto::mode::ModeType;
+use crate::proto::{
+    ChannelExt, ChannelMode, MessageParseError, Mode, Response, StandardReply, StandardReplyKind,
+    UserMode,
+};
 
 /// List of all client commands as defined in [RFC 2812](http://tools.ietf.org/html/rfc2812). This
 /// also includes commands from the
@@ -199,6 +204,22 @@ pub enum Command {
     /// CHGHOST user host
     CHGHOST(String, String),
 
+    // Later IRCv3 extensions
+    /// TAGMSG msgtarget
+    TAGMSG(String),
+    /// SETNAME :realname
+    SETNAME(String),
+    /// REDACT target msgid :[reason]
+    REDACT(String, String, Option<String>),
+    /// MARKREAD target [timestamp]
+    MARKREAD(String, Option<String>),
+    /// CHATHISTORY COMMAND params
+    CHATHISTORY(ChatHistorySubCommand, Vec<String>),
+    /// (FAIL|WARN|NOTE) command code [context] :description
+    StandardReply(StandardReply),
+    /// WEBIRC password gateway hostname ip :[options]
+    WEBIRC(String, String, String, String, Option<String>),
+
     // Default option.
     /// An IRC response code with arguments and optional suffix.
     Response(Response, Vec<String>, Option<String>),
@@ -206,1419 +227,1184 @@ pub enum Command {
     Raw(String, Vec<String>, Option<String>),
 }
 
-fn stringify(cmd: &str, args: &[&str], suffix: Option<&str>) -> String {
-    let args = args.join(" ");
-    let sp = if args.is_empty() { "" } else { " " };
-    match suffix {
-        Some(suffix) => format!("{}{}{} :{}", cmd, sp, args, suffix),
-        None => format!("{}{}{}", cmd, sp, args),
+/// The parameters of a command, in the order they are written on the wire.
+type Params<'a> = Vec<Cow<'a, str>>;
+
+/// An entry in the command table, describing how to parse and serialize one command.
+struct CommandSpec {
+    /// The name of the command, in upper case.
+    name: &'static str,
+    /// The minimum number of parameters, counting the suffix.
+    min: usize,
+    /// The maximum number of parameters, counting the suffix.
+    max: usize,
+    /// The position from which the last parameter is always written as the suffix, after a colon.
+    /// Before it, or if this is `None`, the last parameter is only written that way if it has to be.
+    suffix: Option<usize>,
+    /// Builds the command from its parameters, of which there are between `min` and `max`. An
+    /// `InvalidSubcommand` error makes the command fall back to `Command::Raw`.
+    parse: fn(&[&str]) -> Result<Command, MessageParseError>,
+    /// Gets the parameters of the command, or `None` if it is a different command.
+    serialize: fn(&Command) -> Option<Params>,
+}
+
+impl CommandSpec {
+    /// Finds the entry for a command name, ignoring case.
+    fn find(name: &str) -> Option<&'static CommandSpec> {
+        COMMANDS
+            .binary_search_by(|spec| {
+                spec.name
+                    .bytes()
+                    .cmp(name.bytes().map(|b| b.to_ascii_uppercase()))
+            })
+            .ok()
+            .map(|i| &COMMANDS[i])
     }
 }
 
-impl<'a> From<&'a Command> for String {
-    #[allow(clippy::many_single_char_names)]
-    fn from(cmd: &'a Command) -> String {
-        match *cmd {
-            Command::PASS(ref p) => stringify("PASS", &[], Some(p)),
-            Command::NICK(ref n) => stringify("NICK", &[], Some(n)),
-            Command::USER(ref u, ref m, ref r) => stringify("USER", &[u, m, "*"], Some(r)),
-            Command::OPER(ref u, ref p) => stringify("OPER", &[u], Some(p)),
-            Command::UserMODE(ref u, ref m) => format!(
-                "MODE {}{}",
-                u,
-                m.iter().fold(String::new(), |mut acc, mode| {
-                    acc.push_str(" ");
-                    acc.push_str(&mode.to_string());
-                    acc
-                })
-            ),
+/// The table of all commands known to the crate, sorted by name.
+static COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "ACCOUNT",
+        min: 1,
+        max: 1,
+        suffix: None,
+        parse: |p| Ok(Command::ACCOUNT(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::ACCOUNT(ref a) => params(&[a], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "ADMIN",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::ADMIN(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::ADMIN(ref t) => params(&[], t.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "AUTHENTICATE",
+        min: 1,
+        max: 1,
+        suffix: None,
+        parse: |p| Ok(Command::AUTHENTICATE(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::AUTHENTICATE(ref d) => params(&[d], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "AWAY",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::AWAY(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::AWAY(ref m) => params(&[], m.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "BATCH",
+        min: 1,
+        max: usize::MAX,
+        suffix: None,
+        parse: |p| {
+            Ok(Command::BATCH(
+                p[0].to_owned(),
+                p.get(1).map(|s| s.parse()).transpose()?,
+                opt_list(p, 2),
+            ))
+        },
+        serialize: |cmd| match *cmd {
+            Command::BATCH(ref t, ref c, ref a) => {
+                let mut args = vec![&t[..]];
+                args.extend(c.as_ref().map(|c| c.to_str()));
+                args.extend(a.iter().flatten().map(|s| &s[..]));
+                params(&args, None)
+            }
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "BOTSERV",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::BOTSERV(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::BOTSERV(ref m) => params(&[], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "CAP",
+        min: 1,
+        max: 4,
+        suffix: Some(1),
+        parse: |p| {
+            // the target is only present in replies from the server
+            let (target, p) = match p[0].parse::<CapSubCommand>() {
+                Ok(_) => (None, p),
+                Err(_) => (Some(p[0].to_owned()), &p[1..]),
+            };
+            let sub = p.first().unwrap_or(&"").parse()?;
+            let (extra, param) = match p.len() {
+                1 => (None, None),
+                2 => (None, opt(p, 1)),
+                3 => (opt(p, 1), opt(p, 2)),
+                _ => {
+                    return Err(MessageParseError::InvalidSubcommand {
+                        cmd: "CAP",
+                        sub: p.join(" "),
+                    })
+                }
+            };
+            Ok(Command::CAP(target, sub, extra, param))
+        },
+        serialize: |cmd| match *cmd {
+            Command::CAP(ref k, ref s, ref c, ref p) => {
+                let mut args = Vec::new();
+                args.extend(k.as_deref());
+                args.push(s.to_str());
+                args.extend(c.as_deref());
+                params(&args, p.as_deref())
+            }
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "CHANSERV",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::CHANSERV(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::CHANSERV(ref m) => params(&[], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "CHATHISTORY",
+        min: 1,
+        max: usize::MAX,
+        suffix: None,
+        parse: |p| Ok(Command::CHATHISTORY(p[0].parse()?, list(&p[1..]))),
+        serialize: |cmd| match *cmd {
+            Command::CHATHISTORY(ref s, ref a) => {
+                let mut args = vec![s.to_str()];
+                args.extend(strs(a));
+                params(&args, None)
+            }
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "CHGHOST",
+        min: 2,
+        max: 2,
+        suffix: None,
+        parse: |p| Ok(Command::CHGHOST(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::CHGHOST(ref u, ref h) => params(&[u, h], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "CONNECT",
+        min: 2,
+        max: 3,
+        suffix: Some(2),
+        parse: |p| {
+            Ok(Command::CONNECT(
+                p[0].to_owned(),
+                p[1].to_owned(),
+                opt(p, 2),
+            ))
+        },
+        serialize: |cmd| match *cmd {
+            Command::CONNECT(ref t, ref p, ref r) => params(&[t, p], r.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "DIE",
+        min: 0,
+        max: 0,
+        suffix: None,
+        parse: |_| Ok(Command::DIE),
+        serialize: |cmd| match *cmd {
+            Command::DIE => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "ERROR",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::ERROR(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::ERROR(ref m) => params(&[], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "FAIL",
+        min: 3,
+        max: usize::MAX,
+        suffix: Some(2),
+        parse: |p| Ok(standard_reply(StandardReplyKind::Fail, p)),
+        serialize: standard_reply_params,
+    },
+    CommandSpec {
+        name: "HOSTSERV",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::HOSTSERV(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::HOSTSERV(ref m) => params(&[], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "INFO",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::INFO(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::INFO(ref t) => params(&[], t.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "INVITE",
+        min: 2,
+        max: 2,
+        suffix: None,
+        parse: |p| Ok(Command::INVITE(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::INVITE(ref n, ref c) => params(&[n, c], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "ISON",
+        min: 0,
+        max: usize::MAX,
+        suffix: None,
+        parse: |p| Ok(Command::ISON(list(p))),
+        serialize: |cmd| match *cmd {
+            Command::ISON(ref u) => params(&strs(u), None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "JOIN",
+        min: 1,
+        max: 3,
+        suffix: Some(2),
+        parse: |p| Ok(Command::JOIN(p[0].to_owned(), opt(p, 1), opt(p, 2))),
+        serialize: |cmd| match *cmd {
+            Command::JOIN(ref c, Some(ref k), ref n) => params(&[c, k], n.as_deref()),
+            Command::JOIN(ref c, None, ref n) => params(&[c], n.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "KICK",
+        min: 2,
+        max: 3,
+        suffix: Some(2),
+        parse: |p| Ok(Command::KICK(p[0].to_owned(), p[1].to_owned(), opt(p, 2))),
+        serialize: |cmd| match *cmd {
+            Command::KICK(ref c, ref n, ref r) => params(&[c, n], r.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "KILL",
+        min: 2,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::KILL(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::KILL(ref n, ref c) => params(&[n], Some(c)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "LINKS",
+        min: 0,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| {
+            Ok(match p.len() {
+                2 => Command::LINKS(opt(p, 0), opt(p, 1)),
+                _ => Command::LINKS(None, opt(p, 0)),
+            })
+        },
+        serialize: |cmd| match *cmd {
+            Command::LINKS(Some(ref r), Some(ref s)) => params(&[r], Some(s)),
+            Command::LINKS(None, ref s) => params(&[], s.as_deref()),
+            Command::LINKS(Some(ref r), None) => params(&[r], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "LIST",
+        min: 0,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::LIST(opt(p, 0), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::LIST(Some(ref c), ref t) => params(&[c], t.as_deref()),
+            Command::LIST(None, _) => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "LUSERS",
+        min: 0,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::LUSERS(opt(p, 0), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::LUSERS(Some(ref m), ref t) => params(&[m], t.as_deref()),
+            Command::LUSERS(None, _) => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "MARKREAD",
+        min: 1,
+        max: 2,
+        suffix: None,
+        parse: |p| Ok(Command::MARKREAD(p[0].to_owned(), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::MARKREAD(ref t, Some(ref m)) => params(&[t, m], None),
+            Command::MARKREAD(ref t, None) => params(&[t], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "MEMOSERV",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::MEMOSERV(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::MEMOSERV(ref m) => params(&[], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "METADATA",
+        min: 2,
+        max: usize::MAX,
+        suffix: Some(2),
+        parse: |p| {
+            // the last parameter is the value, and any others in between are keys
+            let rest = &p[2..];
+            let (args, param) = match rest.split_last() {
+                Some((param, args)) => (opt_list(args, 0), Some((*param).to_owned())),
+                None => (None, None),
+            };
+            Ok(Command::METADATA(
+                p[0].to_owned(),
+                Some(p[1].parse()?),
+                args,
+                param,
+            ))
+        },
+        serialize: |cmd| match *cmd {
+            Command::METADATA(ref t, ref c, ref a, ref p) => {
+                let mut args = vec![&t[..]];
+                args.extend(c.as_ref().map(|c| c.to_str()));
+                args.extend(a.iter().flatten().map(|s| &s[..]));
+                params(&args, p.as_deref())
+            }
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "MODE",
+        min: 1,
+        max: usize::MAX,
+        suffix: None,
+        parse: |p| {
+            let modes = p[1..].join(" ");
+            Ok(if p[0].is_channel_name() {
+                Command::ChannelMODE(p[0].to_owned(), Mode::from_channel_mode_string(&modes)?)
+            } else {
+                Command::UserMODE(p[0].to_owned(), Mode::from_user_mode_string(&modes)?)
+            })
+        },
+        serialize: |cmd| match *cmd {
+            Command::UserMODE(ref u, ref m) => mode_params(u, m),
+            Command::ChannelMODE(ref c, ref m) => mode_params(c, m),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "MONITOR",
+        min: 1,
+        max: 2,
+        suffix: None,
+        parse: |p| Ok(Command::MONITOR(p[0].to_owned(), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::MONITOR(ref c, Some(ref t)) => params(&[c, t], None),
+            Command::MONITOR(ref c, None) => params(&[c], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "MOTD",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::MOTD(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::MOTD(ref t) => params(&[], t.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "NAMES",
+        min: 0,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::NAMES(opt(p, 0), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::NAMES(Some(ref c), ref t) => params(&[c], t.as_deref()),
+            Command::NAMES(None, _) => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "NICK",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::NICK(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::NICK(ref n) => params(&[], Some(n)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "NICKSERV",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::NICKSERV(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::NICKSERV(ref m) => params(&[], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "NOTE",
+        min: 3,
+        max: usize::MAX,
+        suffix: Some(2),
+        parse: |p| Ok(standard_reply(StandardReplyKind::Note, p)),
+        serialize: standard_reply_params,
+    },
+    CommandSpec {
+        name: "NOTICE",
+        min: 2,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::NOTICE(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::NOTICE(ref t, ref m) => params(&[t], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "OPER",
+        min: 2,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::OPER(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::OPER(ref u, ref p) => params(&[u], Some(p)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "OPERSERV",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::OPERSERV(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::OPERSERV(ref m) => params(&[], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "PART",
+        min: 1,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::PART(p[0].to_owned(), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::PART(ref c, ref m) => params(&[c], m.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "PASS",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::PASS(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::PASS(ref p) => params(&[], Some(p)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "PING",
+        min: 1,
+        max: 2,
+        suffix: Some(0),
+        parse: |p| Ok(Command::PING(p[0].to_owned(), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::PING(ref s, Some(ref t)) => params(&[s], Some(t)),
+            Command::PING(ref s, None) => params(&[], Some(s)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "PONG",
+        min: 1,
+        max: 2,
+        suffix: Some(0),
+        parse: |p| Ok(Command::PONG(p[0].to_owned(), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::PONG(ref s, Some(ref t)) => params(&[s], Some(t)),
+            Command::PONG(ref s, None) => params(&[], Some(s)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "PRIVMSG",
+        min: 2,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::PRIVMSG(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::PRIVMSG(ref t, ref m) => params(&[t], Some(m)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "QUIT",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::QUIT(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::QUIT(ref m) => params(&[], m.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "REDACT",
+        min: 2,
+        max: 3,
+        suffix: Some(2),
+        parse: |p| Ok(Command::REDACT(p[0].to_owned(), p[1].to_owned(), opt(p, 2))),
+        serialize: |cmd| match *cmd {
+            Command::REDACT(ref t, ref m, ref r) => params(&[t, m], r.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "REHASH",
+        min: 0,
+        max: 0,
+        suffix: None,
+        parse: |_| Ok(Command::REHASH),
+        serialize: |cmd| match *cmd {
+            Command::REHASH => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "RESTART",
+        min: 0,
+        max: 0,
+        suffix: None,
+        parse: |_| Ok(Command::RESTART),
+        serialize: |cmd| match *cmd {
+            Command::RESTART => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SAJOIN",
+        min: 2,
+        max: 2,
+        suffix: None,
+        parse: |p| Ok(Command::SAJOIN(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::SAJOIN(ref n, ref c) => params(&[n, c], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SAMODE",
+        min: 2,
+        max: 3,
+        suffix: None,
+        parse: |p| Ok(Command::SAMODE(p[0].to_owned(), p[1].to_owned(), opt(p, 2))),
+        serialize: |cmd| match *cmd {
+            Command::SAMODE(ref t, ref m, Some(ref p)) => params(&[t, m, p], None),
+            Command::SAMODE(ref t, ref m, None) => params(&[t, m], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SANICK",
+        min: 2,
+        max: 2,
+        suffix: None,
+        parse: |p| Ok(Command::SANICK(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::SANICK(ref o, ref n) => params(&[o, n], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SAPART",
+        min: 2,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::SAPART(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::SAPART(ref c, ref r) => params(&[c], Some(r)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SAQUIT",
+        min: 2,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::SAQUIT(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::SAQUIT(ref c, ref r) => params(&[c], Some(r)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SERVICE",
+        min: 6,
+        max: 6,
+        suffix: Some(5),
+        parse: |p| {
+            Ok(Command::SERVICE(
+                p[0].to_owned(),
+                p[1].to_owned(),
+                p[2].to_owned(),
+                p[3].to_owned(),
+                p[4].to_owned(),
+                p[5].to_owned(),
+            ))
+        },
+        serialize: |cmd| match *cmd {
             Command::SERVICE(ref n, ref r, ref d, ref t, ref re, ref i) => {
-                stringify("SERVICE", &[n, r, d, t, re], Some(i))
-            }
-            Command::QUIT(Some(ref m)) => stringify("QUIT", &[], Some(m)),
-            Command::QUIT(None) => stringify("QUIT", &[], None),
-            Command::SQUIT(ref s, ref c) => stringify("SQUIT", &[s], Some(c)),
-            Command::JOIN(ref c, Some(ref k), Some(ref n)) => stringify("JOIN", &[c, k], Some(n)),
-            Command::JOIN(ref c, Some(ref k), None) => stringify("JOIN", &[c, k], None),
-            Command::JOIN(ref c, None, Some(ref n)) => stringify("JOIN", &[c], Some(n)),
-            Command::JOIN(ref c, None, None) => stringify("JOIN", &[c], None),
-            Command::PART(ref c, Some(ref m)) => stringify("PART", &[c], Some(m)),
-            Command::PART(ref c, None) => stringify("PART", &[c], None),
-            Command::ChannelMODE(ref u, ref m) => format!(
-                "MODE {}{}",
-                u,
-                m.iter().fold(String::new(), |mut acc, mode| {
-                    acc.push_str(" ");
-                    acc.push_str(&mode.to_string());
-                    acc
-                })
-            ),
-            Command::TOPIC(ref c, Some(ref t)) => stringify("TOPIC", &[c], Some(t)),
-            Command::TOPIC(ref c, None) => stringify("TOPIC", &[c], None),
-            Command::NAMES(Some(ref c), Some(ref t)) => stringify("NAMES", &[c], Some(t)),
-            Command::NAMES(Some(ref c), None) => stringify("NAMES", &[c], None),
-            Command::NAMES(None, _) => stringify("NAMES", &[], None),
-            Command::LIST(Some(ref c), Some(ref t)) => stringify("LIST", &[c], Some(t)),
-            Command::LIST(Some(ref c), None) => stringify("LIST", &[c], None),
-            Command::LIST(None, _) => stringify("LIST", &[], None),
-            Command::INVITE(ref n, ref c) => stringify("INVITE", &[n, c], None),
-            Command::KICK(ref c, ref n, Some(ref r)) => stringify("KICK", &[c, n], Some(r)),
-            Command::KICK(ref c, ref n, None) => stringify("KICK", &[c, n], None),
-            Command::PRIVMSG(ref t, ref m) => stringify("PRIVMSG", &[t], Some(m)),
-            Command::NOTICE(ref t, ref m) => stringify("NOTICE", &[t], Some(m)),
-            Command::MOTD(Some(ref t)) => stringify("MOTD", &[], Some(t)),
-            Command::MOTD(None) => stringify("MOTD", &[], None),
-            Command::LUSERS(Some(ref m), Some(ref t)) => stringify("LUSERS", &[m], Some(t)),
-            Command::LUSERS(Some(ref m), None) => stringify("LUSERS", &[m], None),
-            Command::LUSERS(None, _) => stringify("LUSERS", &[], None),
-            Command::VERSION(Some(ref t)) => stringify("VERSION", &[], Some(t)),
-            Command::VERSION(None) => stringify("VERSION", &[], None),
-            Command::STATS(Some(ref q), Some(ref t)) => stringify("STATS", &[q], Some(t)),
-            Command::STATS(Some(ref q), None) => stringify("STATS", &[q], None),
-            Command::STATS(None, _) => stringify("STATS", &[], None),
-            Command::LINKS(Some(ref r), Some(ref s)) => stringify("LINKS", &[r], Some(s)),
-            Command::LINKS(None, Some(ref s)) => stringify("LINKS", &[], Some(s)),
-            Command::LINKS(_, None) => stringify("LINKS", &[], None),
-            Command::TIME(Some(ref t)) => stringify("TIME", &[], Some(t)),
-            Command::TIME(None) => stringify("TIME", &[], None),
-            Command::CONNECT(ref t, ref p, Some(ref r)) => stringify("CONNECT", &[t, p], Some(r)),
-            Command::CONNECT(ref t, ref p, None) => stringify("CONNECT", &[t, p], None),
-            Command::TRACE(Some(ref t)) => stringify("TRACE", &[], Some(t)),
-            Command::TRACE(None) => stringify("TRACE", &[], None),
-            Command::ADMIN(Some(ref t)) => stringify("ADMIN", &[], Some(t)),
-            Command::ADMIN(None) => stringify("ADMIN", &[], None),
-            Command::INFO(Some(ref t)) => stringify("INFO", &[], Some(t)),
-            Command::INFO(None) => stringify("INFO", &[], None),
-            Command::SERVLIST(Some(ref m), Some(ref t)) => stringify("SERVLIST", &[m], Some(t)),
-            Command::SERVLIST(Some(ref m), None) => stringify("SERVLIST", &[m], None),
-            Command::SERVLIST(None, _) => stringify("SERVLIST", &[], None),
-            Command::SQUERY(ref s, ref t) => stringify("SQUERY", &[s, t], None),
-            Command::WHO(Some(ref s), Some(true)) => stringify("WHO", &[s, "o"], None),
-            Command::WHO(Some(ref s), _) => stringify("WHO", &[s], None),
-            Command::WHO(None, _) => stringify("WHO", &[], None),
-            Command::WHOIS(Some(ref t), ref m) => stringify("WHOIS", &[t, m], None),
-            Command::WHOIS(None, ref m) => stringify("WHOIS", &[m], None),
-            Command::WHOWAS(ref n, Some(ref c), Some(ref t)) => {
-                stringify("WHOWAS", &[n, c], Some(t))
-            }
-            Command::WHOWAS(ref n, Some(ref c), None) => stringify("WHOWAS", &[n, c], None),
-            Command::WHOWAS(ref n, None, _) => stringify("WHOWAS", &[n], None),
-            Command::KILL(ref n, ref c) => stringify("KILL", &[n], Some(c)),
-            Command::PING(ref s, Some(ref t)) => stringify("PING", &[s], Some(t)),
-            Command::PING(ref s, None) => stringify("PING", &[], Some(s)),
-            Command::PONG(ref s, Some(ref t)) => stringify("PONG", &[s], Some(t)),
-            Command::PONG(ref s, None) => stringify("PONG", &[], Some(s)),
-            Command::ERROR(ref m) => stringify("ERROR", &[], Some(m)),
-            Command::AWAY(Some(ref m)) => stringify("AWAY", &[], Some(m)),
-            Command::AWAY(None) => stringify("AWAY", &[], None),
-            Command::REHASH => stringify("REHASH", &[], None),
-            Command::DIE => stringify("DIE", &[], None),
-            Command::RESTART => stringify("RESTART", &[], None),
-            Command::SUMMON(ref u, Some(ref t), Some(ref c)) => {
-                stringify("SUMMON", &[u, t], Some(c))
-            }
-            Command::SUMMON(ref u, Some(ref t), None) => stringify("SUMMON", &[u, t], None),
-            Command::SUMMON(ref u, None, _) => stringify("SUMMON", &[u], None),
-            Command::USERS(Some(ref t)) => stringify("USERS", &[], Some(t)),
-            Command::USERS(None) => stringify("USERS", &[], None),
-            Command::WALLOPS(ref t) => stringify("WALLOPS", &[], Some(t)),
-            Command::USERHOST(ref u) => stringify(
-                "USERHOST",
-                &u.iter().map(|s| &s[..]).collect::<Vec<_>>(),
-                None,
-            ),
-            Command::ISON(ref u) => {
-                stringify("ISON", &u.iter().map(|s| &s[..]).collect::<Vec<_>>(), None)
-            }
+                params(&[n, r, d, t, re], Some(i))
+            }
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SERVLIST",
+        min: 0,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::SERVLIST(opt(p, 0), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::SERVLIST(Some(ref m), ref t) => params(&[m], t.as_deref()),
+            Command::SERVLIST(None, _) => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SETNAME",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::SETNAME(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::SETNAME(ref r) => params(&[], Some(r)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SQUERY",
+        min: 2,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::SQUERY(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::SQUERY(ref s, ref t) => params(&[s], Some(t)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SQUIT",
+        min: 2,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::SQUIT(p[0].to_owned(), p[1].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::SQUIT(ref s, ref c) => params(&[s], Some(c)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "STATS",
+        min: 0,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::STATS(opt(p, 0), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::STATS(Some(ref q), ref t) => params(&[q], t.as_deref()),
+            Command::STATS(None, _) => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "SUMMON",
+        min: 1,
+        max: 3,
+        suffix: Some(2),
+        parse: |p| Ok(Command::SUMMON(p[0].to_owned(), opt(p, 1), opt(p, 2))),
+        serialize: |cmd| match *cmd {
+            Command::SUMMON(ref u, Some(ref t), ref c) => params(&[u, t], c.as_deref()),
+            Command::SUMMON(ref u, None, _) => params(&[u], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "TAGMSG",
+        min: 1,
+        max: 1,
+        suffix: None,
+        parse: |p| Ok(Command::TAGMSG(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::TAGMSG(ref t) => params(&[t], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "TIME",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::TIME(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::TIME(ref t) => params(&[], t.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "TOPIC",
+        min: 1,
+        max: 2,
+        suffix: Some(1),
+        parse: |p| Ok(Command::TOPIC(p[0].to_owned(), opt(p, 1))),
+        serialize: |cmd| match *cmd {
+            Command::TOPIC(ref c, ref t) => params(&[c], t.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "TRACE",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::TRACE(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::TRACE(ref t) => params(&[], t.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "USER",
+        min: 4,
+        max: 4,
+        suffix: Some(3),
+        parse: |p| {
+            Ok(Command::USER(
+                p[0].to_owned(),
+                p[1].to_owned(),
+                p[3].to_owned(),
+            ))
+        },
+        serialize: |cmd| match *cmd {
+            Command::USER(ref u, ref m, ref r) => params(&[u, m, "*"], Some(r)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "USERHOST",
+        min: 0,
+        max: usize::MAX,
+        suffix: None,
+        parse: |p| Ok(Command::USERHOST(list(p))),
+        serialize: |cmd| match *cmd {
+            Command::USERHOST(ref u) => params(&strs(u), None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "USERS",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::USERS(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::USERS(ref t) => params(&[], t.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "VERSION",
+        min: 0,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::VERSION(opt(p, 0))),
+        serialize: |cmd| match *cmd {
+            Command::VERSION(ref t) => params(&[], t.as_deref()),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "WALLOPS",
+        min: 1,
+        max: 1,
+        suffix: Some(0),
+        parse: |p| Ok(Command::WALLOPS(p[0].to_owned())),
+        serialize: |cmd| match *cmd {
+            Command::WALLOPS(ref t) => params(&[], Some(t)),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "WARN",
+        min: 3,
+        max: usize::MAX,
+        suffix: Some(2),
+        parse: |p| Ok(standard_reply(StandardReplyKind::Warn, p)),
+        serialize: standard_reply_params,
+    },
+    CommandSpec {
+        name: "WEBIRC",
+        min: 4,
+        max: 5,
+        suffix: Some(4),
+        parse: |p| {
+            Ok(Command::WEBIRC(
+                p[0].to_owned(),
+                p[1].to_owned(),
+                p[2].to_owned(),
+                p[3].to_owned(),
+                opt(p, 4),
+            ))
+        },
+        serialize: |cmd| match *cmd {
+            Command::WEBIRC(ref p, ref g, ref h, ref i, ref o) => {
+                params(&[p, g, h, i], o.as_deref())
+            }
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "WHO",
+        min: 0,
+        max: 2,
+        suffix: None,
+        parse: |p| Ok(Command::WHO(opt(p, 0), p.get(1).map(|&o| o == "o"))),
+        serialize: |cmd| match *cmd {
+            Command::WHO(Some(ref s), Some(true)) => params(&[s, "o"], None),
+            Command::WHO(Some(ref s), _) => params(&[s], None),
+            Command::WHO(None, _) => params(&[], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "WHOIS",
+        min: 1,
+        max: 2,
+        suffix: None,
+        parse: |p| {
+            Ok(match p.len() {
+                2 => Command::WHOIS(opt(p, 0), p[1].to_owned()),
+                _ => Command::WHOIS(None, p[0].to_owned()),
+            })
+        },
+        serialize: |cmd| match *cmd {
+            Command::WHOIS(Some(ref t), ref m) => params(&[t, m], None),
+            Command::WHOIS(None, ref m) => params(&[m], None),
+            _ => None,
+        },
+    },
+    CommandSpec {
+        name: "WHOWAS",
+        min: 1,
+        max: 3,
+        suffix: Some(2),
+        parse: |p| Ok(Command::WHOWAS(p[0].to_owned(), opt(p, 1), opt(p, 2))),
+        serialize: |cmd| match *cmd {
+            Command::WHOWAS(ref n, Some(ref c), ref t) => params(&[n, c], t.as_deref()),
+            Command::WHOWAS(ref n, None, _) => params(&[n], None),
+            _ => None,
+        },
+    },
+];
 
-            Command::SAJOIN(ref n, ref c) => stringify("SAJOIN", &[n, c], None),
-            Command::SAMODE(ref t, ref m, Some(ref p)) => stringify("SAMODE", &[t, m, p], None),
-            Command::SAMODE(ref t, ref m, None) => stringify("SAMODE", &[t, m], None),
-            Command::SANICK(ref o, ref n) => stringify("SANICK", &[o, n], None),
-            Command::SAPART(ref c, ref r) => stringify("SAPART", &[c], Some(r)),
-            Command::SAQUIT(ref c, ref r) => stringify("SAQUIT", &[c], Some(r)),
-
-            Command::NICKSERV(ref m) => stringify("NICKSERV", &[m], None),
-            Command::CHANSERV(ref m) => stringify("CHANSERV", &[m], None),
-            Command::OPERSERV(ref m) => stringify("OPERSERV", &[m], None),
-            Command::BOTSERV(ref m) => stringify("BOTSERV", &[m], None),
-            Command::HOSTSERV(ref m) => stringify("HOSTSERV", &[m], None),
-            Command::MEMOSERV(ref m) => stringify("MEMOSERV", &[m], None),
-
-            Command::CAP(None, ref s, None, Some(ref p)) => {
-                stringify("CAP", &[s.to_str()], Some(p))
-            }
-            Command::CAP(None, ref s, None, None) => stringify("CAP", &[s.to_str()], None),
-            Command::CAP(Some(ref k), ref s, None, Some(ref p)) => {
-                stringify("CAP", &[k, s.to_str()], Some(p))
-            }
-            Command::CAP(Some(ref k), ref s, None, None) => {
-                stringify("CAP", &[k, s.to_str()], None)
-            }
-            Command::CAP(None, ref s, Some(ref c), Some(ref p)) => {
-                stringify("CAP", &[s.to_str(), c], Some(p))
-            }
-            Command::CAP(None, ref s, Some(ref c), None) => {
-                stringify("CAP", &[s.to_str(), c], None)
-            }
-            Command::CAP(Some(ref k), ref s, Some(ref c), Some(ref p)) => {
-                stringify("CAP", &[k, s.to_str(), c], Some(p))
-            }
-            Command::CAP(Some(ref k), ref s, Some(ref c), None) => {
-                stringify("CAP", &[k, s.to_str(), c], None)
-            }
+/// Borrows the given arguments and optional last argument as the parameters of a command.
+fn params<'a>(args: &[&'a str], last: Option<&'a str>) -> Option<Params<'a>> {
+    Some(
+        args.iter()
+            .cloned()
+            .chain(last)
+            .map(Cow::Borrowed)
+            .collect(),
+    )
+}
 
-            Command::AUTHENTICATE(ref d) => stringify("AUTHENTICATE", &[d], None),
-            Command::ACCOUNT(ref a) => stringify("ACCOUNT", &[a], None),
+/// Gets the parameters of a `MODE` command.
+fn mode_params<'a, T: ModeType>(target: &'a str, modes: &'a [Mode<T>]) -> Option<Params<'a>> {
+    let mut args = vec![Cow::Borrowed(target)];
+    for mode in modes {
+        let (flag, arg) = match *mode {
+            Mode::Plus(ref mode, ref arg) => (format!("+{}", mode), arg),
+            Mode::Minus(ref mode, ref arg) => (format!("-{}", mode), arg),
+        };
+        args.push(Cow::Owned(flag));
+        args.extend(arg.as_deref().map(Cow::Borrowed));
+    }
+    Some(args)
+}
 
-            Command::METADATA(ref t, Some(ref c), None, Some(ref p)) => {
-                stringify("METADATA", &[&t[..], c.to_str()], Some(p))
-            }
-            Command::METADATA(ref t, Some(ref c), None, None) => {
-                stringify("METADATA", &[&t[..], c.to_str()], None)
-            }
+/// Builds a standard reply from its parameters.
+fn standard_reply(kind: StandardReplyKind, p: &[&str]) -> Command {
+    let last = p.len() - 1;
+    Command::StandardReply(StandardReply {
+        kind,
+        command: p[0].to_owned(),
+        code: p[1].to_owned(),
+        context: list(&p[2..last]),
+        description: p[last].to_owned(),
+    })
+}
 
-            Command::METADATA(ref t, Some(ref c), Some(ref a), Some(ref p)) => stringify(
-                "METADATA",
-                &vec![t, &c.to_str().to_owned()]
-                    .iter()
-                    .map(|s| &s[..])
-                    .chain(a.iter().map(|s| &s[..]))
-                    .collect::<Vec<_>>(),
-                Some(p),
-            ),
-            Command::METADATA(ref t, Some(ref c), Some(ref a), None) => stringify(
-                "METADATA",
-                &vec![t, &c.to_str().to_owned()]
-                    .iter()
-                    .map(|s| &s[..])
-                    .chain(a.iter().map(|s| &s[..]))
-                    .collect::<Vec<_>>(),
-                None,
-            ),
-            Command::METADATA(ref t, None, None, Some(ref p)) => {
-                stringify("METADATA", &[t], Some(p))
-            }
-            Command::METADATA(ref t, None, None, None) => stringify("METADATA", &[t], None),
-            Command::METADATA(ref t, None, Some(ref a), Some(ref p)) => stringify(
-                "METADATA",
-                &vec![t]
-                    .iter()
-                    .map(|s| &s[..])
-                    .chain(a.iter().map(|s| &s[..]))
-                    .collect::<Vec<_>>(),
-                Some(p),
-            ),
-            Command::METADATA(ref t, None, Some(ref a), None) => stringify(
-                "METADATA",
-                &vec![t]
-                    .iter()
-                    .map(|s| &s[..])
-                    .chain(a.iter().map(|s| &s[..]))
-                    .collect::<Vec<_>>(),
-                None,
-            ),
-            Command::MONITOR(ref c, Some(ref t)) => stringify("MONITOR", &[c, t], None),
-            Command::MONITOR(ref c, None) => stringify("MONITOR", &[c], None),
-            Command::BATCH(ref t, Some(ref c), Some(ref a)) => stringify(
-                "BATCH",
-                &vec![t, &c.to_str().to_owned()]
-                    .iter()
-                    .map(|s| &s[..])
-                    .chain(a.iter().map(|s| &s[..]))
-                    .collect::<Vec<_>>(),
-                None,
-            ),
-            Command::BATCH(ref t, Some(ref c), None) => stringify("BATCH", &[t, c.to_str()], None),
-            Command::BATCH(ref t, None, Some(ref a)) => stringify(
-                "BATCH",
-                &vec![t]
-                    .iter()
-                    .map(|s| &s[..])
-                    .chain(a.iter().map(|s| &s[..]))
-                    .collect::<Vec<_>>(),
-                None,
-            ),
-            Command::BATCH(ref t, None, None) => stringify("BATCH", &[t], None),
-            Command::CHGHOST(ref u, ref h) => stringify("CHGHOST", &[u, h], None),
+/// Gets the parameters of a standard reply. `FAIL`, `WARN` and `NOTE` are all the same variant, so
+/// which entry this is used by depends on the reply's kind rather than on the variant.
+fn standard_reply_params(cmd: &Command) -> Option<Params<'_>> {
+    match *cmd {
+        Command::StandardReply(ref reply) => {
+            let mut args = vec![&reply.command[..], &reply.code[..]];
+            args.extend(strs(&reply.context));
+            params(&args, Some(&reply.description))
+        }
+        _ => None,
+    }
+}
 
-            Command::Response(ref resp, ref a, Some(ref s)) => stringify(
-                &format!("{:03}", *resp as u16),
-                &a.iter().map(|s| &s[..]).collect::<Vec<_>>(),
-                Some(s),
-            ),
-            Command::Response(ref resp, ref a, None) => stringify(
+/// Borrows a list of owned strings.
+fn strs(list: &[String]) -> Vec<&str> {
+    list.iter().map(|s| &s[..]).collect()
+}
+
+/// Copies the parameter at the given index, if there is one.
+fn opt(p: &[&str], i: usize) -> Option<String> {
+    p.get(i).map(|&s| s.to_owned())
+}
+
+/// Copies the parameters from the given index onwards, if there are any.
+fn opt_list(p: &[&str], i: usize) -> Option<Vec<String>> {
+    p.get(i..).filter(|rest| !rest.is_empty()).map(list)
+}
+
+/// Copies all the parameters.
+fn list(p: &[&str]) -> Vec<String> {
+    p.iter().map(|&s| s.to_owned()).collect()
+}
+
+/// Writes a command with its parameters, the last of which is written as the suffix if it's at
+/// `suffix` or later, or if it's empty, contains a space or starts with a colon.
+fn stringify(cmd: &str, params: &[Cow<'_, str>], suffix: Option<usize>) -> String {
+    let mut line = cmd.to_owned();
+    for (i, arg) in params.iter().enumerate() {
+        let last = i + 1 == params.len();
+        let needs_colon = arg.is_empty() || arg.contains(' ') || arg.starts_with(':');
+        if last && (matches!(suffix, Some(suffix) if i >= suffix) || needs_colon) {
+            line.push_str(" :");
+        } else {
+            line.push(' ');
+        }
+        line.push_str(arg);
+    }
+    line
+}
+
+/// Borrows the arguments and suffix of a response or raw command as its parameters.
+fn raw_params<'a>(args: &'a [String], suffix: &'a Option<String>) -> Params<'a> {
+    args.iter()
+        .map(|s| &s[..])
+        .chain(suffix.as_deref())
+        .map(Cow::Borrowed)
+        .collect()
+}
+
+impl<'a> From<&'a Command> for String {
+    fn from(cmd: &'a Command) -> String {
+        match *cmd {
+            Command::Response(ref resp, ref a, ref s) => stringify(
                 &format!("{:03}", *resp as u16),
-                &a.iter().map(|s| &s[..]).collect::<Vec<_>>(),
-                None,
+                &raw_params(a, s),
+                s.as_ref().map(|_| a.len()),
             ),
-            Command::Raw(ref c, ref a, Some(ref s)) => {
-                stringify(c, &a.iter().map(|s| &s[..]).collect::<Vec<_>>(), Some(s))
+            Command::Raw(ref c, ref a, ref s) => {
+                stringify(c, &raw_params(a, s), s.as_ref().map(|_| a.len()))
             }
-            Command::Raw(ref c, ref a, None) => {
-                stringify(c, &a.iter().map(|s| &s[..]).collect::<Vec<_>>(), None)
+            _ => {
+                let spec = cmd
+                    .spec_name()
+                    .and_then(CommandSpec::find)
+                    .expect("command missing from the command table");
+                let params = (spec.serialize)(cmd).expect("command serialized by the wrong entry");
+                stringify(spec.name, &params, spec.suffix)
             }
         }
     }
 }
 
 impl Command {
+    /// Gets the name of the command's entry in the command table, or `None` for responses and raw
+    /// commands, which aren't in it.
+    fn spec_name(&self) -> Option<&str> {
+        Some(match *self {
+            Command::ACCOUNT(..) => "ACCOUNT",
+            Command::ADMIN(..) => "ADMIN",
+            Command::AUTHENTICATE(..) => "AUTHENTICATE",
+            Command::AWAY(..) => "AWAY",
+            Command::BATCH(..) => "BATCH",
+            Command::BOTSERV(..) => "BOTSERV",
+            Command::CAP(..) => "CAP",
+            Command::CHANSERV(..) => "CHANSERV",
+            Command::CHATHISTORY(..) => "CHATHISTORY",
+            Command::CHGHOST(..) => "CHGHOST",
+            Command::CONNECT(..) => "CONNECT",
+            Command::DIE => "DIE",
+            Command::ERROR(..) => "ERROR",
+            Command::HOSTSERV(..) => "HOSTSERV",
+            Command::INFO(..) => "INFO",
+            Command::INVITE(..) => "INVITE",
+            Command::ISON(..) => "ISON",
+            Command::JOIN(..) => "JOIN",
+            Command::KICK(..) => "KICK",
+            Command::KILL(..) => "KILL",
+            Command::LINKS(..) => "LINKS",
+            Command::LIST(..) => "LIST",
+            Command::LUSERS(..) => "LUSERS",
+            Command::MARKREAD(..) => "MARKREAD",
+            Command::MEMOSERV(..) => "MEMOSERV",
+            Command::METADATA(..) => "METADATA",
+            Command::UserMODE(..) | Command::ChannelMODE(..) => "MODE",
+            Command::MONITOR(..) => "MONITOR",
+            Command::MOTD(..) => "MOTD",
+            Command::NAMES(..) => "NAMES",
+            Command::NICK(..) => "NICK",
+            Command::NICKSERV(..) => "NICKSERV",
+            Command::NOTICE(..) => "NOTICE",
+            Command::OPER(..) => "OPER",
+            Command::OPERSERV(..) => "OPERSERV",
+            Command::PART(..) => "PART",
+            Command::PASS(..) => "PASS",
+            Command::PING(..) => "PING",
+            Command::PONG(..) => "PONG",
+            Command::PRIVMSG(..) => "PRIVMSG",
+            Command::QUIT(..) => "QUIT",
+            Command::REDACT(..) => "REDACT",
+            Command::REHASH => "REHASH",
+            Command::RESTART => "RESTART",
+            Command::SAJOIN(..) => "SAJOIN",
+            Command::SAMODE(..) => "SAMODE",
+            Command::SANICK(..) => "SANICK",
+            Command::SAPART(..) => "SAPART",
+            Command::SAQUIT(..) => "SAQUIT",
+            Command::SERVICE(..) => "SERVICE",
+            Command::SERVLIST(..) => "SERVLIST",
+            Command::SETNAME(..) => "SETNAME",
+            Command::SQUERY(..) => "SQUERY",
+            Command::SQUIT(..) => "SQUIT",
+            Command::STATS(..) => "STATS",
+            Command::SUMMON(..) => "SUMMON",
+            Command::TAGMSG(..) => "TAGMSG",
+            Command::TIME(..) => "TIME",
+            Command::TOPIC(..) => "TOPIC",
+            Command::TRACE(..) => "TRACE",
+            Command::USER(..) => "USER",
+            Command::USERHOST(..) => "USERHOST",
+            Command::USERS(..) => "USERS",
+            Command::VERSION(..) => "VERSION",
+            Command::WALLOPS(..) => "WALLOPS",
+            Command::WEBIRC(..) => "WEBIRC",
+            Command::WHO(..) => "WHO",
+            Command::WHOIS(..) => "WHOIS",
+            Command::WHOWAS(..) => "WHOWAS",
+            Command::StandardReply(ref r) => r.kind.to_str(),
+            Command::Response(..) | Command::Raw(..) => return None,
+        })
+    }
+
     /// Constructs a new Command.
-    #[allow(clippy::complexity)]
     pub fn new(
         cmd: &str,
         args: Vec<&str>,
         suffix: Option<&str>,
     ) -> Result<Command, MessageParseError> {
-        Ok(if cmd.eq_ignore_ascii_case("PASS") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::PASS(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::PASS(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("NICK") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::NICK(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::NICK(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("USER") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 3 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::USER(args[0].to_owned(), args[1].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 4 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::USER(args[0].to_owned(), args[1].to_owned(), args[3].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("OPER") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::OPER(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::OPER(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("MODE") {
-            match suffix {
-                Some(suffix) => raw(cmd, args, Some(suffix)),
-                None => {
-                    if args[0].is_channel_name() {
-                        let arg = args[1..].join(" ");
-                        Command::ChannelMODE(
-                            args[0].to_owned(),
-                            Mode::from_channel_mode_string(&arg)?,
-                        )
-                    } else {
-                        let arg = args[1..].join(" ");
-                        Command::UserMODE(args[0].to_owned(), Mode::from_user_mode_string(&arg)?)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("SERVICE") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 5 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::SERVICE(
-                            args[0].to_owned(),
-                            args[1].to_owned(),
-                            args[2].to_owned(),
-                            args[3].to_owned(),
-                            args[4].to_owned(),
-                            suffix.to_owned(),
-                        )
-                    }
-                }
-                None => {
-                    if args.len() != 6 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::SERVICE(
-                            args[0].to_owned(),
-                            args[1].to_owned(),
-                            args[2].to_owned(),
-                            args[3].to_owned(),
-                            args[4].to_owned(),
-                            args[5].to_owned(),
-                        )
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("QUIT") {
-            if !args.is_empty() {
-                raw(cmd, args, suffix)
-            } else {
-                match suffix {
-                    Some(suffix) => Command::QUIT(Some(suffix.to_owned())),
-                    None => Command::QUIT(None),
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("SQUIT") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::SQUIT(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::SQUIT(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("JOIN") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::JOIN(suffix.to_owned(), None, None)
-                    } else if args.len() == 1 {
-                        Command::JOIN(args[0].to_owned(), Some(suffix.to_owned()), None)
-                    } else if args.len() == 2 {
-                        Command::JOIN(
-                            args[0].to_owned(),
-                            Some(args[1].to_owned()),
-                            Some(suffix.to_owned()),
-                        )
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::JOIN(args[0].to_owned(), None, None)
-                    } else if args.len() == 2 {
-                        Command::JOIN(args[0].to_owned(), Some(args[1].to_owned()), None)
-                    } else if args.len() == 3 {
-                        Command::JOIN(
-                            args[0].to_owned(),
-                            Some(args[1].to_owned()),
-                            Some(args[2].to_owned()),
-                        )
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("PART") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::PART(suffix.to_owned(), None)
-                    } else if args.len() == 1 {
-                        Command::PART(args[0].to_owned(), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::PART(args[0].to_owned(), None)
-                    } else if args.len() == 2 {
-                        Command::PART(args[0].to_owned(), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("TOPIC") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::TOPIC(suffix.to_owned(), None)
-                    } else if args.len() == 1 {
-                        Command::TOPIC(args[0].to_owned(), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::TOPIC(args[0].to_owned(), None)
-                    } else if args.len() == 2 {
-                        Command::TOPIC(args[0].to_owned(), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("NAMES") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::NAMES(Some(suffix.to_owned()), None)
-                    } else if args.len() == 1 {
-                        Command::NAMES(Some(args[0].to_owned()), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.is_empty() {
-                        Command::NAMES(None, None)
-                    } else if args.len() == 1 {
-                        Command::NAMES(Some(args[0].to_owned()), None)
-                    } else if args.len() == 2 {
-                        Command::NAMES(Some(args[0].to_owned()), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("LIST") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::LIST(Some(suffix.to_owned()), None)
-                    } else if args.len() == 1 {
-                        Command::LIST(Some(args[0].to_owned()), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.is_empty() {
-                        Command::LIST(None, None)
-                    } else if args.len() == 1 {
-                        Command::LIST(Some(args[0].to_owned()), None)
-                    } else if args.len() == 2 {
-                        Command::LIST(Some(args[0].to_owned()), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("INVITE") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::INVITE(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::INVITE(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("KICK") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 2 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::KICK(
-                            args[0].to_owned(),
-                            args[1].to_owned(),
-                            Some(suffix.to_owned()),
-                        )
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::KICK(args[0].to_owned(), args[1].to_owned(), None)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("PRIVMSG") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::PRIVMSG(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => raw(cmd, args, suffix),
-            }
-        } else if cmd.eq_ignore_ascii_case("NOTICE") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::NOTICE(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => raw(cmd, args, suffix),
-            }
-        } else if cmd.eq_ignore_ascii_case("MOTD") {
-            if !args.is_empty() {
-                raw(cmd, args, suffix)
-            } else {
-                match suffix {
-                    Some(suffix) => Command::MOTD(Some(suffix.to_owned())),
-                    None => Command::MOTD(None),
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("LUSERS") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::LUSERS(Some(suffix.to_owned()), None)
-                    } else if args.len() == 1 {
-                        Command::LUSERS(Some(args[0].to_owned()), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.is_empty() {
-                        Command::LUSERS(None, None)
-                    } else if args.len() == 1 {
-                        Command::LUSERS(Some(args[0].to_owned()), None)
-                    } else if args.len() == 2 {
-                        Command::LUSERS(Some(args[0].to_owned()), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("VERSION") {
-            if !args.is_empty() {
-                raw(cmd, args, suffix)
-            } else {
-                match suffix {
-                    Some(suffix) => Command::VERSION(Some(suffix.to_owned())),
-                    None => Command::VERSION(None),
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("STATS") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::STATS(Some(suffix.to_owned()), None)
-                    } else if args.len() == 1 {
-                        Command::STATS(Some(args[0].to_owned()), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.is_empty() {
-                        Command::STATS(None, None)
-                    } else if args.len() == 1 {
-                        Command::STATS(Some(args[0].to_owned()), None)
-                    } else if args.len() == 2 {
-                        Command::STATS(Some(args[0].to_owned()), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("LINKS") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::LINKS(None, Some(suffix.to_owned()))
-                    } else if args.len() == 1 {
-                        Command::LINKS(Some(args[0].to_owned()), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.is_empty() {
-                        Command::LINKS(None, None)
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("TIME") {
-            if !args.is_empty() {
-                raw(cmd, args, suffix)
-            } else {
-                match suffix {
-                    Some(suffix) => Command::TIME(Some(suffix.to_owned())),
-                    None => Command::TIME(None),
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("CONNECT") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 2 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::CONNECT(
-                            args[0].to_owned(),
-                            args[1].to_owned(),
-                            Some(suffix.to_owned()),
-                        )
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::CONNECT(args[0].to_owned(), args[1].to_owned(), None)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("TRACE") {
-            if !args.is_empty() {
-                raw(cmd, args, suffix)
-            } else {
-                match suffix {
-                    Some(suffix) => Command::TRACE(Some(suffix.to_owned())),
-                    None => Command::TRACE(None),
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("ADMIN") {
-            if !args.is_empty() {
-                raw(cmd, args, suffix)
-            } else {
-                match suffix {
-                    Some(suffix) => Command::ADMIN(Some(suffix.to_owned())),
-                    None => Command::ADMIN(None),
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("INFO") {
-            if !args.is_empty() {
-                raw(cmd, args, suffix)
-            } else {
-                match suffix {
-                    Some(suffix) => Command::INFO(Some(suffix.to_owned())),
-                    None => Command::INFO(None),
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("SERVLIST") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::SERVLIST(Some(suffix.to_owned()), None)
-                    } else if args.len() == 1 {
-                        Command::SERVLIST(Some(args[0].to_owned()), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.is_empty() {
-                        Command::SERVLIST(None, None)
-                    } else if args.len() == 1 {
-                        Command::SERVLIST(Some(args[0].to_owned()), None)
-                    } else if args.len() == 2 {
-                        Command::SERVLIST(Some(args[0].to_owned()), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("SQUERY") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::SQUERY(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::SQUERY(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("WHO") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::WHO(Some(suffix.to_owned()), None)
-                    } else if args.len() == 1 {
-                        Command::WHO(Some(args[0].to_owned()), Some(&suffix[..] == "o"))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.is_empty() {
-                        Command::WHO(None, None)
-                    } else if args.len() == 1 {
-                        Command::WHO(Some(args[0].to_owned()), None)
-                    } else if args.len() == 2 {
-                        Command::WHO(Some(args[0].to_owned()), Some(&args[1][..] == "o"))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("WHOIS") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::WHOIS(None, suffix.to_owned())
-                    } else if args.len() == 1 {
-                        Command::WHOIS(Some(args[0].to_owned()), suffix.to_owned())
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::WHOIS(None, args[0].to_owned())
-                    } else if args.len() == 2 {
-                        Command::WHOIS(Some(args[0].to_owned()), args[1].to_owned())
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("WHOWAS") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::WHOWAS(suffix.to_owned(), None, None)
-                    } else if args.len() == 1 {
-                        Command::WHOWAS(args[0].to_owned(), None, Some(suffix.to_owned()))
-                    } else if args.len() == 2 {
-                        Command::WHOWAS(
-                            args[0].to_owned(),
-                            Some(args[1].to_owned()),
-                            Some(suffix.to_owned()),
-                        )
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::WHOWAS(args[0].to_owned(), None, None)
-                    } else if args.len() == 2 {
-                        Command::WHOWAS(args[0].to_owned(), None, Some(args[1].to_owned()))
-                    } else if args.len() == 3 {
-                        Command::WHOWAS(
-                            args[0].to_owned(),
-                            Some(args[1].to_owned()),
-                            Some(args[2].to_owned()),
-                        )
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("KILL") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::KILL(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::KILL(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("PING") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::PING(suffix.to_owned(), None)
-                    } else if args.len() == 1 {
-                        Command::PING(args[0].to_owned(), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::PING(args[0].to_owned(), None)
-                    } else if args.len() == 2 {
-                        Command::PING(args[0].to_owned(), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("PONG") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::PONG(suffix.to_owned(), None)
-                    } else if args.len() == 1 {
-                        Command::PONG(args[0].to_owned(), Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::PONG(args[0].to_owned(), None)
-                    } else if args.len() == 2 {
-                        Command::PONG(args[0].to_owned(), Some(args[1].to_owned()))
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("ERROR") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::ERROR(suffix.to_owned())
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => raw(cmd, args, suffix),
-            }
-        } else if cmd.eq_ignore_ascii_case("AWAY") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::AWAY(Some(suffix.to_owned()))
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => raw(cmd, args, suffix),
-            }
-        } else if cmd.eq_ignore_ascii_case("REHASH") {
-            if args.is_empty() {
-                Command::REHASH
-            } else {
-                raw(cmd, args, suffix)
-            }
-        } else if cmd.eq_ignore_ascii_case("DIE") {
-            if args.is_empty() {
-                Command::DIE
-            } else {
-                raw(cmd, args, suffix)
-            }
-        } else if cmd.eq_ignore_ascii_case("RESTART") {
-            if args.is_empty() {
-                Command::RESTART
-            } else {
-                raw(cmd, args, suffix)
-            }
-        } else if cmd.eq_ignore_ascii_case("SUMMON") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::SUMMON(suffix.to_owned(), None, None)
-                    } else if args.len() == 1 {
-                        Command::SUMMON(args[0].to_owned(), Some(suffix.to_owned()), None)
-                    } else if args.len() == 2 {
-                        Command::SUMMON(
-                            args[0].to_owned(),
-                            Some(args[1].to_owned()),
-                            Some(suffix.to_owned()),
-                        )
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::SUMMON(args[0].to_owned(), None, None)
-                    } else if args.len() == 2 {
-                        Command::SUMMON(args[0].to_owned(), Some(args[1].to_owned()), None)
-                    } else if args.len() == 3 {
-                        Command::SUMMON(
-                            args[0].to_owned(),
-                            Some(args[1].to_owned()),
-                            Some(args[2].to_owned()),
-                        )
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("USERS") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::USERS(Some(suffix.to_owned()))
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::USERS(Some(args[0].to_owned()))
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("WALLOPS") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::WALLOPS(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::WALLOPS(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("USERHOST") {
-            if suffix.is_none() {
-                Command::USERHOST(args.into_iter().map(|s| s.to_owned()).collect())
-            } else {
-                raw(cmd, args, suffix)
-            }
-        } else if cmd.eq_ignore_ascii_case("ISON") {
-            if suffix.is_none() {
-                Command::USERHOST(args.into_iter().map(|s| s.to_owned()).collect())
-            } else {
-                raw(cmd, args, suffix)
-            }
-        } else if cmd.eq_ignore_ascii_case("SAJOIN") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::SAJOIN(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::SAJOIN(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("SAMODE") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() == 1 {
-                        Command::SAMODE(args[0].to_owned(), suffix.to_owned(), None)
-                    } else if args.len() == 2 {
-                        Command::SAMODE(
-                            args[0].to_owned(),
-                            args[1].to_owned(),
-                            Some(suffix.to_owned()),
-                        )
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 2 {
-                        Command::SAMODE(args[0].to_owned(), args[1].to_owned(), None)
-                    } else if args.len() == 3 {
-                        Command::SAMODE(
-                            args[0].to_owned(),
-                            args[1].to_owned(),
-                            Some(args[2].to_owned()),
-                        )
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("SANICK") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::SANICK(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::SANICK(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("SAPART") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::SAPART(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::SAPART(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("SAQUIT") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() != 1 {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::SAQUIT(args[0].to_owned(), suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 2 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::SAQUIT(args[0].to_owned(), args[1].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("NICKSERV") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::NICKSERV(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::NICKSERV(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("CHANSERV") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::CHANSERV(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::CHANSERV(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("OPERSERV") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::OPERSERV(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::OPERSERV(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("BOTSERV") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::BOTSERV(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::BOTSERV(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("HOSTSERV") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::HOSTSERV(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::HOSTSERV(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("MEMOSERV") {
-            match suffix {
-                Some(suffix) => {
-                    if !args.is_empty() {
-                        raw(cmd, args, Some(suffix))
-                    } else {
-                        Command::MEMOSERV(suffix.to_owned())
-                    }
-                }
-                None => {
-                    if args.len() != 1 {
-                        raw(cmd, args, suffix)
-                    } else {
-                        Command::MEMOSERV(args[0].to_owned())
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("CAP") {
-            if args.len() == 1 {
-                if let Ok(cmd) = args[0].parse() {
-                    match suffix {
-                        Some(suffix) => Command::CAP(None, cmd, None, Some(suffix.to_owned())),
-                        None => Command::CAP(None, cmd, None, None),
-                    }
-                } else {
-                    raw(cmd, args, suffix)
-                }
-            } else if args.len() == 2 {
-                if let Ok(cmd) = args[0].parse() {
-                    match suffix {
-                        Some(suffix) => Command::CAP(
-                            None,
-                            cmd,
-                            Some(args[1].to_owned()),
-                            Some(suffix.to_owned()),
-                        ),
-                        None => Command::CAP(None, cmd, Some(args[1].to_owned()), None),
-                    }
-                } else if let Ok(cmd) = args[1].parse() {
-                    match suffix {
-                        Some(suffix) => Command::CAP(
-                            Some(args[0].to_owned()),
-                            cmd,
-                            None,
-                            Some(suffix.to_owned()),
-                        ),
-                        None => Command::CAP(Some(args[0].to_owned()), cmd, None, None),
-                    }
-                } else {
-                    raw(cmd, args, suffix)
-                }
-            } else if args.len() == 3 {
-                if let Ok(cmd) = args[1].parse() {
-                    match suffix {
-                        Some(suffix) => Command::CAP(
-                            Some(args[0].to_owned()),
-                            cmd,
-                            Some(args[2].to_owned()),
-                            Some(suffix.to_owned()),
-                        ),
-                        None => Command::CAP(
-                            Some(args[0].to_owned()),
-                            cmd,
-                            Some(args[2].to_owned()),
-                            None,
-                        ),
-                    }
-                } else {
-                    raw(cmd, args, suffix)
-                }
-            } else {
-                raw(cmd, args, suffix)
-            }
-        } else if cmd.eq_ignore_ascii_case("AUTHENTICATE") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::AUTHENTICATE(suffix.to_owned())
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::AUTHENTICATE(args[0].to_owned())
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("ACCOUNT") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::ACCOUNT(suffix.to_owned())
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::ACCOUNT(args[0].to_owned())
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("METADATA") {
-            if args.len() == 2 {
-                match suffix {
-                    Some(_) => raw(cmd, args, suffix),
-                    None => match args[1].parse() {
-                        Ok(c) => Command::METADATA(args[0].to_owned(), Some(c), None, None),
-                        Err(_) => raw(cmd, args, suffix),
-                    },
-                }
-            } else if args.len() > 2 {
-                match args[1].parse() {
-                    Ok(c) => Command::METADATA(
-                        args[0].to_owned(),
-                        Some(c),
-                        Some(args.into_iter().skip(1).map(|s| s.to_owned()).collect()),
+        let spec = match CommandSpec::find(cmd) {
+            Some(spec) => spec,
+            None => {
+                return Ok(match cmd.parse() {
+                    Ok(resp) => Command::Response(
+                        resp,
+                        args.into_iter().map(|s| s.to_owned()).collect(),
                         suffix.map(|s| s.to_owned()),
                     ),
-                    Err(_) => {
-                        if args.len() == 3 && suffix.is_some() {
-                            Command::METADATA(
-                                args[0].to_owned(),
-                                None,
-                                Some(args.into_iter().skip(1).map(|s| s.to_owned()).collect()),
-                                suffix.map(|s| s.to_owned()),
-                            )
-                        } else {
-                            raw(cmd, args, suffix)
-                        }
-                    }
-                }
-            } else {
-                raw(cmd, args, suffix)
-            }
-        } else if cmd.eq_ignore_ascii_case("MONITOR") {
-            if args.len() == 1 {
-                Command::MONITOR(args[0].to_owned(), suffix.map(|s| s.to_owned()))
-            } else {
-                raw(cmd, args, suffix)
-            }
-        } else if cmd.eq_ignore_ascii_case("BATCH") {
-            match suffix {
-                Some(suffix) => {
-                    if args.is_empty() {
-                        Command::BATCH(suffix.to_owned(), None, None)
-                    } else if args.len() == 1 {
-                        Command::BATCH(args[0].to_owned(), Some(suffix.parse().unwrap()), None)
-                    } else if args.len() > 1 {
-                        Command::BATCH(
-                            args[0].to_owned(),
-                            Some(args[1].parse().unwrap()),
-                            Some(
-                                vec![suffix.to_owned()]
-                                    .into_iter()
-                                    .chain(args.into_iter().skip(2).map(|s| s.to_owned()))
-                                    .collect(),
-                            ),
-                        )
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 1 {
-                        Command::BATCH(args[0].to_owned(), None, None)
-                    } else if args.len() == 2 {
-                        Command::BATCH(args[0].to_owned(), Some(args[1].parse().unwrap()), None)
-                    } else if args.len() > 2 {
-                        Command::BATCH(
-                            args[0].to_owned(),
-                            Some(args[1].parse().unwrap()),
-                            Some(args.iter().skip(2).map(|&s| s.to_owned()).collect()),
-                        )
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
-            }
-        } else if cmd.eq_ignore_ascii_case("CHGHOST") {
-            match suffix {
-                Some(suffix) => {
-                    if args.len() == 1 {
-                        Command::CHGHOST(args[0].to_owned(), suffix.to_owned())
-                    } else {
-                        raw(cmd, args, Some(suffix))
-                    }
-                }
-                None => {
-                    if args.len() == 2 {
-                        Command::CHGHOST(args[0].to_owned(), args[1].to_owned())
-                    } else {
-                        raw(cmd, args, suffix)
-                    }
-                }
+                    Err(_) => raw(cmd, args, suffix),
+                })
             }
-        } else if let Ok(resp) = cmd.parse() {
-            Command::Response(
-                resp,
-                args.into_iter().map(|s| s.to_owned()).collect(),
-                suffix.map(|s| s.to_owned()),
-            )
-        } else {
-            raw(cmd, args, suffix)
-        })
+        };
+
+        // the suffix is just the last parameter, written so that it can contain spaces
+        let params: Vec<&str> = args.iter().cloned().chain(suffix).collect();
+        if params.len() < spec.min || params.len() > spec.max {
+            return Ok(raw(cmd, args, suffix));
+        }
+        // mode strings that only make sense with the server's `CHANMODES` are also kept raw
+        match (spec.parse)(&params) {
+            Err(MessageParseError::InvalidSubcommand { .. })
+            | Err(MessageParseError::InvalidModeString { .. }) => Ok(raw(cmd, args, suffix)),
+            result => result,
+        }
     }
 }
 
@@ -1744,6 +1530,63 @@ impl FromStr for MetadataSubCommand {
     }
 }
 
+/// A list of all the subcommands for the
+/// [chathistory extension](https://ircv3.net/specs/extensions/chathistory).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatHistorySubCommand {
+    /// Requests the most recent messages.
+    LATEST,
+    /// Requests messages before a timestamp or message ID.
+    BEFORE,
+    /// Requests messages after a timestamp or message ID.
+    AFTER,
+    /// Requests messages around a timestamp or message ID.
+    AROUND,
+    /// Requests messages between two timestamps or message IDs.
+    BETWEEN,
+    /// Requests the targets with messages between two timestamps, or lists one in a reply.
+    TARGETS,
+}
+
+impl ChatHistorySubCommand {
+    /// Gets the string that corresponds to this subcommand.
+    pub fn to_str(&self) -> &str {
+        match *self {
+            ChatHistorySubCommand::LATEST => "LATEST",
+            ChatHistorySubCommand::BEFORE => "BEFORE",
+            ChatHistorySubCommand::AFTER => "AFTER",
+            ChatHistorySubCommand::AROUND => "AROUND",
+            ChatHistorySubCommand::BETWEEN => "BETWEEN",
+            ChatHistorySubCommand::TARGETS => "TARGETS",
+        }
+    }
+}
+
+impl FromStr for ChatHistorySubCommand {
+    type Err = MessageParseError;
+
+    fn from_str(s: &str) -> Result<ChatHistorySubCommand, Self::Err> {
+        if s.eq_ignore_ascii_case("LATEST") {
+            Ok(ChatHistorySubCommand::LATEST)
+        } else if s.eq_ignore_ascii_case("BEFORE") {
+            Ok(ChatHistorySubCommand::BEFORE)
+        } else if s.eq_ignore_ascii_case("AFTER") {
+            Ok(ChatHistorySubCommand::AFTER)
+        } else if s.eq_ignore_ascii_case("AROUND") {
+            Ok(ChatHistorySubCommand::AROUND)
+        } else if s.eq_ignore_ascii_case("BETWEEN") {
+            Ok(ChatHistorySubCommand::BETWEEN)
+        } else if s.eq_ignore_ascii_case("TARGETS") {
+            Ok(ChatHistorySubCommand::TARGETS)
+        } else {
+            Err(MessageParseError::InvalidSubcommand {
+                cmd: "CHATHISTORY",
+                sub: s.to_owned(),
+            })
+        }
+    }
+}
+
 /// [batch extension](http://ircv3.net/specs/extensions/batch-3.2.html).
 #[derive(Clone, Debug, PartialEq)]
 pub enum BatchSubCommand {
@@ -1782,9 +1625,32 @@ impl FromStr for BatchSubCommand {
 
 #[cfg(test)]
 mod test {
-    use super::Command;
     use super::Response;
-    use crate::proto::Message;
+    use super::{
+        BatchSubCommand, CapSubCommand, ChatHistorySubCommand, Command, MetadataSubCommand,
+        COMMANDS,
+    };
+    use crate::proto::{ChannelMode, Message, Mode, StandardReply, StandardReplyKind, UserMode};
+
+    fn s(s: &str) -> String {
+        s.to_owned()
+    }
+
+    fn reply(
+        kind: StandardReplyKind,
+        command: &str,
+        code: &str,
+        context: &[&str],
+        description: &str,
+    ) -> StandardReply {
+        StandardReply {
+            kind,
+            command: s(command),
+            code: s(code),
+            context: context.iter().map(|c| s(c)).collect(),
+            description: s(description),
+        }
+    }
 
     #[test]
     fn format_response() {
@@ -1813,4 +1679,338 @@ mod test {
             cmd
         );
     }
+
+    #[test]
+    fn table_is_sorted() {
+        for pair in COMMANDS.windows(2) {
+            assert!(
+                pair[0].name < pair[1].name,
+                "{} is out of order",
+                pair[1].name
+            );
+        }
+    }
+
+    #[test]
+    fn serialize_suffix() {
+        use self::Command::*;
+
+        let lines = vec![
+            (PART(s("#a"), None), "PART #a"),
+            (PART(s("#a"), Some(s("bye"))), "PART #a :bye"),
+            (PRIVMSG(s("#a"), s("hi")), "PRIVMSG #a :hi"),
+            (WHOIS(None, s("nick")), "WHOIS nick"),
+            // parameters that couldn't be read back otherwise are always written as the suffix
+            (ACCOUNT(s("")), "ACCOUNT :"),
+            (MONITOR(s("+"), Some(s(":odd"))), "MONITOR + ::odd"),
+            (Raw(s("X"), vec![s("a"), s("b c")], None), "X a :b c"),
+            (Raw(s("X"), vec![s("a")], Some(s("b"))), "X a :b"),
+        ];
+        for (cmd, line) in lines {
+            assert_eq!(String::from(&cmd), line);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        use self::Command::*;
+
+        let commands = vec![
+            PASS(s("password")),
+            NICK(s("nick")),
+            USER(s("user"), s("8"), s("Real Name")),
+            OPER(s("name"), s("password")),
+            UserMODE(s("nick"), vec![]),
+            UserMODE(
+                s("nick"),
+                vec![
+                    Mode::Plus(UserMode::Invisible, None),
+                    Mode::Minus(UserMode::Wallops, None),
+                ],
+            ),
+            ChannelMODE(
+                s("#channel"),
+                vec![
+                    Mode::Plus(ChannelMode::Oper, Some(s("nick"))),
+                    Mode::Minus(ChannelMode::Voice, Some(s("other"))),
+                    Mode::Plus(ChannelMode::Moderated, None),
+                ],
+            ),
+            SERVICE(
+                s("dict"),
+                s("*"),
+                s("*.fr"),
+                s("0"),
+                s("0"),
+                s("French dictionary"),
+            ),
+            QUIT(None),
+            QUIT(Some(s("Gone to lunch"))),
+            SQUIT(s("tolsun.oulu.fi"), s("Bad link")),
+            JOIN(s("#channel"), None, None),
+            JOIN(s("#channel"), Some(s("key")), None),
+            JOIN(s("#channel"), Some(s("account")), Some(s("Real Name"))),
+            PART(s("#channel"), None),
+            PART(s("#channel"), Some(s("Goodbye"))),
+            TOPIC(s("#channel"), None),
+            TOPIC(s("#channel"), Some(s("New topic"))),
+            NAMES(None, None),
+            NAMES(Some(s("#channel")), None),
+            NAMES(Some(s("#channel")), Some(s("server"))),
+            LIST(None, None),
+            LIST(Some(s("#channel")), None),
+            LIST(Some(s("#channel")), Some(s("server"))),
+            INVITE(s("nick"), s("#channel")),
+            KICK(s("#channel"), s("nick"), None),
+            KICK(s("#channel"), s("nick"), Some(s("Go away"))),
+            PRIVMSG(s("#channel"), s("Hello, world!")),
+            NOTICE(s("nick"), s("Hello, world!")),
+            MOTD(None),
+            MOTD(Some(s("server"))),
+            LUSERS(None, None),
+            LUSERS(Some(s("mask")), None),
+            LUSERS(Some(s("mask")), Some(s("server"))),
+            VERSION(None),
+            VERSION(Some(s("server"))),
+            STATS(None, None),
+            STATS(Some(s("m")), None),
+            STATS(Some(s("m")), Some(s("server"))),
+            LINKS(None, None),
+            LINKS(None, Some(s("*.au"))),
+            LINKS(Some(s("*.edu")), Some(s("*.bu.edu"))),
+            TIME(None),
+            TIME(Some(s("server"))),
+            CONNECT(s("tolsun.oulu.fi"), s("6667"), None),
+            CONNECT(s("tolsun.oulu.fi"), s("6667"), Some(s("remote"))),
+            TRACE(None),
+            TRACE(Some(s("server"))),
+            ADMIN(None),
+            ADMIN(Some(s("server"))),
+            INFO(None),
+            INFO(Some(s("server"))),
+            SERVLIST(None, None),
+            SERVLIST(Some(s("*SERV")), None),
+            SERVLIST(Some(s("*SERV")), Some(s("0"))),
+            SQUERY(s("irchelp"), s("HELP privmsg")),
+            WHO(None, None),
+            WHO(Some(s("*.fi")), None),
+            WHO(Some(s("jto*")), Some(true)),
+            WHOIS(None, s("nick")),
+            WHOIS(Some(s("server")), s("nick")),
+            WHOWAS(s("nick"), None, None),
+            WHOWAS(s("nick"), Some(s("9")), None),
+            WHOWAS(s("nick"), Some(s("9")), Some(s("server"))),
+            KILL(s("nick"), s("Spamming")),
+            PING(s("server"), None),
+            PING(s("server"), Some(s("other"))),
+            PONG(s("server"), None),
+            PONG(s("server"), Some(s("other"))),
+            ERROR(s("Closing link")),
+            AWAY(None),
+            AWAY(Some(s("Out to lunch"))),
+            REHASH,
+            DIE,
+            RESTART,
+            SUMMON(s("user"), None, None),
+            SUMMON(s("user"), Some(s("server")), None),
+            SUMMON(s("user"), Some(s("server")), Some(s("#channel"))),
+            USERS(None),
+            USERS(Some(s("server"))),
+            WALLOPS(s("Server restarting")),
+            USERHOST(vec![]),
+            USERHOST(vec![s("nick"), s("other")]),
+            ISON(vec![s("nick"), s("other")]),
+            SAJOIN(s("nick"), s("#channel")),
+            SAMODE(s("#channel"), s("+m"), None),
+            SAMODE(s("#channel"), s("+o"), Some(s("nick"))),
+            SANICK(s("old"), s("new")),
+            SAPART(s("nick"), s("Parted")),
+            SAQUIT(s("nick"), s("Quit")),
+            NICKSERV(s("IDENTIFY password")),
+            CHANSERV(s("OP #channel")),
+            OPERSERV(s("HELP")),
+            BOTSERV(s("HELP")),
+            HOSTSERV(s("ON")),
+            MEMOSERV(s("LIST")),
+            CAP(None, CapSubCommand::END, None, None),
+            CAP(None, CapSubCommand::LS, None, Some(s("302"))),
+            CAP(None, CapSubCommand::REQ, None, Some(s("multi-prefix sasl"))),
+            CAP(
+                Some(s("*")),
+                CapSubCommand::LS,
+                None,
+                Some(s("multi-prefix sasl")),
+            ),
+            CAP(
+                Some(s("nick")),
+                CapSubCommand::LS,
+                Some(s("*")),
+                Some(s("sasl")),
+            ),
+            AUTHENTICATE(s("PLAIN")),
+            ACCOUNT(s("account")),
+            METADATA(s("nick"), Some(MetadataSubCommand::LIST), None, None),
+            METADATA(
+                s("nick"),
+                Some(MetadataSubCommand::GET),
+                None,
+                Some(s("url")),
+            ),
+            METADATA(
+                s("nick"),
+                Some(MetadataSubCommand::SET),
+                Some(vec![s("url")]),
+                Some(s("http://example.com")),
+            ),
+            MONITOR(s("+"), Some(s("nick,other"))),
+            MONITOR(s("L"), None),
+            BATCH(s("+ref"), None, None),
+            BATCH(s("-ref"), None, None),
+            BATCH(s("+ref"), Some(BatchSubCommand::NETSPLIT), None),
+            BATCH(
+                s("+ref"),
+                Some(BatchSubCommand::NETJOIN),
+                Some(vec![s("irc.hub.other.net"), s("irc.link.net")]),
+            ),
+            BATCH(
+                s("+ref"),
+                Some(BatchSubCommand::CUSTOM(s("CHATHISTORY"))),
+                Some(vec![s("#channel")]),
+            ),
+            CHGHOST(s("user"), s("host")),
+            TAGMSG(s("#channel")),
+            SETNAME(s("New Name")),
+            REDACT(s("#channel"), s("msgid"), None),
+            REDACT(s("#channel"), s("msgid"), Some(s("Spam"))),
+            MARKREAD(s("#channel"), None),
+            MARKREAD(s("#channel"), Some(s("timestamp=2019-01-04T14:33:26.123Z"))),
+            CHATHISTORY(
+                ChatHistorySubCommand::LATEST,
+                vec![s("#channel"), s("*"), s("50")],
+            ),
+            CHATHISTORY(
+                ChatHistorySubCommand::BETWEEN,
+                vec![s("#channel"), s("msgid=a"), s("msgid=b"), s("50")],
+            ),
+            StandardReply(reply(
+                StandardReplyKind::Fail,
+                "ACC",
+                "REG_INVALID_CALLBACK",
+                &[],
+                "Invalid callback",
+            )),
+            StandardReply(reply(
+                StandardReplyKind::Warn,
+                "REHASH",
+                "CERTS_EXPIRED",
+                &[],
+                "Certificates expired",
+            )),
+            StandardReply(reply(
+                StandardReplyKind::Note,
+                "PRIVMSG",
+                "OPERATION_NOTE",
+                &["#channel", "x"],
+                "Something happened",
+            )),
+            WEBIRC(s("password"), s("gateway"), s("host"), s("127.0.0.1"), None),
+            WEBIRC(
+                s("password"),
+                s("gateway"),
+                s("host"),
+                s("127.0.0.1"),
+                Some(s("secure")),
+            ),
+            Response(
+                super::Response::RPL_WELCOME,
+                vec![s("nick")],
+                Some(s("Welcome!")),
+            ),
+            Raw(s("UNKNOWN"), vec![s("a"), s("b")], Some(s("c d"))),
+        ];
+
+        for cmd in &commands {
+            let line = String::from(cmd);
+            let parsed = line.parse::<Message>().unwrap().command;
+            assert_eq!(&parsed, cmd, "{} did not round trip", line);
+        }
+        for spec in COMMANDS {
+            assert!(
+                commands.iter().any(|cmd| (spec.serialize)(cmd).is_some()),
+                "{} is not covered",
+                spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn parse_ignores_case() {
+        let cmd = "privmsg #channel :hi".parse::<Message>().unwrap().command;
+        assert_eq!(cmd, Command::PRIVMSG(s("#channel"), s("hi")));
+    }
+
+    #[test]
+    fn parse_without_suffix() {
+        let cmd = "PRIVMSG #channel hi".parse::<Message>().unwrap().command;
+        assert_eq!(cmd, Command::PRIVMSG(s("#channel"), s("hi")));
+    }
+
+    #[test]
+    fn parse_wrong_arity_as_raw() {
+        let cmd = "PRIVMSG #channel".parse::<Message>().unwrap().command;
+        assert_eq!(cmd, Command::Raw(s("PRIVMSG"), vec![s("#channel")], None));
+        let cmd = "KICK #channel a b :c".parse::<Message>().unwrap().command;
+        assert_eq!(
+            cmd,
+            Command::Raw(s("KICK"), vec![s("#channel"), s("a"), s("b")], Some(s("c")))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_subcommand_as_raw() {
+        let cmd = "CAP * FOO :bar".parse::<Message>().unwrap().command;
+        assert_eq!(
+            cmd,
+            Command::Raw(s("CAP"), vec![s("*"), s("FOO")], Some(s("bar")))
+        );
+    }
+
+    #[test]
+    fn parse_mode_string() {
+        let cmd = "MODE #channel +o-v+m nick other"
+            .parse::<Message>()
+            .unwrap()
+            .command;
+        assert_eq!(
+            cmd,
+            Command::ChannelMODE(
+                s("#channel"),
+                vec![
+                    Mode::Plus(ChannelMode::Oper, Some(s("nick"))),
+                    Mode::Minus(ChannelMode::Voice, Some(s("other"))),
+                    Mode::Plus(ChannelMode::Moderated, None),
+                ]
+            )
+        );
+        let cmd = "MODE #channel +l-l 10".parse::<Message>().unwrap().command;
+        assert_eq!(
+            cmd,
+            Command::ChannelMODE(
+                s("#channel"),
+                vec![
+                    Mode::Plus(ChannelMode::Limit, Some(s("10"))),
+                    Mode::Minus(ChannelMode::Limit, None),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn parse_unknown_mode_argument_as_raw() {
+        let cmd = "MODE #channel +f 10:5".parse::<Message>().unwrap().command;
+        assert_eq!(
+            cmd,
+            Command::Raw(s("MODE"), vec![s("#channel"), s("+f"), s("10:5")], None)
+        );
+    }
 }
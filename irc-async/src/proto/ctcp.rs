@@ -0,0 +1,171 @@
+//! The [Client-to-Client Protocol](https://modern.ircdocs.horse/ctcp.html), carried in the text of
+//! `PRIVMSG`s and `NOTICE`s.
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use crate::proto::{Command, MessageParseError};
+
+/// The byte that starts and ends a CTCP message.
+const DELIMITER: char = '\x01';
+
+/// A CTCP message. Queries are sent in a `PRIVMSG`, and replies in a `NOTICE`.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// let command = Command::PRIVMSG("#rust".to_owned(), "\x01ACTION waves\x01".to_owned());
+/// assert_eq!(Ctcp::from_command(&command), Some(Ctcp::Action("waves".to_owned())));
+/// assert_eq!(
+///     Ctcp::Version(None).reply("nick"),
+///     Command::NOTICE("nick".to_owned(), "\x01VERSION\x01".to_owned())
+/// );
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ctcp {
+    /// `ACTION`: a message describing what the sender is doing, like `/me waves`.
+    Action(String),
+    /// `VERSION`: asks for the name and version of the client, or answers with them.
+    Version(Option<String>),
+    /// `PING`: asks for the parameters to be echoed back, to measure lag.
+    Ping(Option<String>),
+    /// `TIME`: asks for the local time of the client, or answers with it.
+    Time(Option<String>),
+    /// `CLIENTINFO`: asks for the CTCP messages the client supports, or answers with them.
+    ClientInfo(Option<String>),
+    /// Any other CTCP message, with its command in uppercase.
+    Other(String, Option<String>),
+}
+
+impl Ctcp {
+    /// Creates a CTCP message from its command and parameters.
+    pub fn new(command: &str, params: Option<String>) -> Ctcp {
+        let command = command.to_uppercase();
+        match (&command[..], params) {
+            ("ACTION", params) => Ctcp::Action(params.unwrap_or_default()),
+            ("VERSION", params) => Ctcp::Version(params),
+            ("PING", params) => Ctcp::Ping(params),
+            ("TIME", params) => Ctcp::Time(params),
+            ("CLIENTINFO", params) => Ctcp::ClientInfo(params),
+            (_, params) => Ctcp::Other(command, params),
+        }
+    }
+
+    /// Gets the command of the message.
+    pub fn command(&self) -> &str {
+        match *self {
+            Ctcp::Action(_) => "ACTION",
+            Ctcp::Version(_) => "VERSION",
+            Ctcp::Ping(_) => "PING",
+            Ctcp::Time(_) => "TIME",
+            Ctcp::ClientInfo(_) => "CLIENTINFO",
+            Ctcp::Other(ref command, _) => command,
+        }
+    }
+
+    /// Gets the parameters of the message, if any.
+    pub fn params(&self) -> Option<&str> {
+        match *self {
+            Ctcp::Action(ref text) => Some(text),
+            Ctcp::Version(ref params)
+            | Ctcp::Ping(ref params)
+            | Ctcp::Time(ref params)
+            | Ctcp::ClientInfo(ref params)
+            | Ctcp::Other(_, ref params) => params.as_deref(),
+        }
+    }
+
+    /// Parses the CTCP message in the text of a `PRIVMSG` or `NOTICE`, if it has one.
+    pub fn from_command(command: &Command) -> Option<Ctcp> {
+        match *command {
+            Command::PRIVMSG(_, ref text) | Command::NOTICE(_, ref text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Builds a `PRIVMSG` sending this message as a query to the target.
+    pub fn query(&self, target: &str) -> Command {
+        Command::PRIVMSG(target.to_owned(), self.to_string())
+    }
+
+    /// Builds a `NOTICE` sending this message as a reply to the target.
+    pub fn reply(&self, target: &str) -> Command {
+        Command::NOTICE(target.to_owned(), self.to_string())
+    }
+}
+
+impl FromStr for Ctcp {
+    type Err = MessageParseError;
+
+    fn from_str(s: &str) -> Result<Ctcp, Self::Err> {
+        let body = s
+            .strip_prefix(DELIMITER)
+            .ok_or(MessageParseError::InvalidCommand)?;
+        // some clients leave off the closing delimiter
+        let body = body.strip_suffix(DELIMITER).unwrap_or(body);
+        let mut parts = body.splitn(2, ' ');
+        let command = match parts.next() {
+            Some(command) if !command.is_empty() => command,
+            _ => return Err(MessageParseError::InvalidCommand),
+        };
+        Ok(Ctcp::new(command, parts.next().map(str::to_owned)))
+    }
+}
+
+impl Display for Ctcp {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self.params() {
+            Some(params) => write!(f, "{}{} {}{}", DELIMITER, self.command(), params, DELIMITER),
+            None => write!(f, "{}{}{}", DELIMITER, self.command(), DELIMITER),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ctcp;
+    use crate::proto::Command;
+
+    #[test]
+    fn parse() {
+        let cases = vec![
+            ("\x01ACTION waves\x01", Ctcp::Action("waves".to_owned())),
+            ("\x01action  waves \x01", Ctcp::Action(" waves ".to_owned())),
+            ("\x01VERSION\x01", Ctcp::Version(None)),
+            ("\x01PING 1234", Ctcp::Ping(Some("1234".to_owned()))),
+            ("\x01TIME\x01", Ctcp::Time(None)),
+            (
+                "\x01DCC SEND file 1 2 3\x01",
+                Ctcp::Other("DCC".to_owned(), Some("SEND file 1 2 3".to_owned())),
+            ),
+        ];
+        for (text, ctcp) in cases {
+            assert_eq!(text.parse::<Ctcp>().unwrap(), ctcp);
+        }
+        assert!("hello".parse::<Ctcp>().is_err());
+        assert!("\x01\x01".parse::<Ctcp>().is_err());
+    }
+
+    #[test]
+    fn to_string() {
+        assert_eq!(
+            Ctcp::Action("waves".to_owned()).to_string(),
+            "\x01ACTION waves\x01"
+        );
+        assert_eq!(
+            Ctcp::ClientInfo(Some("ACTION PING".to_owned())).to_string(),
+            "\x01CLIENTINFO ACTION PING\x01"
+        );
+        assert_eq!(Ctcp::Ping(None).to_string(), "\x01PING\x01");
+    }
+
+    #[test]
+    fn commands() {
+        let ping = Ctcp::Ping(Some("42".to_owned()));
+        assert_eq!(Ctcp::from_command(&ping.query("nick")), Some(ping.clone()));
+        assert_eq!(Ctcp::from_command(&ping.reply("nick")), Some(ping));
+        let command = Command::PRIVMSG("#a".to_owned(), "not ctcp".to_owned());
+        assert_eq!(Ctcp::from_command(&command), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! [Direct Client-to-Client](https://modern.ircdocs.horse/dcc.html) offers, carried in CTCP `DCC`
+//! messages.
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use crate::proto::{Ctcp, MessageParseError};
+
+/// A `DCC` request, asking for or answering a direct connection between two clients.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # use std::net::Ipv4Addr;
+/// # fn main() {
+/// let ctcp: Ctcp = "\x01DCC SEND \"my file.txt\" 2130706433 5000 1024\x01".parse().unwrap();
+/// let offer = Dcc::from_ctcp(&ctcp).unwrap();
+/// assert_eq!(
+///     offer,
+///     Dcc::Send {
+///         filename: "my file.txt".to_owned(),
+///         address: Ipv4Addr::LOCALHOST.into(),
+///         port: 5000,
+///         size: Some(1024),
+///         token: None,
+///     }
+/// );
+/// assert_eq!(
+///     offer.resume(512).unwrap().to_string(),
+///     "RESUME \"my file.txt\" 5000 512"
+/// );
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dcc {
+    /// An offer to send a file, to be downloaded by connecting to the address and port.
+    Send {
+        /// The name of the file.
+        filename: String,
+        /// The address to connect to.
+        address: IpAddr,
+        /// The port to connect to, or 0 if the sender asks to be connected to instead.
+        port: u16,
+        /// The size of the file in bytes, if the sender gave it.
+        size: Option<u64>,
+        /// The token identifying a reverse offer, where the port is 0.
+        token: Option<String>,
+    },
+    /// An offer to chat, by connecting to the address and port.
+    Chat {
+        /// The address to connect to.
+        address: IpAddr,
+        /// The port to connect to.
+        port: u16,
+    },
+    /// Asks the sender of a file to resume sending it at a position.
+    Resume {
+        /// The name of the file.
+        filename: String,
+        /// The port of the offer being resumed.
+        port: u16,
+        /// The byte offset to resume at.
+        position: u64,
+        /// The token of the offer being resumed, if it had one.
+        token: Option<String>,
+    },
+    /// Agrees to resume sending a file at a position.
+    Accept {
+        /// The name of the file.
+        filename: String,
+        /// The port of the offer being resumed.
+        port: u16,
+        /// The byte offset to resume at.
+        position: u64,
+        /// The token of the offer being resumed, if it had one.
+        token: Option<String>,
+    },
+}
+
+impl Dcc {
+    /// Parses the `DCC` request in a CTCP message, if it is one.
+    pub fn from_ctcp(ctcp: &Ctcp) -> Option<Dcc> {
+        match *ctcp {
+            Ctcp::Other(ref command, Some(ref params)) if command == "DCC" => params.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Builds the CTCP message carrying this request.
+    pub fn to_ctcp(&self) -> Ctcp {
+        Ctcp::Other("DCC".to_owned(), Some(self.to_string()))
+    }
+
+    /// Builds the request to resume this offer at a position, if it is an offer to send a file.
+    pub fn resume(&self, position: u64) -> Option<Dcc> {
+        match *self {
+            Dcc::Send {
+                ref filename,
+                port,
+                ref token,
+                ..
+            } => Some(Dcc::Resume {
+                filename: filename.clone(),
+                port,
+                position,
+                token: token.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Builds the answer agreeing to this request to resume, if it is one.
+    pub fn accept(&self) -> Option<Dcc> {
+        match *self {
+            Dcc::Resume {
+                ref filename,
+                port,
+                position,
+                ref token,
+            } => Some(Dcc::Accept {
+                filename: filename.clone(),
+                port,
+                position,
+                token: token.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Dcc {
+    type Err = MessageParseError;
+
+    fn from_str(s: &str) -> Result<Dcc, Self::Err> {
+        let args = split_args(s);
+        let invalid = || MessageParseError::InvalidSubcommand {
+            cmd: "DCC",
+            sub: s.to_owned(),
+        };
+        let arg = |i: usize| args.get(i).map(String::as_str).ok_or_else(invalid);
+        let number = |i: usize| arg(i)?.parse::<u64>().map_err(|_| invalid());
+        let port = |i: usize| arg(i)?.parse::<u16>().map_err(|_| invalid());
+        let address = |i: usize| parse_address(arg(i)?).ok_or_else(invalid);
+        let token = |i: usize| args.get(i).cloned();
+
+        match &arg(0)?.to_uppercase()[..] {
+            "SEND" => Ok(Dcc::Send {
+                filename: arg(1)?.to_owned(),
+                address: address(2)?,
+                port: port(3)?,
+                size: number(4).ok(),
+                token: token(5),
+            }),
+            "CHAT" => Ok(Dcc::Chat {
+                address: address(2)?,
+                port: port(3)?,
+            }),
+            "RESUME" => Ok(Dcc::Resume {
+                filename: arg(1)?.to_owned(),
+                port: port(2)?,
+                position: number(3)?,
+                token: token(4),
+            }),
+            "ACCEPT" => Ok(Dcc::Accept {
+                filename: arg(1)?.to_owned(),
+                port: port(2)?,
+                position: number(3)?,
+                token: token(4),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for Dcc {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            Dcc::Send {
+                ref filename,
+                address,
+                port,
+                size,
+                ref token,
+            } => {
+                write!(
+                    f,
+                    "SEND {} {} {}",
+                    quote(filename),
+                    format_address(address),
+                    port
+                )?;
+                // the token can only follow a size
+                match (size, token) {
+                    (Some(size), Some(token)) => write!(f, " {} {}", size, token),
+                    (None, Some(token)) => write!(f, " 0 {}", token),
+                    (Some(size), None) => write!(f, " {}", size),
+                    (None, None) => Ok(()),
+                }
+            }
+            Dcc::Chat { address, port } => {
+                write!(f, "CHAT chat {} {}", format_address(address), port)
+            }
+            Dcc::Resume {
+                ref filename,
+                port,
+                position,
+                ref token,
+            } => {
+                write!(f, "RESUME {} {} {}", quote(filename), port, position)?;
+                write_token(f, token)
+            }
+            Dcc::Accept {
+                ref filename,
+                port,
+                position,
+                ref token,
+            } => {
+                write!(f, "ACCEPT {} {} {}", quote(filename), port, position)?;
+                write_token(f, token)
+            }
+        }
+    }
+}
+
+fn write_token(f: &mut Formatter, token: &Option<String>) -> FmtResult {
+    match *token {
+        Some(ref token) => write!(f, " {}", token),
+        None => Ok(()),
+    }
+}
+
+/// Splits the parameters of a `DCC` request on spaces, keeping quoted filenames whole.
+fn split_args(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let (arg, next) = match rest.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => match rest.find(' ') {
+                Some(end) => (&rest[..end], &rest[end..]),
+                None => (rest, ""),
+            },
+        };
+        args.push(arg.to_owned());
+        rest = next.trim_start();
+    }
+    args
+}
+
+/// Quotes a filename if it has spaces.
+fn quote(filename: &str) -> String {
+    if filename.contains(' ') {
+        format!("\"{}\"", filename)
+    } else {
+        filename.to_owned()
+    }
+}
+
+/// Parses an address, which is an IPv4 address as a decimal number, or an IPv6 address as usual.
+fn parse_address(s: &str) -> Option<IpAddr> {
+    match s.parse::<u32>() {
+        Ok(ip) => Some(Ipv4Addr::from(ip).into()),
+        Err(_) => s.parse().ok(),
+    }
+}
+
+fn format_address(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(ip) => u32::from(ip).to_string(),
+        IpAddr::V6(ip) => ip.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::Dcc;
+    use crate::proto::Ctcp;
+
+    #[test]
+    fn parse() {
+        let localhost = IpAddr::from(Ipv4Addr::LOCALHOST);
+        let cases = vec![
+            (
+                "SEND file.txt 2130706433 5000 1024",
+                Dcc::Send {
+                    filename: "file.txt".to_owned(),
+                    address: localhost,
+                    port: 5000,
+                    size: Some(1024),
+                    token: None,
+                },
+            ),
+            (
+                "SEND \"a b.txt\" ::1 0 1024 17",
+                Dcc::Send {
+                    filename: "a b.txt".to_owned(),
+                    address: Ipv6Addr::LOCALHOST.into(),
+                    port: 0,
+                    size: Some(1024),
+                    token: Some("17".to_owned()),
+                },
+            ),
+            (
+                "CHAT chat 2130706433 5000",
+                Dcc::Chat {
+                    address: localhost,
+                    port: 5000,
+                },
+            ),
+            (
+                "ACCEPT file.txt 5000 512",
+                Dcc::Accept {
+                    filename: "file.txt".to_owned(),
+                    port: 5000,
+                    position: 512,
+                    token: None,
+                },
+            ),
+        ];
+        for (params, dcc) in cases {
+            assert_eq!(params.parse::<Dcc>().unwrap(), dcc);
+            assert_eq!(dcc.to_string(), params);
+        }
+        assert!("SEND file.txt".parse::<Dcc>().is_err());
+        assert!("SEND file.txt localhost 5000".parse::<Dcc>().is_err());
+        assert!("GET file.txt".parse::<Dcc>().is_err());
+    }
+
+    #[test]
+    fn without_size() {
+        let dcc: Dcc = "SEND file.txt 2130706433 5000".parse().unwrap();
+        match dcc {
+            Dcc::Send { size: None, .. } => (),
+            dcc => panic!("unexpected {:?}", dcc),
+        }
+    }
+
+    #[test]
+    fn ctcp() {
+        let ctcp: Ctcp = "\x01DCC RESUME file.txt 5000 512\x01".parse().unwrap();
+        let resume = Dcc::from_ctcp(&ctcp).unwrap();
+        assert_eq!(resume.to_ctcp(), ctcp);
+        assert_eq!(
+            resume.accept().unwrap().to_string(),
+            "ACCEPT file.txt 5000 512"
+        );
+        assert_eq!(Dcc::from_ctcp(&Ctcp::Version(None)), None);
+    }
+}
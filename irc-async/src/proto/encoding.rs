@@ -0,0 +1,124 @@
+//! Decoding IRC traffic that isn't UTF-8.
+use std::borrow::Cow;
+use std::str::FromStr;
+
+/// The characters bytes `0x80` to `0x9F` stand for in CP1252. The bytes CP1252 leaves undefined
+/// stand for the C1 control characters, like in Latin-1, so that every byte can be decoded.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// A text encoding to decode lines with when they aren't valid UTF-8.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// assert_eq!(Encoding::Latin1.decode(b"caf\xe9"), "café");
+/// assert_eq!(Encoding::Windows1252.decode(b"\x93hi\x94"), "\u{201C}hi\u{201D}");
+/// assert_eq!(Encoding::Utf8.decode(b"caf\xe9"), "caf\u{FFFD}");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+    /// UTF-8, replacing invalid sequences with U+FFFD.
+    Utf8,
+    /// ISO-8859-1, where each byte is the code point of the same value.
+    Latin1,
+    /// Windows-1252, a superset of the printable characters of Latin-1. The most common legacy
+    /// encoding on IRC, and the default.
+    #[default]
+    Windows1252,
+}
+
+impl Encoding {
+    /// Gets the name of the encoding.
+    pub fn to_str(&self) -> &str {
+        match *self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Latin1 => "iso-8859-1",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// Decodes bytes as text in this encoding.
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, str> {
+        match *self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes),
+            Encoding::Latin1 => Cow::Owned(bytes.iter().map(|&b| char::from(b)).collect()),
+            Encoding::Windows1252 => Cow::Owned(
+                bytes
+                    .iter()
+                    .map(|&b| match b {
+                        0x80..=0x9F => CP1252_HIGH[usize::from(b - 0x80)],
+                        b => char::from(b),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = UnknownEncoding;
+
+    fn from_str(s: &str) -> Result<Encoding, Self::Err> {
+        match &s.to_ascii_lowercase()[..] {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "iso-8859-1" | "latin1" | "latin-1" => Ok(Encoding::Latin1),
+            "windows-1252" | "cp1252" => Ok(Encoding::Windows1252),
+            _ => Err(UnknownEncoding(s.to_owned())),
+        }
+    }
+}
+
+/// The error for an encoding name that isn't supported.
+#[derive(Debug, Error)]
+#[error("unknown encoding: {0}")]
+pub struct UnknownEncoding(pub String);
+
+/// Decodes a line as UTF-8, or in the fallback encoding if it isn't valid UTF-8.
+pub fn decode_line(bytes: &[u8], fallback: Encoding) -> Cow<'_, str> {
+    match std::str::from_utf8(bytes) {
+        Ok(line) => Cow::Borrowed(line),
+        Err(_) => fallback.decode(bytes),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_line, Encoding};
+
+    #[test]
+    fn decode() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for encoding in &[Encoding::Latin1, Encoding::Windows1252] {
+            // every byte decodes to a distinct character
+            let text = encoding.decode(&bytes);
+            let mut chars: Vec<char> = text.chars().collect();
+            assert_eq!(chars.len(), 256);
+            chars.sort();
+            chars.dedup();
+            assert_eq!(chars.len(), 256, "{} is lossy", encoding.to_str());
+        }
+        assert_eq!(Encoding::Windows1252.decode(b"\x80\x81"), "\u{20AC}\u{81}");
+    }
+
+    #[test]
+    fn fallback() {
+        assert_eq!(decode_line("héllo".as_bytes(), Encoding::Latin1), "héllo");
+        assert_eq!(decode_line(b"h\xe9llo", Encoding::Latin1), "héllo");
+        assert_eq!(decode_line(b"h\xe9llo", Encoding::Utf8), "h\u{FFFD}llo");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("UTF-8".parse::<Encoding>().unwrap(), Encoding::Utf8);
+        assert_eq!("latin1".parse::<Encoding>().unwrap(), Encoding::Latin1);
+        assert_eq!("CP1252".parse::<Encoding>().unwrap(), Encoding::Windows1252);
+        assert!("koi8-r".parse::<Encoding>().is_err());
+    }
+}
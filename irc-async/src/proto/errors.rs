@@ -1,4 +1,5 @@
 use std::io;
+use std::str::Utf8Error;
 
 use tokio_util::codec::LinesCodecError;
 
@@ -73,6 +74,15 @@ pub enum IrcError {
     /// Failed to encode or decode a line
     #[error("line codec failed: {0}")]
     Codec(#[from] LinesCodecError),
+
+    /// A line was longer than the server allows.
+    #[error("line of {len} bytes is longer than the limit of {max}")]
+    LineTooLong {
+        /// The length of the line, or of the part received so far.
+        len: usize,
+        /// The maximum length of a line.
+        max: usize,
+    },
     // /// All specified nicknames were in use or unusable.
     // #[error("none of the specified nicknames were usable")]
     // NoUsableNick,
@@ -100,6 +110,13 @@ pub enum ModeParseError {
     /// Missing modifier used in a mode string.
     #[error("missing mode modifier")]
     MissingModeModifier,
+
+    /// An argument left over after every mode got its argument.
+    #[error("unexpected mode argument: {arg}")]
+    UnexpectedArgument {
+        /// The unexpected argument.
+        arg: String,
+    },
 }
 
 /// Errors that occur when parsing messages.
@@ -113,6 +130,10 @@ pub enum MessageParseError {
     #[error("invalid command")]
     InvalidCommand,
 
+    /// The message was not valid UTF-8.
+    #[error("invalid utf-8: {0}")]
+    InvalidUtf8(#[from] Utf8Error),
+
     /// The mode string was malformed.
     #[error("invalid mode string: {string}")]
     InvalidModeString {
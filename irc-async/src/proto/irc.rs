@@ -1,17 +1,59 @@
 //! Implementation of IRC codec for Tokio.
-use bytes::BytesMut;
-use tokio_util::codec::{Decoder, Encoder, LinesCodec};
+use std::borrow::Cow;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-use super::errors::IrcError;
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::encoding::{decode_line, Encoding};
+use super::errors::{IrcError, MessageParseError};
 use super::message::Message;
+use super::message_ref::MessageRef;
+
+/// The maximum length of a line, including the trailing `\r\n` but not the tags, unless the server
+/// advertises another with `LINELEN`.
+pub const DEFAULT_LINE_LEN: usize = 512;
+
+/// The maximum length of the tags a server may send before a line, including the `@` and the
+/// space after them.
+const MAX_TAGS_LEN: usize = 8191;
 
-/// An IRC codec built around an inner codec.
-#[derive(Default)]
+/// An IRC codec, which splits lines on bytes and decodes each one as UTF-8, or in a fallback
+/// encoding if it isn't valid UTF-8. Outgoing lines are always UTF-8.
 pub struct IrcCodec {
-    inner: LinesCodec,
+    fallback: Encoding,
+    line_len: Arc<AtomicUsize>,
+    /// How far into the buffer we've already looked for a newline.
+    next_index: usize,
+    /// Whether we're skipping the rest of a line that was too long.
+    discarding: bool,
+}
+
+impl Default for IrcCodec {
+    fn default() -> Self {
+        IrcCodec::new(Encoding::default())
+    }
 }
 
 impl IrcCodec {
+    /// Creates a codec that decodes lines that aren't valid UTF-8 with the fallback encoding.
+    pub fn new(fallback: Encoding) -> Self {
+        IrcCodec {
+            fallback,
+            line_len: Arc::new(AtomicUsize::new(DEFAULT_LINE_LEN)),
+            next_index: 0,
+            discarding: false,
+        }
+    }
+
+    /// Gets a handle to the maximum line length, which can be changed while the codec is in use,
+    /// such as once the server advertises `LINELEN`.
+    pub fn line_len(&self) -> Arc<AtomicUsize> {
+        self.line_len.clone()
+    }
+
     /// Sanitizes the input string by cutting up to (and including) the first occurence of a line
     /// terminiating phrase (`\r\n`, `\r`, or `\n`). This is used in sending messages back to
     /// prevent the injection of additional commands.
@@ -26,6 +68,58 @@ impl IrcCodec {
         }
         data
     }
+
+    /// Splits the next line off the buffer, decoded as UTF-8 but not parsed yet, so that it can
+    /// be parsed in place with `Line::message`. This is the same as `decode` without allocating
+    /// an owned `Message`, and without copying the line unless it's in the fallback encoding.
+    pub fn decode_line(&mut self, src: &mut BytesMut) -> Result<Option<Line>, IrcError> {
+        let max = MAX_TAGS_LEN + self.line_len.load(Ordering::Relaxed);
+        loop {
+            let newline = src[self.next_index..].iter().position(|&b| b == b'\n');
+            let end = match newline {
+                Some(offset) => self.next_index + offset,
+                None if src.len() > max && !self.discarding => {
+                    // skip the rest of the line, rather than buffering without limit
+                    let len = src.len();
+                    src.clear();
+                    self.next_index = 0;
+                    self.discarding = true;
+                    return Err(IrcError::LineTooLong { len, max });
+                }
+                None => {
+                    if self.discarding {
+                        src.clear();
+                    }
+                    self.next_index = src.len();
+                    return Ok(None);
+                }
+            };
+            self.next_index = 0;
+            let mut line = src.split_to(end + 1);
+            if self.discarding {
+                self.discarding = false;
+                continue;
+            }
+            line.truncate(strip_line_ending(&line).len());
+            if line.is_empty() {
+                continue;
+            }
+            if line.len() > max {
+                return Err(IrcError::LineTooLong {
+                    len: line.len(),
+                    max,
+                });
+            }
+            let decoded = match decode_line(&line, self.fallback) {
+                Cow::Borrowed(_) => None,
+                Cow::Owned(text) => Some(text),
+            };
+            return Ok(Some(Line(match decoded {
+                Some(text) => Bytes::from(text),
+                None => line.freeze(),
+            })));
+        }
+    }
 }
 
 impl Decoder for IrcCodec {
@@ -33,12 +127,10 @@ impl Decoder for IrcCodec {
     type Error = IrcError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, Self::Error> {
-        let result = self
-            .inner
-            .decode(src)
-            .map_err(IrcError::from)
-            .and_then(|res| res.map_or(Ok(None), |msg| msg.parse::<Message>().map(Some)));
-        result
+        match self.decode_line(src)? {
+            Some(line) => line.as_str().parse().map(Some),
+            None => Ok(None),
+        }
     }
 }
 
@@ -47,8 +139,141 @@ impl Encoder for IrcCodec {
     type Error = IrcError;
 
     fn encode(&mut self, msg: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        self.inner
-            .encode(IrcCodec::sanitize(msg.to_string()), dst)
-            .map_err(IrcError::from)
+        let line = IrcCodec::sanitize(msg.to_string());
+        let line = strip_line_ending(line.as_bytes());
+        check_line_len(line, self.line_len.load(Ordering::Relaxed))?;
+        dst.reserve(line.len() + 2);
+        dst.put_slice(line);
+        dst.put_slice(b"\r\n");
+        Ok(())
+    }
+}
+
+/// A line split off by `IrcCodec::decode_line`. It shares the buffer it was read into, unless it
+/// had to be converted from the fallback encoding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line(Bytes);
+
+impl Line {
+    /// Gets the line, without its line ending.
+    pub fn as_str(&self) -> &str {
+        str::from_utf8(&self.0).expect("lines are decoded to UTF-8")
+    }
+
+    /// Parses the line in place.
+    pub fn message(&self) -> Result<MessageRef<'_>, MessageParseError> {
+        MessageRef::parse(self.as_str())
+    }
+}
+
+/// Strips the `\r\n` or `\n` from the end of a line.
+/// Checks that a message fits in `max` bytes when encoded, so it can be turned down before it's
+/// sent, such as before waiting on the replies to it.
+pub(crate) fn check_len(msg: &Message, max: usize) -> Result<(), IrcError> {
+    let line = IrcCodec::sanitize(msg.to_string());
+    check_line_len(strip_line_ending(line.as_bytes()), max)
+}
+
+/// Checks that a line, without its line ending, fits in `max` bytes along with the line ending.
+fn check_line_len(line: &[u8], max: usize) -> Result<(), IrcError> {
+    // the limit doesn't count the tags
+    let body = match line.first() {
+        Some(b'@') => line
+            .iter()
+            .position(|&b| b == b' ')
+            .map_or(line, |space| &line[space + 1..]),
+        _ => line,
+    };
+    if body.len() + 2 > max {
+        return Err(IrcError::LineTooLong {
+            len: body.len() + 2,
+            max,
+        });
+    }
+    Ok(())
+}
+
+fn strip_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::IrcCodec;
+    use crate::proto::{Command, Encoding, IrcError, Message};
+
+    #[test]
+    fn decode_fallback() {
+        let mut codec = IrcCodec::new(Encoding::Latin1);
+        let mut buf = BytesMut::from(&b"PRIVMSG #a :caf\xe9\r\nPRIVMSG #a :caf\xc3\xa9\nPRIV"[..]);
+        let privmsg = |text: &str| Command::PRIVMSG("#a".to_owned(), text.to_owned());
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().command,
+            privmsg("café")
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().command,
+            privmsg("café")
+        );
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.extend_from_slice(b"MSG #a :b\r\n");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().command,
+            privmsg("b")
+        );
+    }
+
+    #[test]
+    fn decode_line() {
+        let mut codec = IrcCodec::new(Encoding::Latin1);
+        let mut buf = BytesMut::from(&b"@a=b :n!u@h PRIVMSG #a :caf\xe9\r\nPING x\n\r\nPO"[..]);
+        let line = codec.decode_line(&mut buf).unwrap().unwrap();
+        let message = line.message().unwrap();
+        assert_eq!(message.tags().collect::<Vec<_>>(), vec![("a", Some("b"))]);
+        assert_eq!(message.prefix(), Some("n!u@h"));
+        assert_eq!(message.command(), "PRIVMSG");
+        assert_eq!(message.args().collect::<Vec<_>>(), vec!["#a"]);
+        assert_eq!(message.suffix(), Some("café"));
+        // empty lines are skipped
+        let line = codec.decode_line(&mut buf).unwrap().unwrap();
+        assert_eq!(line.as_str(), "PING x");
+        assert!(codec.decode_line(&mut buf).unwrap().is_none());
+        assert_eq!(&buf[..], b"PO");
+    }
+
+    #[test]
+    fn decode_too_long() {
+        let mut codec = IrcCodec::default();
+        codec.line_len().store(20, Ordering::Relaxed);
+        let mut buf = BytesMut::from(&vec![b'a'; 9000][..]);
+        match codec.decode(&mut buf) {
+            Err(IrcError::LineTooLong { len: 9000, .. }) => (),
+            result => panic!("unexpected {:?}", result),
+        }
+        // the rest of the long line is skipped
+        buf.extend_from_slice(b"aaa\r\nPING :x\r\n");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().command,
+            Command::PING("x".to_owned(), None)
+        );
+    }
+
+    #[test]
+    fn encode() {
+        let mut codec = IrcCodec::default();
+        let mut buf = BytesMut::new();
+        let message = Message::from(Command::PRIVMSG("#a".to_owned(), "é\r\nQUIT".to_owned()));
+        codec.encode(message, &mut buf).unwrap();
+        assert_eq!(&buf[..], "PRIVMSG #a :é\r\n".as_bytes());
+
+        codec.line_len().store(16, Ordering::Relaxed);
+        let message = Message::from(Command::PRIVMSG("#a".to_owned(), "long".to_owned()));
+        assert!(codec.encode(message, &mut buf).is_err());
     }
 }
@@ -0,0 +1,282 @@
+//! The features a server advertises in [RPL_ISUPPORT](https://modern.ircdocs.horse/#rplisupport-005).
+use std::collections::HashMap;
+
+use crate::proto::irc::DEFAULT_LINE_LEN;
+use crate::proto::CaseMapping;
+
+/// The channel modes of RFC 2812, assumed until the server advertises its own.
+const DEFAULT_CHANMODES: &str = "beI,k,l,imnpst";
+
+/// The membership prefixes of RFC 2812, assumed until the server advertises its own.
+const DEFAULT_PREFIX: &str = "(ov)@+";
+
+/// How a channel mode takes an argument, as advertised in `CHANMODES` and `PREFIX`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelModeKind {
+    /// Type A: a mode that adds or removes an address to or from a list, like bans.
+    List,
+    /// Type B: a mode that always takes an argument, like the channel key.
+    Always,
+    /// Type C: a mode that only takes an argument when it is set, like the user limit.
+    WhenSet,
+    /// Type D: a mode that never takes an argument, like moderation.
+    Never,
+    /// A mode that gives a member a prefix, like `@` for operators. Always takes a nick.
+    Prefix,
+}
+
+/// The parameters a server advertised in `RPL_ISUPPORT`.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// let mut isupport = ISupport::default();
+/// isupport.update(&["NETWORK=Example\\x20Net", "PREFIX=(qov)~@+", "SAFELIST"]);
+/// assert_eq!(isupport.get("NETWORK"), Some("Example Net"));
+/// assert!(isupport.contains("SAFELIST"));
+/// assert_eq!(isupport.prefix_mode('~'), Some('q'));
+/// assert_eq!(isupport.channel_mode_kind('q'), Some(ChannelModeKind::Prefix));
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ISupport {
+    /// The advertised parameters, with their values, if any.
+    params: HashMap<String, Option<String>>,
+
+    /// The modes of each type in `CHANMODES`, from A to D.
+    chanmodes: [Vec<char>; 4],
+
+    /// The modes in `PREFIX`, with their prefixes, from the highest rank to the lowest.
+    prefix: Vec<(char, char)>,
+}
+
+impl Default for ISupport {
+    fn default() -> Self {
+        ISupport {
+            params: HashMap::new(),
+            chanmodes: parse_chanmodes(DEFAULT_CHANMODES),
+            prefix: parse_prefix(DEFAULT_PREFIX),
+        }
+    }
+}
+
+impl ISupport {
+    /// Updates the parameters from the tokens of an `RPL_ISUPPORT` reply, without the leading nick
+    /// and the trailing "are supported by this server".
+    pub fn update<S: AsRef<str>>(&mut self, tokens: &[S]) {
+        for token in tokens {
+            let token = token.as_ref();
+            // a leading `-` means the server no longer supports the parameter
+            if let Some(key) = token.strip_prefix('-') {
+                let _ = self.params.remove(key);
+                match key {
+                    "CHANMODES" => self.chanmodes = parse_chanmodes(DEFAULT_CHANMODES),
+                    "PREFIX" => self.prefix = parse_prefix(DEFAULT_PREFIX),
+                    _ => (),
+                }
+                continue;
+            }
+
+            let mut parts = token.splitn(2, '=');
+            let key = parts.next().unwrap_or(token);
+            let value = parts.next().map(unescape);
+            match (key, &value) {
+                ("CHANMODES", Some(value)) => self.chanmodes = parse_chanmodes(value),
+                ("PREFIX", Some(value)) => self.prefix = parse_prefix(value),
+                _ => (),
+            }
+            let _ = self.params.insert(key.to_owned(), value);
+        }
+    }
+
+    /// Returns true if the server advertised the parameter.
+    pub fn contains(&self, key: &str) -> bool {
+        self.params.contains_key(key)
+    }
+
+    /// Gets the value of a parameter, if the server advertised it with one.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params.get(key)?.as_deref()
+    }
+
+    /// Gets the case mapping the server compares nicks and channel names with, or the default if
+    /// it didn't advertise a known one.
+    pub fn case_mapping(&self) -> CaseMapping {
+        self.get("CASEMAPPING")
+            .and_then(|mapping| mapping.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Gets the maximum length of a line, including the trailing `\r\n` but not the tags.
+    pub fn line_len(&self) -> usize {
+        self.get("LINELEN")
+            .and_then(|len| len.parse().ok())
+            .unwrap_or(DEFAULT_LINE_LEN)
+    }
+
+    /// Gets how a channel mode takes an argument, if the server advertised the mode.
+    pub fn channel_mode_kind(&self, mode: char) -> Option<ChannelModeKind> {
+        if self.prefix.iter().any(|&(m, _)| m == mode) {
+            return Some(ChannelModeKind::Prefix);
+        }
+        let kinds = [
+            ChannelModeKind::List,
+            ChannelModeKind::Always,
+            ChannelModeKind::WhenSet,
+            ChannelModeKind::Never,
+        ];
+        self.chanmodes
+            .iter()
+            .zip(&kinds)
+            .find(|(modes, _)| modes.contains(&mode))
+            .map(|(_, &kind)| kind)
+    }
+
+    /// Gets the membership modes and their prefixes, from the highest rank to the lowest.
+    pub fn prefixes(&self) -> &[(char, char)] {
+        &self.prefix
+    }
+
+    /// Gets the membership mode a prefix stands for.
+    pub fn prefix_mode(&self, prefix: char) -> Option<char> {
+        self.prefix
+            .iter()
+            .find(|&&(_, p)| p == prefix)
+            .map(|&(mode, _)| mode)
+    }
+
+    /// Gets the prefix of a membership mode.
+    pub fn mode_prefix(&self, mode: char) -> Option<char> {
+        self.prefix
+            .iter()
+            .find(|&&(m, _)| m == mode)
+            .map(|&(_, prefix)| prefix)
+    }
+
+    /// Gets the rank of a membership mode, where 0 is the highest.
+    pub fn prefix_rank(&self, mode: char) -> Option<usize> {
+        self.prefix.iter().position(|&(m, _)| m == mode)
+    }
+}
+
+/// Parses the value of `CHANMODES`, like `beI,k,l,imnpst`.
+fn parse_chanmodes(value: &str) -> [Vec<char>; 4] {
+    let mut groups = value.split(',').map(|group| group.chars().collect());
+    // servers may add more types, which we can't make sense of
+    [
+        groups.next().unwrap_or_default(),
+        groups.next().unwrap_or_default(),
+        groups.next().unwrap_or_default(),
+        groups.next().unwrap_or_default(),
+    ]
+}
+
+/// Parses the value of `PREFIX`, like `(ov)@+`.
+fn parse_prefix(value: &str) -> Vec<(char, char)> {
+    let value = value.strip_prefix('(').unwrap_or(value);
+    let mut parts = value.splitn(2, ')');
+    let modes = parts.next().unwrap_or("");
+    let prefixes = parts.next().unwrap_or("");
+    modes.chars().zip(prefixes.chars()).collect()
+}
+
+/// Unescapes the `\xHH` escapes in a parameter value.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find("\\x") {
+        result.push_str(&rest[..index]);
+        rest = &rest[index..];
+        match rest
+            .get(2..4)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            Some(byte) => {
+                result.push(char::from(byte));
+                rest = &rest[4..];
+            }
+            None => {
+                result.push('\\');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChannelModeKind, ISupport};
+    use crate::proto::CaseMapping;
+
+    #[test]
+    fn defaults() {
+        let isupport = ISupport::default();
+        assert_eq!(isupport.channel_mode_kind('b'), Some(ChannelModeKind::List));
+        assert_eq!(
+            isupport.channel_mode_kind('k'),
+            Some(ChannelModeKind::Always)
+        );
+        assert_eq!(
+            isupport.channel_mode_kind('l'),
+            Some(ChannelModeKind::WhenSet)
+        );
+        assert_eq!(
+            isupport.channel_mode_kind('m'),
+            Some(ChannelModeKind::Never)
+        );
+        assert_eq!(
+            isupport.channel_mode_kind('o'),
+            Some(ChannelModeKind::Prefix)
+        );
+        assert_eq!(isupport.channel_mode_kind('f'), None);
+        assert_eq!(isupport.prefixes(), &[('o', '@'), ('v', '+')]);
+    }
+
+    #[test]
+    fn update_and_remove() {
+        let mut isupport = ISupport::default();
+        isupport.update(&[
+            "CHANMODES=beIq,k,fl,imnpstj",
+            "PREFIX=(Yqaohv)!~&@%+",
+            "EXCEPTS",
+        ]);
+        assert_eq!(
+            isupport.channel_mode_kind('q'),
+            Some(ChannelModeKind::Prefix)
+        );
+        assert_eq!(
+            isupport.channel_mode_kind('f'),
+            Some(ChannelModeKind::WhenSet)
+        );
+        assert_eq!(
+            isupport.channel_mode_kind('j'),
+            Some(ChannelModeKind::Never)
+        );
+        assert_eq!(isupport.prefix_mode('%'), Some('h'));
+        assert_eq!(isupport.mode_prefix('Y'), Some('!'));
+        assert_eq!(isupport.prefix_rank('v'), Some(5));
+        assert!(isupport.contains("EXCEPTS"));
+        assert_eq!(isupport.get("EXCEPTS"), None);
+        assert_eq!(isupport.case_mapping(), CaseMapping::Rfc1459);
+
+        isupport.update(&["CASEMAPPING=ascii"]);
+        assert_eq!(isupport.case_mapping(), CaseMapping::Ascii);
+
+        assert_eq!(isupport.line_len(), 512);
+        isupport.update(&["LINELEN=2048"]);
+        assert_eq!(isupport.line_len(), 2048);
+
+        isupport.update(&["-CHANMODES", "-EXCEPTS"]);
+        assert_eq!(isupport.channel_mode_kind('f'), None);
+        assert!(!isupport.contains("EXCEPTS"));
+    }
+
+    #[test]
+    fn unescape() {
+        assert_eq!(super::unescape("a\\x20b\\x3Dc"), "a b=c");
+        assert_eq!(super::unescape("a\\xZZ\\"), "a\\xZZ\\");
+    }
+}
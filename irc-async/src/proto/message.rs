@@ -3,7 +3,7 @@ use std::borrow::ToOwned;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::str::FromStr;
 
-use crate::proto::{ChannelExt, Command};
+use crate::proto::{ChannelExt, Command, MessageRef, Source};
 use crate::proto::{IrcError, MessageParseError};
 
 /// A data structure representing an IRC message according to the protocol specification. It
@@ -18,7 +18,7 @@ pub struct Message {
     /// in IRCv3 extensions to the IRC protocol.
     pub tags: Option<Vec<Tag>>,
     /// The message prefix (or source) as defined by [RFC 2812](http://tools.ietf.org/html/rfc2812).
-    pub prefix: Option<String>,
+    pub prefix: Option<Source>,
     /// The IRC command, parsed according to the known specifications. The command itself and its
     /// arguments (including the special suffix argument) are captured in this component.
     pub command: Command,
@@ -57,7 +57,7 @@ impl Message {
     ) -> Result<Message, MessageParseError> {
         Ok(Message {
             tags,
-            prefix: prefix.map(|s| s.to_owned()),
+            prefix: prefix.map(Source::from),
             command: Command::new(command, args, suffix)?,
         })
     }
@@ -75,18 +75,7 @@ impl Message {
     /// # }
     /// ```
     pub fn source_nickname(&self) -> Option<&str> {
-        // <prefix> ::= <servername> | <nick> [ '!' <user> ] [ '@' <host> ]
-        // <servername> ::= <host>
-        self.prefix.as_ref().and_then(|s| match (
-            s.find('!'),
-            s.find('@'),
-            s.find('.'),
-        ) {
-            (Some(i), _, _) | // <nick> '!' <user> [ '@' <host> ]
-            (None, Some(i), _) => Some(&s[..i]), // <nick> '@' <host>
-            (None, None, None) => Some(s), // <nick>
-            _ => None, // <servername>
-        })
+        self.prefix.as_ref()?.nick()
     }
 
     /// Gets the likely intended place to respond to this message.
@@ -144,7 +133,7 @@ impl Message {
         }
         if let Some(ref prefix) = self.prefix {
             ret.push(':');
-            ret.push_str(prefix);
+            ret.push_str(&prefix.to_string());
             ret.push(' ');
         }
         let cmd: String = From::from(&self.command);
@@ -168,90 +157,12 @@ impl FromStr for Message {
     type Err = IrcError;
 
     fn from_str(s: &str) -> Result<Message, Self::Err> {
-        if s.is_empty() {
-            return Err(IrcError::InvalidMessage {
+        MessageRef::parse(s)
+            .and_then(|message| message.to_message())
+            .map_err(|cause| IrcError::InvalidMessage {
                 string: s.to_owned(),
-                cause: MessageParseError::EmptyMessage,
-            });
-        }
-
-        let mut state = s;
-
-        let tags = if state.starts_with('@') {
-            let tags = state.find(' ').map(|i| &state[1..i]);
-            state = state.find(' ').map_or("", |i| &state[i + 1..]);
-            tags.map(|ts| {
-                ts.split(';')
-                    .filter(|s| !s.is_empty())
-                    .map(|s: &str| {
-                        let mut iter = s.splitn(2, '=');
-                        let (fst, snd) = (iter.next(), iter.next());
-                        Tag(fst.unwrap_or("").to_owned(), snd.map(|s| s.to_owned()))
-                    })
-                    .collect::<Vec<_>>()
+                cause,
             })
-        } else {
-            None
-        };
-
-        let prefix = if state.starts_with(':') {
-            let prefix = state.find(' ').map(|i| &state[1..i]);
-            state = state.find(' ').map_or("", |i| &state[i + 1..]);
-            prefix
-        } else {
-            None
-        };
-
-        let line_ending_len = if state.ends_with("\r\n") {
-            "\r\n"
-        } else if state.ends_with('\r') {
-            "\r"
-        } else if state.ends_with('\n') {
-            "\n"
-        } else {
-            ""
-        }
-        .len();
-
-        let suffix = if state.contains(" :") {
-            let suffix = state
-                .find(" :")
-                .map(|i| &state[i + 2..state.len() - line_ending_len]);
-            state = state.find(" :").map_or("", |i| &state[..=i]);
-            suffix
-        } else {
-            state = &state[..state.len() - line_ending_len];
-            None
-        };
-
-        let command = match state.find(' ').map(|i| &state[..i]) {
-            Some(cmd) => {
-                state = state.find(' ').map_or("", |i| &state[i + 1..]);
-                cmd
-            }
-            // If there's no arguments but the "command" starts with colon, it's not a command.
-            None if state.starts_with(':') => {
-                return Err(IrcError::InvalidMessage {
-                    string: s.to_owned(),
-                    cause: MessageParseError::InvalidCommand,
-                })
-            }
-            // If there's no arguments following the command, the rest of the state is the command.
-            None => {
-                let cmd = state;
-                state = "";
-                cmd
-            }
-        };
-
-        let args: Vec<_> = state.splitn(14, ' ').filter(|s| !s.is_empty()).collect();
-
-        Message::with_tags(tags, prefix, command, args, suffix).map_err(|e| {
-            IrcError::InvalidMessage {
-                string: s.to_owned(),
-                cause: e,
-            }
-        })
     }
 }
 
@@ -361,7 +272,7 @@ mod test {
         assert_eq!(&message.as_string()[..], "PRIVMSG test :Testing!\r\n");
         let message = Message {
             tags: None,
-            prefix: Some(format!("test!test@test")),
+            prefix: Some("test!test@test".into()),
             command: PRIVMSG(format!("test"), format!("Still testing!")),
         };
         assert_eq!(
@@ -383,7 +294,7 @@ mod test {
         );
         let message = Message {
             tags: None,
-            prefix: Some(format!("test!test@test")),
+            prefix: Some("test!test@test".into()),
             command: PRIVMSG(format!("test"), format!("Still testing!")),
         };
         assert_eq!(
@@ -398,7 +309,7 @@ mod test {
                 Tag(format!("ccc"), None),
                 Tag(format!("example.com/ddd"), Some(format!("eee"))),
             ]),
-            prefix: Some(format!("test!test@test")),
+            prefix: Some("test!test@test".into()),
             command: PRIVMSG(format!("test"), format!("Testing with tags!")),
         };
         assert_eq!(
@@ -450,7 +361,7 @@ mod test {
         assert_eq!(msg, message);
         let message = Message {
             tags: None,
-            prefix: Some(format!("test!test@test")),
+            prefix: Some("test!test@test".into()),
             command: PRIVMSG(format!("test"), format!("Still testing!")),
         };
         let msg: Message = ":test!test@test PRIVMSG test :Still testing!\r\n".into();
@@ -463,7 +374,7 @@ mod test {
         // colons within individual parameters. So, let's make sure it parses correctly.
         let message = Message {
             tags: None,
-            prefix: Some(format!("test!test@test")),
+            prefix: Some("test!test@test".into()),
             command: Raw(
                 format!("COMMAND"),
                 vec![format!("ARG:test")],
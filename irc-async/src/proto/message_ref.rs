@@ -0,0 +1,228 @@
+//! A borrowing view of an IRC message, for parsing without allocating.
+use std::str;
+
+use crate::proto::message::Tag;
+use crate::proto::{Command, Message, MessageParseError, Source};
+
+/// An IRC message parsed in place from a line of text. Unlike
+/// [Message](../message/struct.Message.html), parsing a `MessageRef` never allocates: every
+/// component borrows from the original line, and tags and arguments are only split apart as they
+/// are iterated over. It can be converted into an owned `Message` or `Command` once it is known to
+/// be interesting.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// let message = MessageRef::parse(":nick!user@host PRIVMSG #channel :Hello!\r\n").unwrap();
+/// assert_eq!(message.prefix(), Some("nick!user@host"));
+/// assert_eq!(message.command(), "PRIVMSG");
+/// assert_eq!(message.args().collect::<Vec<_>>(), vec!["#channel"]);
+/// assert_eq!(message.suffix(), Some("Hello!"));
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MessageRef<'a> {
+    tags: Option<&'a str>,
+    prefix: Option<&'a str>,
+    command: &'a str,
+    args: &'a str,
+    suffix: Option<&'a str>,
+}
+
+impl<'a> MessageRef<'a> {
+    /// Parses a line, with or without its line ending, into its components.
+    pub fn parse(s: &'a str) -> Result<MessageRef<'a>, MessageParseError> {
+        if s.is_empty() {
+            return Err(MessageParseError::EmptyMessage);
+        }
+
+        let mut state = s;
+
+        let tags = if state.starts_with('@') {
+            let tags = state.find(' ').map(|i| &state[1..i]);
+            state = state.find(' ').map_or("", |i| &state[i + 1..]);
+            tags
+        } else {
+            None
+        };
+
+        let prefix = if state.starts_with(':') {
+            let prefix = state.find(' ').map(|i| &state[1..i]);
+            state = state.find(' ').map_or("", |i| &state[i + 1..]);
+            prefix
+        } else {
+            None
+        };
+
+        let line_ending_len = if state.ends_with("\r\n") {
+            "\r\n"
+        } else if state.ends_with('\r') {
+            "\r"
+        } else if state.ends_with('\n') {
+            "\n"
+        } else {
+            ""
+        }
+        .len();
+
+        let suffix = if state.contains(" :") {
+            let suffix = state
+                .find(" :")
+                .map(|i| &state[i + 2..state.len() - line_ending_len]);
+            state = state.find(" :").map_or("", |i| &state[..=i]);
+            suffix
+        } else {
+            state = &state[..state.len() - line_ending_len];
+            None
+        };
+
+        let command = match state.find(' ').map(|i| &state[..i]) {
+            Some(cmd) => {
+                state = state.find(' ').map_or("", |i| &state[i + 1..]);
+                cmd
+            }
+            // If there's no arguments but the "command" starts with colon, it's not a command.
+            None if state.starts_with(':') => return Err(MessageParseError::InvalidCommand),
+            // If there's no arguments following the command, the rest of the state is the command.
+            None => {
+                let cmd = state;
+                state = "";
+                cmd
+            }
+        };
+
+        Ok(MessageRef {
+            tags,
+            prefix,
+            command,
+            args: state,
+            suffix,
+        })
+    }
+
+    /// Parses a line of raw bytes, such as a frame split off a `BytesMut`, which must be UTF-8.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<MessageRef<'a>, MessageParseError> {
+        MessageRef::parse(str::from_utf8(bytes)?)
+    }
+
+    /// Iterates over the message tags as key and optional value.
+    pub fn tags(&self) -> impl Iterator<Item = (&'a str, Option<&'a str>)> {
+        self.tags
+            .unwrap_or("")
+            .split(';')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let mut iter = s.splitn(2, '=');
+                (iter.next().unwrap_or(""), iter.next())
+            })
+    }
+
+    /// Gets the message prefix (or source), if it exists.
+    pub fn prefix(&self) -> Option<&'a str> {
+        self.prefix
+    }
+
+    /// Gets the name of the command, as it appeared in the message.
+    pub fn command(&self) -> &'a str {
+        self.command
+    }
+
+    /// Iterates over the arguments to the command, excluding the suffix.
+    pub fn args(&self) -> impl Iterator<Item = &'a str> {
+        self.args.splitn(14, ' ').filter(|s| !s.is_empty())
+    }
+
+    /// Gets the special suffix argument, if it exists.
+    pub fn suffix(&self) -> Option<&'a str> {
+        self.suffix
+    }
+
+    /// Parses the command and its arguments into an owned `Command`.
+    pub fn to_command(&self) -> Result<Command, MessageParseError> {
+        Command::new(self.command, self.args().collect(), self.suffix)
+    }
+
+    /// Converts this into an owned `Message`.
+    pub fn to_message(&self) -> Result<Message, MessageParseError> {
+        let tags = self.tags.map(|_| {
+            self.tags()
+                .map(|(key, value)| Tag(key.to_owned(), value.map(|s| s.to_owned())))
+                .collect()
+        });
+        Ok(Message {
+            tags,
+            prefix: self.prefix.map(Source::from),
+            command: self.to_command()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MessageRef;
+    use crate::proto::{Command, Message, MessageParseError};
+
+    #[test]
+    fn parse() {
+        let message = MessageRef::parse(
+            "@aaa=bbb;ccc;example.com/ddd=eee :test!test@test PRIVMSG test :Testing with tags!\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            message.tags().collect::<Vec<_>>(),
+            vec![
+                ("aaa", Some("bbb")),
+                ("ccc", None),
+                ("example.com/ddd", Some("eee"))
+            ]
+        );
+        assert_eq!(message.prefix(), Some("test!test@test"));
+        assert_eq!(message.command(), "PRIVMSG");
+        assert_eq!(message.args().collect::<Vec<_>>(), vec!["test"]);
+        assert_eq!(message.suffix(), Some("Testing with tags!"));
+    }
+
+    #[test]
+    fn parse_no_prefix_no_args() {
+        let message = MessageRef::parse("QUIT\r\n").unwrap();
+        assert_eq!(message.tags().count(), 0);
+        assert_eq!(message.prefix(), None);
+        assert_eq!(message.command(), "QUIT");
+        assert_eq!(message.args().count(), 0);
+        assert_eq!(message.suffix(), None);
+        assert_eq!(message.to_command().unwrap(), Command::QUIT(None));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        match MessageRef::parse("") {
+            Err(MessageParseError::EmptyMessage) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match MessageRef::parse(":invalid :message") {
+            Err(MessageParseError::InvalidCommand) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match MessageRef::from_bytes(b"PRIVMSG test :\xff\r\n") {
+            Err(MessageParseError::InvalidUtf8(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn to_message() {
+        for line in &[
+            "PRIVMSG test :Testing!\r\n",
+            ":test!test@test PRIVMSG test :Still testing!\r\n",
+            "@aaa=bbb;ccc :test!test@test COMMAND ARG:test :Testing!\r\n",
+            ":irc.test.net 353 nick = #channel :nick @op +voice\r\n",
+        ] {
+            let message: Message = MessageRef::from_bytes(line.as_bytes())
+                .unwrap()
+                .to_message()
+                .unwrap();
+            assert_eq!(&message.to_string(), line);
+        }
+    }
+}
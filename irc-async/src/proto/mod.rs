@@ -3,21 +3,41 @@
 //! Support for the IRC protocol using Tokio.
 
 pub mod caps;
+pub mod casemap;
 pub mod chan;
 pub mod colors;
 pub mod command;
+pub mod ctcp;
+pub mod dcc;
+pub mod encoding;
 mod errors;
 pub mod irc;
+pub mod isupport;
 pub mod message;
+pub mod message_ref;
 pub mod mode;
+pub mod reply;
 pub mod response;
+pub mod source;
+pub mod split;
+pub mod state;
 
 pub use self::caps::{Capability, NegotiationVersion};
+pub use self::casemap::{CaseMap, CaseMapping};
 pub use self::chan::ChannelExt;
 pub use self::colors::FormattedStringExt;
-pub use self::command::{BatchSubCommand, CapSubCommand, Command};
-pub use self::irc::IrcCodec;
+pub use self::command::{BatchSubCommand, CapSubCommand, ChatHistorySubCommand, Command};
+pub use self::ctcp::Ctcp;
+pub use self::dcc::Dcc;
+pub use self::encoding::Encoding;
+pub use self::irc::{IrcCodec, Line};
+pub use self::isupport::{ChannelModeKind, ISupport};
 pub use self::message::Message;
+pub use self::message_ref::MessageRef;
 pub use self::mode::{ChannelMode, Mode, UserMode};
+pub use self::reply::{StandardReply, StandardReplyKind};
 pub use self::response::Response;
+pub use self::source::Source;
+pub use self::split::{MultilineLimits, TextLine};
+pub use self::state::ChannelState;
 pub use errors::*;
@@ -1,6 +1,7 @@
 //! A module defining an API for IRC user and channel modes.
 use std::fmt;
 
+use crate::proto::isupport::{ChannelModeKind, ISupport};
 use crate::proto::Command;
 use crate::proto::MessageParseError::{self, *};
 use crate::proto::ModeParseError::*;
@@ -135,18 +136,13 @@ impl ModeType for ChannelMode {
     }
 
     fn takes_arg(&self) -> bool {
-        use self::ChannelMode::*;
-
-        match *self {
-            Ban | Exception | Limit | InviteException | Key | Founder | Admin | Oper | Halfop
-            | Voice => true,
-            _ => false,
-        }
+        !matches!(self.kind(), None | Some(ChannelModeKind::Never))
     }
 }
 
 impl ChannelMode {
-    fn from_char(c: char) -> ChannelMode {
+    /// Gets the mode for a mode character.
+    pub fn from_char(c: char) -> ChannelMode {
         use self::ChannelMode::*;
 
         match c {
@@ -169,35 +165,79 @@ impl ChannelMode {
             _ => Unknown(c),
         }
     }
+
+    /// Gets the mode for a mode character, given the server's advertised `CHANMODES` and `PREFIX`.
+    ///
+    /// Servers reuse letters for different modes, so a letter is only parsed as one of the crate's
+    /// modes if the server advertises it with the same kind of argument, or not at all. For
+    /// example, `q` is `Founder` if it's in `PREFIX`, but a quiet list on servers that list it in
+    /// `CHANMODES` as a list mode, which is parsed as `Unknown('q')`.
+    ///
+    /// # Example
+    /// ```
+    /// # use irc_async::proto::*;
+    /// # fn main() {
+    /// let mut isupport = ISupport::default();
+    /// isupport.update(&["PREFIX=(qaohv)~&@%+"]);
+    /// assert_eq!(ChannelMode::from_char_with('q', &isupport), ChannelMode::Founder);
+    /// isupport.update(&["CHANMODES=beIq,k,l,imnpst", "PREFIX=(ov)@+"]);
+    /// assert_eq!(ChannelMode::from_char_with('q', &isupport), ChannelMode::Unknown('q'));
+    /// assert_eq!(ChannelMode::from_char_with('b', &isupport), ChannelMode::Ban);
+    /// # }
+    /// ```
+    pub fn from_char_with(c: char, isupport: &ISupport) -> ChannelMode {
+        let mode = ChannelMode::from_char(c);
+        match isupport.channel_mode_kind(c) {
+            Some(kind) if mode.kind() != Some(kind) => ChannelMode::Unknown(c),
+            _ => mode,
+        }
+    }
+
+    /// Gets how this mode takes an argument, going by RFC 2812 and common use, or `None` for modes
+    /// unknown to the crate.
+    fn kind(&self) -> Option<ChannelModeKind> {
+        use self::ChannelMode::*;
+
+        Some(match *self {
+            Ban | Exception | InviteException => ChannelModeKind::List,
+            Key => ChannelModeKind::Always,
+            Limit => ChannelModeKind::WhenSet,
+            InviteOnly | Moderated | RegisteredOnly | Secret | ProtectedTopic
+            | NoExternalMessages => ChannelModeKind::Never,
+            Founder | Admin | Oper | Halfop | Voice => ChannelModeKind::Prefix,
+            Unknown(_) => return None,
+        })
+    }
+
+    /// Gets the mode character of this mode.
+    pub fn to_char(&self) -> char {
+        use self::ChannelMode::*;
+
+        match *self {
+            Ban => 'b',
+            Exception => 'e',
+            Limit => 'l',
+            InviteOnly => 'i',
+            InviteException => 'I',
+            Key => 'k',
+            Moderated => 'm',
+            RegisteredOnly => 'r',
+            Secret => 's',
+            ProtectedTopic => 't',
+            NoExternalMessages => 'n',
+            Founder => 'q',
+            Admin => 'a',
+            Oper => 'o',
+            Halfop => 'h',
+            Voice => 'v',
+            Unknown(c) => c,
+        }
+    }
 }
 
 impl fmt::Display for ChannelMode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use self::ChannelMode::*;
-
-        write!(
-            f,
-            "{}",
-            match *self {
-                Ban => 'b',
-                Exception => 'e',
-                Limit => 'l',
-                InviteOnly => 'i',
-                InviteException => 'I',
-                Key => 'k',
-                Moderated => 'm',
-                RegisteredOnly => 'r',
-                Secret => 's',
-                ProtectedTopic => 't',
-                NoExternalMessages => 'n',
-                Founder => 'q',
-                Admin => 'a',
-                Oper => 'o',
-                Halfop => 'h',
-                Voice => 'v',
-                Unknown(c) => c,
-            }
-        )
+        write!(f, "{}", self.to_char())
     }
 }
 
@@ -235,114 +275,124 @@ where
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Mode::Plus(ref mode, Some(ref arg)) => write!(f, "+{} {}", mode, arg),
-            Mode::Minus(ref mode, Some(ref arg)) => write!(f, "+{} {}", mode, arg),
+            Mode::Minus(ref mode, Some(ref arg)) => write!(f, "-{} {}", mode, arg),
             Mode::Plus(ref mode, None) => write!(f, "+{}", mode),
             Mode::Minus(ref mode, None) => write!(f, "-{}", mode),
         }
     }
 }
 
-enum PlusMinus {
-    Plus,
-    Minus,
-}
-
-// MODE user [modes]
-impl Mode<UserMode> {
-    // TODO: turning more edge cases into errors.
-    /// Parses the specified mode string as user modes.
-    pub fn from_user_mode_string(s: &str) -> Result<Vec<Mode<UserMode>>, MessageParseError> {
-        use self::PlusMinus::*;
-
-        let mut res = vec![];
-        let mut pieces = s.split(' ');
-        for term in pieces.clone() {
-            if term.starts_with('+') || term.starts_with('-') {
-                let _ = pieces.next();
-
-                let mut chars = term.chars();
-                let init = match chars.next() {
-                    Some('+') => Plus,
-                    Some('-') => Minus,
-                    Some(c) => {
-                        return Err(InvalidModeString {
-                            string: s.to_owned(),
-                            cause: InvalidModeModifier { modifier: c },
-                        })
-                    }
-                    None => {
-                        return Err(InvalidModeString {
-                            string: s.to_owned(),
-                            cause: MissingModeModifier,
-                        })
-                    }
-                };
-
-                for c in chars {
-                    let mode = UserMode::from_char(c);
-                    let arg = if mode.takes_arg() {
-                        pieces.next()
+/// Parses a mode string, where `takes_arg` decides whether a mode, being added or not, takes an
+/// argument.
+///
+/// Arguments either follow all of the modes (`+ov-m nick other`), or each group of modes
+/// (`+ov nick other -m`).
+fn parse_modes<T, C, F>(
+    s: &str,
+    from_char: C,
+    takes_arg: F,
+) -> Result<Vec<Mode<T>>, MessageParseError>
+where
+    T: ModeType,
+    C: Fn(char) -> T,
+    F: Fn(&T, bool) -> bool,
+{
+    let error = |cause| InvalidModeString {
+        string: s.to_owned(),
+        cause,
+    };
+
+    let mut res = vec![];
+    let mut pieces = s.split(' ').filter(|piece| !piece.is_empty());
+    while let Some(term) = pieces.next() {
+        let mut plus = match term.chars().next() {
+            Some('+') => true,
+            Some('-') => false,
+            // left over after every mode got its argument
+            _ => {
+                return Err(error(UnexpectedArgument {
+                    arg: term.to_owned(),
+                }))
+            }
+        };
+        for c in term.chars() {
+            match c {
+                '+' => plus = true,
+                '-' => plus = false,
+                c => {
+                    let mode = from_char(c);
+                    let arg = if takes_arg(&mode, plus) {
+                        pieces.next().map(|s| s.to_owned())
                     } else {
                         None
                     };
-                    res.push(match init {
-                        Plus => Mode::Plus(mode, arg.map(|s| s.to_owned())),
-                        Minus => Mode::Minus(mode, arg.map(|s| s.to_owned())),
-                    })
+                    res.push(if plus {
+                        Mode::Plus(mode, arg)
+                    } else {
+                        Mode::Minus(mode, arg)
+                    });
                 }
             }
         }
+    }
+
+    Ok(res)
+}
 
-        Ok(res)
+// MODE user [modes]
+impl Mode<UserMode> {
+    /// Parses the specified mode string as user modes.
+    pub fn from_user_mode_string(s: &str) -> Result<Vec<Mode<UserMode>>, MessageParseError> {
+        parse_modes(s, UserMode::from_char, |mode, _| mode.takes_arg())
     }
 }
 
 // MODE channel [modes [modeparams]]
 impl Mode<ChannelMode> {
-    // TODO: turning more edge cases into errors.
-    /// Parses the specified mode string as channel modes.
+    /// Parses the specified mode string as channel modes, assuming the server supports the
+    /// channel modes of RFC 2812.
+    ///
+    /// Servers advertise which modes take arguments, and any modes of their own, in `RPL_ISUPPORT`.
+    /// Use [`from_channel_mode_string_with`](#method.from_channel_mode_string_with) to parse with
+    /// that information.
     pub fn from_channel_mode_string(s: &str) -> Result<Vec<Mode<ChannelMode>>, MessageParseError> {
-        use self::PlusMinus::*;
-
-        let mut res = vec![];
-        let mut pieces = s.split(' ');
-        for term in pieces.clone() {
-            if term.starts_with('+') || term.starts_with('-') {
-                let _ = pieces.next();
-
-                let mut chars = term.chars();
-                let init = match chars.next() {
-                    Some('+') => Plus,
-                    Some('-') => Minus,
-                    Some(c) => {
-                        return Err(InvalidModeString {
-                            string: s.to_owned(),
-                            cause: InvalidModeModifier { modifier: c },
-                        })
-                    }
-                    None => {
-                        return Err(InvalidModeString {
-                            string: s.to_owned(),
-                            cause: MissingModeModifier,
-                        })
-                    }
-                };
-
-                for c in chars {
-                    let mode = ChannelMode::from_char(c);
-                    let arg = if mode.takes_arg() {
-                        pieces.next()
-                    } else {
-                        None
-                    };
-                    res.push(match init {
-                        Plus => Mode::Plus(mode, arg.map(|s| s.to_owned())),
-                        Minus => Mode::Minus(mode, arg.map(|s| s.to_owned())),
-                    })
-                }
-            }
-        }
+        Mode::from_channel_mode_string_with(s, &ISupport::default())
+    }
 
-        Ok(res)
+    /// Parses the specified mode string as channel modes, using the server's advertised
+    /// `CHANMODES` and `PREFIX` to decide which modes take arguments, and which letters mean
+    /// something other than the crate's modes (see
+    /// [`ChannelMode::from_char_with`](enum.ChannelMode.html#method.from_char_with)).
+    ///
+    /// # Example
+    /// ```
+    /// # use irc_async::proto::*;
+    /// # fn main() {
+    /// let mut isupport = ISupport::default();
+    /// isupport.update(&["CHANMODES=beIq,k,fl,imnpst", "PREFIX=(ov)@+"]);
+    /// let modes = Mode::from_channel_mode_string_with("+fq-l 10:5 *!*@spam", &isupport).unwrap();
+    /// assert_eq!(modes, vec![
+    ///     Mode::plus(ChannelMode::Unknown('f'), Some("10:5")),
+    ///     // a quiet, not a founder, since this server lists `q` as a list mode
+    ///     Mode::plus(ChannelMode::Unknown('q'), Some("*!*@spam")),
+    ///     Mode::minus(ChannelMode::Limit, None),
+    /// ]);
+    /// # }
+    /// ```
+    pub fn from_channel_mode_string_with(
+        s: &str,
+        isupport: &ISupport,
+    ) -> Result<Vec<Mode<ChannelMode>>, MessageParseError> {
+        let from_char = |c| ChannelMode::from_char_with(c, isupport);
+        parse_modes(s, from_char, |mode, plus| {
+            match isupport.channel_mode_kind(mode.to_char()) {
+                Some(ChannelModeKind::List)
+                | Some(ChannelModeKind::Always)
+                | Some(ChannelModeKind::Prefix) => true,
+                Some(ChannelModeKind::WhenSet) => plus,
+                Some(ChannelModeKind::Never) => false,
+                None => mode.takes_arg(),
+            }
+        })
     }
 }
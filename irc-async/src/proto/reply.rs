@@ -0,0 +1,92 @@
+//! Structured [standard replies](https://ircv3.net/specs/extensions/standard-replies).
+use std::str::FromStr;
+
+use crate::proto::MessageParseError;
+
+/// The severity of a standard reply.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StandardReplyKind {
+    /// The command failed.
+    Fail,
+    /// The command succeeded, but something may need the user's attention.
+    Warn,
+    /// Information about the command, not indicating any problem.
+    Note,
+}
+
+impl StandardReplyKind {
+    /// Gets the command name that corresponds to this kind of reply.
+    pub fn to_str(&self) -> &str {
+        match *self {
+            StandardReplyKind::Fail => "FAIL",
+            StandardReplyKind::Warn => "WARN",
+            StandardReplyKind::Note => "NOTE",
+        }
+    }
+}
+
+impl FromStr for StandardReplyKind {
+    type Err = MessageParseError;
+
+    fn from_str(s: &str) -> Result<StandardReplyKind, Self::Err> {
+        if s.eq_ignore_ascii_case("FAIL") {
+            Ok(StandardReplyKind::Fail)
+        } else if s.eq_ignore_ascii_case("WARN") {
+            Ok(StandardReplyKind::Warn)
+        } else if s.eq_ignore_ascii_case("NOTE") {
+            Ok(StandardReplyKind::Note)
+        } else {
+            Err(MessageParseError::InvalidCommand)
+        }
+    }
+}
+
+/// A `FAIL`, `WARN` or `NOTE` reply from the server, describing the outcome of a command in a
+/// machine-readable way.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// let message: Message = "FAIL PRIVMSG INVALID_TARGET #secret :No such channel\r\n".into();
+/// match message.command {
+///     Command::StandardReply(reply) => {
+///         assert_eq!(reply.kind, StandardReplyKind::Fail);
+///         assert_eq!(reply.command, "PRIVMSG");
+///         assert_eq!(reply.code, "INVALID_TARGET");
+///         assert_eq!(reply.context, vec!["#secret"]);
+///         assert_eq!(reply.description, "No such channel");
+///     }
+///     _ => unreachable!(),
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct StandardReply {
+    /// Whether this is a failure, a warning or a note.
+    pub kind: StandardReplyKind,
+
+    /// The command this reply is about, or `*` if it isn't about a specific command.
+    pub command: String,
+
+    /// A machine-readable code for the reply, such as `INVALID_TARGET`.
+    pub code: String,
+
+    /// Extra parameters giving context, such as the target of the command.
+    pub context: Vec<String>,
+
+    /// A human-readable description of the reply.
+    pub description: String,
+}
+
+impl StandardReply {
+    /// Returns true if this reply reports that a command failed.
+    pub fn is_failure(&self) -> bool {
+        self.kind == StandardReplyKind::Fail
+    }
+
+    /// Returns true if this reply is about the given command, ignoring case.
+    pub fn is_about(&self, command: &str) -> bool {
+        self.command.eq_ignore_ascii_case(command)
+    }
+}
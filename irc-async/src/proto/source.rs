@@ -0,0 +1,224 @@
+//! The source of a message, parsed from its prefix.
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::proto::CaseMapping;
+
+/// Where a message came from: a server, or a user given as `nick!user@host`, as defined by
+/// [RFC 2812](http://tools.ietf.org/html/rfc2812#section-2.3.1).
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// let source = Source::from("nick!~user@host.example.com");
+/// assert_eq!(source.nick(), Some("nick"));
+/// assert_eq!(source.user(), Some("~user"));
+/// assert!(source.matches("*!*@*.example.com", CaseMapping::Rfc1459));
+/// assert_eq!(Source::from("irc.example.com"), Source::Server("irc.example.com".to_owned()));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// A server, by its name.
+    Server(String),
+    /// A user, with as much of their user name and host as the server included.
+    User {
+        /// The nick of the user.
+        nick: String,
+        /// The user name, if the server included it.
+        user: Option<String>,
+        /// The host, if the server included it.
+        host: Option<String>,
+    },
+}
+
+impl Source {
+    /// Gets the nick of the user, if this is a user.
+    pub fn nick(&self) -> Option<&str> {
+        match *self {
+            Source::User { ref nick, .. } => Some(nick),
+            Source::Server(_) => None,
+        }
+    }
+
+    /// Gets the user name of the user, if this is a user and the server included it.
+    pub fn user(&self) -> Option<&str> {
+        match *self {
+            Source::User { ref user, .. } => user.as_deref(),
+            Source::Server(_) => None,
+        }
+    }
+
+    /// Gets the host of the user, or the name of the server.
+    pub fn host(&self) -> Option<&str> {
+        match *self {
+            Source::User { ref host, .. } => host.as_deref(),
+            Source::Server(ref host) => Some(host),
+        }
+    }
+
+    /// Returns true if this is a server.
+    pub fn is_server(&self) -> bool {
+        match *self {
+            Source::Server(_) => true,
+            Source::User { .. } => false,
+        }
+    }
+
+    /// Returns true if the source matches a hostmask like `*!*@*.example.com`, as used in ban
+    /// lists. `*` matches any run of characters and `?` matches any one. Parts of the source the
+    /// server didn't include only match a mask that allows them to be empty.
+    pub fn matches(&self, mask: &str, mapping: CaseMapping) -> bool {
+        let source = match *self {
+            Source::Server(ref host) => host.clone(),
+            Source::User {
+                ref nick,
+                ref user,
+                ref host,
+            } => format!(
+                "{}!{}@{}",
+                nick,
+                user.as_deref().unwrap_or(""),
+                host.as_deref().unwrap_or("")
+            ),
+        };
+        glob_match(mask, &source, mapping)
+    }
+}
+
+impl<'a> From<&'a str> for Source {
+    fn from(s: &'a str) -> Source {
+        // <prefix> ::= <servername> | <nick> [ [ '!' <user> ] '@' <host> ]
+        let (rest, host) = match s.find('@') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let (nick, user) = match rest.find('!') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        // nicks can't contain dots, but server names usually do
+        if host.is_none() && user.is_none() && nick.contains('.') {
+            return Source::Server(s.to_owned());
+        }
+        Source::User {
+            nick: nick.to_owned(),
+            user: user.map(|s| s.to_owned()),
+            host: host.map(|s| s.to_owned()),
+        }
+    }
+}
+
+impl From<String> for Source {
+    fn from(s: String) -> Source {
+        Source::from(&s[..])
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            Source::Server(ref host) => write!(f, "{}", host),
+            Source::User {
+                ref nick,
+                ref user,
+                ref host,
+            } => {
+                write!(f, "{}", nick)?;
+                if let Some(ref user) = *user {
+                    write!(f, "!{}", user)?;
+                }
+                if let Some(ref host) = *host {
+                    write!(f, "@{}", host)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Returns true if the text matches a glob pattern, where `*` matches any run of characters and
+/// `?` matches any one, comparing the rest with the case mapping.
+pub fn glob_match(pattern: &str, text: &str, mapping: CaseMapping) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(|c| mapping.fold_char(c)).collect();
+    let text: Vec<char> = text.chars().map(|c| mapping.fold_char(c)).collect();
+
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was, and how much of the text it has taken so far
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // let the last `*` take one more character
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::{glob_match, Source};
+    use crate::proto::CaseMapping;
+
+    fn user(nick: &str, user: Option<&str>, host: Option<&str>) -> Source {
+        Source::User {
+            nick: nick.to_owned(),
+            user: user.map(|s| s.to_owned()),
+            host: host.map(|s| s.to_owned()),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let cases = vec![
+            ("nick!user@host", user("nick", Some("user"), Some("host"))),
+            ("nick@host.com", user("nick", None, Some("host.com"))),
+            ("nick!user", user("nick", Some("user"), None)),
+            ("nick", user("nick", None, None)),
+            ("irc.test.net", Source::Server("irc.test.net".to_owned())),
+        ];
+        for (prefix, source) in cases {
+            assert_eq!(Source::from(prefix), source);
+            assert_eq!(source.to_string(), prefix);
+        }
+    }
+
+    #[test]
+    fn globs() {
+        let rfc1459 = CaseMapping::Rfc1459;
+        assert!(glob_match("*", "", rfc1459));
+        assert!(glob_match("a*c", "abbbc", rfc1459));
+        assert!(glob_match("a?c", "abc", rfc1459));
+        assert!(!glob_match("a?c", "ac", rfc1459));
+        assert!(glob_match("*b*b", "abab", rfc1459));
+        assert!(!glob_match("*b*b", "abac", rfc1459));
+        assert!(glob_match("NICK[*", "nick{away", rfc1459));
+        assert!(!glob_match("NICK[*", "nick{away", CaseMapping::Ascii));
+    }
+
+    #[test]
+    fn masks() {
+        let rfc1459 = CaseMapping::Rfc1459;
+        let source = Source::from("Spammer!~spam@bad.example.com");
+        assert!(source.matches("*!*@*.example.com", rfc1459));
+        assert!(source.matches("spammer!*@*", rfc1459));
+        assert!(!source.matches("*!*@good.example.com", rfc1459));
+        assert!(Source::from("nick").matches("nick!*@*", rfc1459));
+        assert!(Source::from("irc.example.com").matches("*.example.com", rfc1459));
+    }
+}
@@ -0,0 +1,195 @@
+//! Splitting text into pieces that fit in IRC lines.
+
+/// A piece of text to send in one `PRIVMSG` or `NOTICE`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextLine<'a> {
+    /// The text of the piece.
+    pub text: &'a str,
+    /// Whether the piece continues the line before it, rather than starting a new one, because the
+    /// line was too long to send whole.
+    pub concat: bool,
+}
+
+/// Splits text into lines on its line breaks, and splits each line that is longer than `max_len`
+/// bytes into pieces, after the last space that fits if there is one, and otherwise on a character
+/// boundary. Spaces a line is split after stay at the end of the piece before.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::split::split_text;
+/// # fn main() {
+/// let lines = split_text("hello there\r\nwörld wide", 9);
+/// let texts: Vec<_> = lines.iter().map(|line| line.text).collect();
+/// assert_eq!(texts, vec!["hello ", "there", "wörld ", "wide"]);
+/// assert!(lines[1].concat && !lines[2].concat);
+/// # }
+/// ```
+pub fn split_text(text: &str, max_len: usize) -> Vec<TextLine<'_>> {
+    let mut lines = Vec::new();
+    // a lone `\r` would end the line as far as the server is concerned
+    for line in text.split('\n').flat_map(|line| {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        line.split('\r')
+    }) {
+        let mut rest = line;
+        let mut concat = false;
+        while rest.len() > max_len {
+            let mut end = max_len;
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            if let Some(space) = rest[..end].rfind(' ') {
+                end = space + 1;
+            }
+            // always make progress, even if not a single character fits
+            if end == 0 {
+                end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+            }
+            lines.push(TextLine {
+                text: &rest[..end],
+                concat,
+            });
+            rest = &rest[end..];
+            concat = true;
+        }
+        if !rest.is_empty() || !concat {
+            lines.push(TextLine { text: rest, concat });
+        }
+    }
+    lines
+}
+
+/// The limits the server advertises with the `draft/multiline` capability, like
+/// `max-bytes=4096,max-lines=24`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MultilineLimits {
+    /// The maximum number of bytes of text in one batch, counting the line breaks between lines.
+    pub max_bytes: usize,
+    /// The maximum number of lines in one batch, if there is one.
+    pub max_lines: Option<usize>,
+}
+
+impl MultilineLimits {
+    /// Parses the value of the capability. Returns `None` if it is missing `max-bytes`, which
+    /// servers must advertise.
+    pub fn parse(value: &str) -> Option<MultilineLimits> {
+        let mut max_bytes = None;
+        let mut max_lines = None;
+        for param in value.split(',') {
+            let mut parts = param.splitn(2, '=');
+            let key = parts.next().unwrap_or(param);
+            let value = parts.next().and_then(|value| value.parse().ok());
+            match key {
+                "max-bytes" => max_bytes = value,
+                "max-lines" => max_lines = value,
+                _ => (),
+            }
+        }
+        Some(MultilineLimits {
+            max_bytes: max_bytes?,
+            max_lines,
+        })
+    }
+
+    /// Groups lines into as few batches as the limits allow. A line longer than `max_bytes` gets a
+    /// batch of its own.
+    pub fn batches<'a, 'b>(&self, lines: &'b [TextLine<'a>]) -> Vec<&'b [TextLine<'a>]> {
+        let mut batches = Vec::new();
+        let mut start = 0;
+        let mut bytes = 0;
+        for (i, line) in lines.iter().enumerate() {
+            // lines that don't continue the one before are joined with a line break
+            let len = match (i == start, line.concat) {
+                (false, false) => line.text.len() + 1,
+                _ => line.text.len(),
+            };
+            let full = bytes + len > self.max_bytes
+                || matches!(self.max_lines, Some(max) if i - start >= max);
+            if full && i > start {
+                batches.push(&lines[start..i]);
+                start = i;
+                bytes = line.text.len();
+            } else {
+                bytes += len;
+            }
+        }
+        if start < lines.len() {
+            batches.push(&lines[start..]);
+        }
+        batches
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_text, MultilineLimits, TextLine};
+
+    fn texts<'a>(lines: &[TextLine<'a>]) -> Vec<&'a str> {
+        lines.iter().map(|line| line.text).collect()
+    }
+
+    #[test]
+    fn split_lines() {
+        let lines = split_text("one\ntwo\r\n\rthree", 100);
+        assert_eq!(texts(&lines), vec!["one", "two", "", "three"]);
+        assert!(lines.iter().all(|line| !line.concat));
+    }
+
+    #[test]
+    fn split_words() {
+        let lines = split_text("the quick brown fox", 10);
+        assert_eq!(texts(&lines), vec!["the quick ", "brown fox"]);
+        assert_eq!(
+            lines.iter().map(|line| line.concat).collect::<Vec<_>>(),
+            vec![false, true]
+        );
+        let lines = split_text("abcdefghijkl mno", 5);
+        assert_eq!(texts(&lines), vec!["abcde", "fghij", "kl ", "mno"]);
+    }
+
+    #[test]
+    fn split_utf8() {
+        // each character takes three bytes
+        let lines = split_text("日本語のテキスト", 7);
+        assert_eq!(texts(&lines), vec!["日本", "語の", "テキ", "スト"]);
+        assert_eq!(texts(&split_text("日本", 2)), vec!["日", "本"]);
+        for line in split_text("ça va très bien, merci", 6) {
+            assert!(line.text.len() <= 6);
+        }
+    }
+
+    #[test]
+    fn multiline_limits() {
+        assert_eq!(
+            MultilineLimits::parse("max-bytes=4096,max-lines=24"),
+            Some(MultilineLimits {
+                max_bytes: 4096,
+                max_lines: Some(24),
+            })
+        );
+        assert_eq!(
+            MultilineLimits::parse("max-bytes=40").unwrap().max_lines,
+            None
+        );
+        assert_eq!(MultilineLimits::parse("max-lines=24"), None);
+    }
+
+    #[test]
+    fn multiline_batches() {
+        let limits = MultilineLimits {
+            max_bytes: 12,
+            max_lines: Some(3),
+        };
+        let lines = split_text("abcd\nefgh\nij\nkl\nm n o p q r", 5);
+        let batches = limits.batches(&lines);
+        let batches: Vec<_> = batches.iter().map(|batch| texts(batch)).collect();
+        assert_eq!(
+            batches,
+            vec![
+                vec!["abcd", "efgh", "ij"],
+                vec!["kl", "m n ", "o p "],
+                vec!["q r"],
+            ]
+        );
+    }
+}
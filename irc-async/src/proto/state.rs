@@ -0,0 +1,246 @@
+//! Tracking the state of a channel from the modes the server reports.
+use std::collections::BTreeMap;
+
+use crate::proto::isupport::{ChannelModeKind, ISupport};
+use crate::proto::{CaseMap, ChannelMode, Mode, Source};
+
+/// The modes, lists and members of a channel, kept up to date by applying the changes the server
+/// reports.
+///
+/// # Example
+/// ```
+/// # use irc_async::proto::*;
+/// # fn main() {
+/// let isupport = ISupport::default();
+/// let mut channel = ChannelState::default();
+/// channel.add_member("@nick", &isupport);
+/// channel.apply(&Mode::from_channel_mode_string("+bv-o *!*@spam nick nick").unwrap(), &isupport);
+/// assert_eq!(channel.list(&ChannelMode::Ban), &["*!*@spam".to_owned()]);
+/// assert_eq!(channel.prefix("nick", &isupport), Some('+'));
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ChannelState {
+    /// The modes that are set, with their arguments, if any.
+    modes: BTreeMap<char, Option<String>>,
+
+    /// The entries of list modes, like bans and exceptions.
+    lists: BTreeMap<char, Vec<String>>,
+
+    /// The members of the channel, with their membership modes from the highest rank to the
+    /// lowest.
+    members: CaseMap<Vec<char>>,
+}
+
+impl ChannelState {
+    /// Applies mode changes to the channel.
+    pub fn apply(&mut self, modes: &[Mode<ChannelMode>], isupport: &ISupport) {
+        self.members.set_case_mapping(isupport.case_mapping());
+        for mode in modes {
+            let (plus, mode, arg) = match mode {
+                Mode::Plus(mode, arg) => (true, mode.to_char(), arg.as_ref()),
+                Mode::Minus(mode, arg) => (false, mode.to_char(), arg.as_ref()),
+            };
+            match (isupport.channel_mode_kind(mode), arg) {
+                (Some(ChannelModeKind::Prefix), Some(nick)) => {
+                    // only members can have membership modes, whatever the server says
+                    if let Some(modes) = self.members.get_mut(nick) {
+                        modes.retain(|&m| m != mode);
+                        if plus {
+                            modes.push(mode);
+                            modes.sort_by_key(|&m| isupport.prefix_rank(m));
+                        }
+                    }
+                }
+                (Some(ChannelModeKind::List), Some(mask)) => {
+                    let mapping = isupport.case_mapping();
+                    let list = self.lists.entry(mode).or_default();
+                    list.retain(|m| !mapping.eq(m, mask));
+                    if plus {
+                        list.push(mask.clone());
+                    }
+                }
+                // a list mode without a mask asks for the list, which doesn't change it
+                (Some(ChannelModeKind::List), None) => (),
+                _ if plus => {
+                    let _ = self.modes.insert(mode, arg.cloned());
+                }
+                _ => {
+                    let _ = self.modes.remove(&mode);
+                }
+            }
+        }
+    }
+
+    /// Returns true if a mode is set on the channel.
+    pub fn has_mode(&self, mode: &ChannelMode) -> bool {
+        self.modes.contains_key(&mode.to_char())
+    }
+
+    /// Gets the argument a mode was set with, like the channel key.
+    pub fn mode_arg(&self, mode: &ChannelMode) -> Option<&str> {
+        self.modes.get(&mode.to_char())?.as_deref()
+    }
+
+    /// Gets the modes set on the channel, with their arguments.
+    pub fn modes(&self) -> Vec<Mode<ChannelMode>> {
+        self.modes
+            .iter()
+            .map(|(&mode, arg)| Mode::Plus(ChannelMode::from_char(mode), arg.clone()))
+            .collect()
+    }
+
+    /// Gets the entries of a list mode, like the bans.
+    pub fn list(&self, mode: &ChannelMode) -> &[String] {
+        self.lists
+            .get(&mode.to_char())
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Returns true if the source matches a ban and no exception.
+    pub fn is_banned(&self, source: &Source, isupport: &ISupport) -> bool {
+        let mapping = isupport.case_mapping();
+        let matches = |mode| {
+            self.list(&mode)
+                .iter()
+                .any(|mask| source.matches(mask, mapping))
+        };
+        matches(ChannelMode::Ban) && !matches(ChannelMode::Exception)
+    }
+
+    /// Adds a member, given as it appears in `RPL_NAMREPLY`, with any prefixes before the nick.
+    pub fn add_member(&mut self, name: &str, isupport: &ISupport) {
+        self.members.set_case_mapping(isupport.case_mapping());
+        let nick = name.trim_start_matches(|c| isupport.prefix_mode(c).is_some());
+        let mut modes: Vec<char> = name[..name.len() - nick.len()]
+            .chars()
+            .filter_map(|c| isupport.prefix_mode(c))
+            .collect();
+        modes.sort_by_key(|&m| isupport.prefix_rank(m));
+        let _ = self.members.insert(nick, modes);
+    }
+
+    /// Removes a member, such as when they leave the channel.
+    pub fn remove_member(&mut self, nick: &str) {
+        let _ = self.members.remove(nick);
+    }
+
+    /// Renames a member after they change their nick.
+    pub fn rename_member(&mut self, old: &str, new: &str) {
+        if let Some(modes) = self.members.remove(old) {
+            let _ = self.members.insert(new, modes);
+        }
+    }
+
+    /// Returns true if the nick is a member of the channel.
+    pub fn is_member(&self, nick: &str) -> bool {
+        self.members.contains_key(nick)
+    }
+
+    /// Gets the nicks of the members of the channel.
+    pub fn members(&self) -> impl Iterator<Item = &str> {
+        self.members.keys()
+    }
+
+    /// Gets the membership modes of a member, from the highest rank to the lowest.
+    pub fn member_modes(&self, nick: &str) -> &[char] {
+        self.members.get(nick).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Gets the prefix of the highest membership mode of a member.
+    pub fn prefix(&self, nick: &str, isupport: &ISupport) -> Option<char> {
+        let mode = *self.member_modes(nick).first()?;
+        isupport.mode_prefix(mode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChannelState;
+    use crate::proto::{ChannelMode, ISupport, Mode, Source};
+
+    fn isupport() -> ISupport {
+        let mut isupport = ISupport::default();
+        isupport.update(&["CHANMODES=beIq,k,fl,imnpst", "PREFIX=(aohv)&@%+"]);
+        isupport
+    }
+
+    fn apply(channel: &mut ChannelState, modes: &str, isupport: &ISupport) {
+        let modes = Mode::from_channel_mode_string_with(modes, isupport).unwrap();
+        channel.apply(&modes, isupport);
+    }
+
+    #[test]
+    fn modes() {
+        let isupport = isupport();
+        let mut channel = ChannelState::default();
+        apply(&mut channel, "+ntk-m+fl key 10:5 20", &isupport);
+        assert!(channel.has_mode(&ChannelMode::NoExternalMessages));
+        assert!(!channel.has_mode(&ChannelMode::Moderated));
+        assert_eq!(channel.mode_arg(&ChannelMode::Key), Some("key"));
+        assert_eq!(channel.mode_arg(&ChannelMode::Unknown('f')), Some("10:5"));
+        assert_eq!(channel.mode_arg(&ChannelMode::Limit), Some("20"));
+
+        apply(&mut channel, "-lk key", &isupport);
+        assert!(!channel.has_mode(&ChannelMode::Limit));
+        assert!(!channel.has_mode(&ChannelMode::Key));
+        assert_eq!(
+            channel.modes(),
+            vec![
+                Mode::plus(ChannelMode::Unknown('f'), Some("10:5")),
+                Mode::plus(ChannelMode::NoExternalMessages, None),
+                Mode::plus(ChannelMode::ProtectedTopic, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists() {
+        let isupport = isupport();
+        let mut channel = ChannelState::default();
+        apply(&mut channel, "+bbe a!*@* b!*@* c!*@*", &isupport);
+        apply(&mut channel, "+q-b d!*@* a!*@*", &isupport);
+        apply(&mut channel, "+b", &isupport);
+        assert_eq!(channel.list(&ChannelMode::Ban), &["b!*@*".to_owned()]);
+        assert_eq!(channel.list(&ChannelMode::Exception), &["c!*@*".to_owned()]);
+        // `q` is a quiet list here, not the founder prefix
+        let quiet = ChannelMode::from_char_with('q', &isupport);
+        assert_eq!(quiet, ChannelMode::Unknown('q'));
+        assert_eq!(channel.list(&quiet), &["d!*@*".to_owned()]);
+        assert!(channel.list(&ChannelMode::InviteException).is_empty());
+
+        assert!(channel.is_banned(&Source::from("B!x@y"), &isupport));
+        assert!(!channel.is_banned(&Source::from("c!x@y"), &isupport));
+        apply(&mut channel, "+e *!x@y", &isupport);
+        assert!(!channel.is_banned(&Source::from("B!x@y"), &isupport));
+
+        // masks are removed as the server compares them
+        apply(&mut channel, "-b B!*@*", &isupport);
+        assert!(channel.list(&ChannelMode::Ban).is_empty());
+    }
+
+    #[test]
+    fn members() {
+        let isupport = isupport();
+        let mut channel = ChannelState::default();
+        channel.add_member("@%nick", &isupport);
+        channel.add_member("other", &isupport);
+        assert_eq!(channel.member_modes("nick"), &['o', 'h']);
+        assert_eq!(channel.prefix("other", &isupport), None);
+
+        apply(&mut channel, "+va-o other nick nick", &isupport);
+        assert_eq!(channel.member_modes("nick"), &['a', 'h']);
+        assert_eq!(channel.prefix("nick", &isupport), Some('&'));
+        assert_eq!(channel.prefix("other", &isupport), Some('+'));
+        assert_eq!(channel.member_modes("NICK"), &['a', 'h']);
+
+        apply(&mut channel, "+o-v ghost ghost", &isupport);
+        assert!(!channel.is_member("ghost"));
+
+        channel.rename_member("other", "renamed");
+        channel.remove_member("nick");
+        assert_eq!(channel.members().collect::<Vec<_>>(), vec!["renamed"]);
+        assert_eq!(channel.prefix("renamed", &isupport), Some('+'));
+    }
+}
@@ -1,4 +1,11 @@
-use irc_async::Config as IrcConfig;
+use std::convert::TryFrom;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Error};
+use irc_async::{proto::Encoding, Config as IrcConfig, Proxy, ProxyKind, TlsConfig};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -6,16 +13,154 @@ pub struct Config {
     host: String,
     nick: String,
     port: u16,
+    /// Other servers of the network, to try in order if the first can't be reached.
+    #[serde(default)]
+    fallback_servers: Vec<Server>,
+    /// The local address to connect from.
+    #[serde(default)]
+    bind_address: Option<IpAddr>,
+    /// How many seconds to wait for each connection attempt.
+    #[serde(default)]
+    connect_timeout: Option<u64>,
+    /// Whether to race connections to IPv6 and IPv4 addresses, rather than trying them in turn.
+    /// Defaults to true.
+    #[serde(default)]
+    happy_eyeballs: Option<bool>,
+    /// The proxy to connect through, if any.
+    #[serde(default)]
+    proxy: Option<ProxyConfig>,
     ssl: bool,
+    /// The path of the server's IRC-over-WebSocket endpoint, like `/webirc`, to connect with a
+    /// WebSocket instead of directly.
+    #[serde(default)]
+    websocket_path: Option<String>,
+    /// A PKCS #12 archive with a client certificate to identify with, for CertFP or SASL EXTERNAL.
+    #[serde(default)]
+    client_cert: Option<PathBuf>,
+    /// The password the client certificate archive is encrypted with.
+    #[serde(default)]
+    client_cert_password: String,
+    /// Certificate authorities to trust besides the system's, as PEM or DER files.
+    #[serde(default)]
+    ca_certs: Vec<PathBuf>,
+    /// The SHA-256 fingerprint of the server's certificate, to trust it whoever signed it.
+    #[serde(default)]
+    pinned_fingerprint: Option<String>,
+    /// The encoding of lines that aren't UTF-8, like `latin1` or `cp1252`.
+    #[serde(default)]
+    fallback_encoding: Option<String>,
+    /// How many seconds to wait between keepalive PINGs, or 0 to never send them. Defaults to 60.
+    #[serde(default)]
+    ping_interval: Option<u64>,
+    /// How many seconds to wait for the server to answer a PING. Defaults to 30.
+    #[serde(default)]
+    ping_timeout: Option<u64>,
+    /// Whether to answer CTCP queries like `VERSION` and `PING`.
+    #[serde(default)]
+    ctcp_replies: bool,
+    /// Whether to download files offered with DCC SEND, and attach them to a message.
+    #[serde(default)]
+    dcc: bool,
+    /// The message to leave the server with when flubber shuts down.
+    #[serde(default)]
+    quit_message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Server {
+    host: String,
+    port: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    kind: ProxyConfigKind,
+    host: String,
+    port: u16,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyConfigKind {
+    Socks5,
+    Http,
 }
 
-impl From<&Config> for IrcConfig {
-    fn from(config: &Config) -> Self {
-        IrcConfig {
+impl From<&ProxyConfig> for Proxy {
+    fn from(config: &ProxyConfig) -> Self {
+        Proxy {
+            kind: match config.kind {
+                ProxyConfigKind::Socks5 => ProxyKind::Socks5,
+                ProxyConfigKind::Http => ProxyKind::HttpConnect,
+            },
+            host: config.host.clone(),
+            port: config.port,
+            credentials: config
+                .username
+                .clone()
+                .map(|username| (username, config.password.clone().unwrap_or_default())),
+        }
+    }
+}
+
+impl Config {
+    /// Whether to download files offered with DCC SEND.
+    pub fn accepts_dcc(&self) -> bool {
+        self.dcc
+    }
+
+    /// The message to leave the server with.
+    pub fn quit_message(&self) -> Option<String> {
+        self.quit_message.clone()
+    }
+}
+
+impl TryFrom<&Config> for IrcConfig {
+    type Error = Error;
+
+    fn try_from(config: &Config) -> Result<Self, Self::Error> {
+        let fallback_encoding = match config.fallback_encoding {
+            Some(ref encoding) => encoding.parse()?,
+            None => Encoding::default(),
+        };
+        let read = |path: &PathBuf| {
+            fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))
+        };
+        let tls = TlsConfig {
+            client_cert: config.client_cert.as_ref().map(read).transpose()?,
+            client_cert_password: config.client_cert_password.clone(),
+            ca_certs: config.ca_certs.iter().map(read).collect::<Result<_, _>>()?,
+            pinned_fingerprint: config.pinned_fingerprint.clone(),
+        };
+        Ok(IrcConfig {
             host: config.host.clone(),
             nick: config.nick.clone(),
             port: config.port,
+            fallback_servers: config
+                .fallback_servers
+                .iter()
+                .map(|server| (server.host.clone(), server.port))
+                .collect(),
+            bind_address: config.bind_address,
+            connect_timeout: config.connect_timeout.map(Duration::from_secs),
+            happy_eyeballs: config.happy_eyeballs.unwrap_or(true),
+            proxy: config.proxy.as_ref().map(Proxy::from),
             ssl: config.ssl,
-        }
+            websocket_path: config.websocket_path.clone(),
+            tls,
+            fallback_encoding,
+            ping_interval: match config.ping_interval {
+                Some(0) => None,
+                interval => Some(Duration::from_secs(interval.unwrap_or(60))),
+            },
+            ping_timeout: Duration::from_secs(config.ping_timeout.unwrap_or(30)),
+            ctcp_replies: config.ctcp_replies,
+            // the IDs of sent messages come from their echoes
+            echo_message: true,
+        })
     }
 }
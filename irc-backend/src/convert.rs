@@ -0,0 +1,366 @@
+use irc_async::proto::{
+    message::Tag, Command, Message as IrcMessage, Response as IrcResponse, Source, StandardReply,
+};
+use mime::Mime;
+use proto::backend::{MessageContent, MessageID, ResponseError, ResponseErrorKind, UserID};
+use serde_json::{json, Value as JsonValue};
+
+/// The IRCv3 client tag marking a message as a reply to another message.
+pub const REPLY_TAG: &str = "+draft/reply";
+
+/// The IRCv3 tag carrying the server-assigned ID of a message.
+pub const MSGID_TAG: &str = "msgid";
+
+/// The IRCv3 tag carrying the account the sender is logged in to.
+pub const ACCOUNT_TAG: &str = "account";
+
+/// Looks up the value of a tag in the tags of an IRC message.
+pub fn tag<'a>(tags: &'a Option<Vec<Tag>>, key: &str) -> Option<&'a str> {
+    tags.as_ref()?
+        .iter()
+        .find(|Tag(k, _)| k == key)
+        .and_then(|Tag(_, v)| v.as_deref())
+}
+
+/// Builds the ID of the sender of a message. Users are known by their nick, which is also what
+/// messages to them are addressed to, so a conversation has the same ID both ways.
+pub fn sender_id(source: &Source) -> UserID {
+    match source.nick() {
+        Some(nick) => UserID(nick.to_owned()),
+        // messages from servers have no nick, so use the server name
+        None => UserID(source.to_string()),
+    }
+}
+
+/// Builds the extra data of an incoming message, which has the account the sender is logged in
+/// to, if the server sent the account tag.
+pub fn message_extra(tags: &Option<Vec<Tag>>) -> JsonValue {
+    match tag(tags, ACCOUNT_TAG) {
+        Some(account) => json!({ "account": account }),
+        None => JsonValue::Null,
+    }
+}
+
+/// Builds the tags for an outgoing message replying to `reply_to`, if any.
+pub fn reply_tags(reply_to: Option<&MessageID>) -> Option<Vec<Tag>> {
+    reply_to.map(|id| vec![Tag(REPLY_TAG.to_owned(), Some(id.0.clone()))])
+}
+
+/// Flattens message content into IRC-formatted text.
+pub fn content_to_text(content: &MessageContent) -> String {
+    let mut text = String::new();
+    push_content(&mut text, content);
+    text
+}
+
+fn push_content(text: &mut String, content: &MessageContent) {
+    match content {
+        MessageContent::Bold(inner) => push_formatted(text, '\x02', inner),
+        MessageContent::Italic(inner) => push_formatted(text, '\x1D', inner),
+        MessageContent::Underline(inner) => push_formatted(text, '\x1F', inner),
+        MessageContent::Crossout(inner) => push_formatted(text, '\x1E', inner),
+        MessageContent::Concat(parts) => parts.iter().for_each(|part| push_content(text, part)),
+        MessageContent::Emote(name) => {
+            text.push(':');
+            text.push_str(name);
+            text.push(':');
+        }
+        MessageContent::MessageLink(MessageID(id)) => text.push_str(id),
+        MessageContent::RoomLink(room) => text.push_str(&room.0),
+        MessageContent::UserLink(user) => text.push_str(&user.0),
+        MessageContent::Text(s) | MessageContent::UrlLink(s) => text.push_str(s),
+    }
+}
+
+fn push_formatted(text: &mut String, code: char, inner: &MessageContent) {
+    text.push(code);
+    push_content(text, inner);
+    text.push(code);
+}
+
+/// Classifies an IRC numeric reply as a flubber error, or returns `None` if it isn't an error.
+pub fn error_kind(response: IrcResponse) -> Option<ResponseErrorKind> {
+    use irc_async::proto::Response::*;
+
+    Some(match response {
+        ERR_NOSUCHNICK | ERR_NOSUCHSERVER | ERR_NOSUCHCHANNEL | ERR_WASNOSUCHNICK
+        | ERR_NOSUCHSERVICE | ERR_USERNOTINCHANNEL | ERR_NOTONCHANNEL | ERR_NOLOGIN
+        | ERR_TARGETINVALID | ERR_NOMATCHINGKEY | ERR_KEYNOTSET => ResponseErrorKind::NotFound,
+        ERR_CANNOTSENDTOCHAN
+        | ERR_TOOMANYCHANNELS
+        | ERR_NOPERMFORHOST
+        | ERR_PASSWDMISMATCH
+        | ERR_YOUREBANNEDCREEP
+        | ERR_YOUWILLBEBANNED
+        | ERR_CHANNELISFULL
+        | ERR_INVITEONLYCHAN
+        | ERR_BANNEDFROMCHAN
+        | ERR_BADCHANNELKEY
+        | ERR_NOPRIVILEGES
+        | ERR_CHANOPRIVSNEEDED
+        | ERR_CANTKILLSERVER
+        | ERR_RESTRICTED
+        | ERR_UNIQOPPRIVSNEEDED
+        | ERR_NOOPERHOST
+        | ERR_USERSDONTMATCH
+        | ERR_NOPRIVS
+        | ERR_KEYNOPERMISSION
+        | ERR_NICKLOCKED
+        | ERR_SASLFAIL => ResponseErrorKind::Forbidden,
+        RPL_TRYAGAIN | ERR_TOOMANYTARGETS => ResponseErrorKind::RateLimited(None),
+        ERR_UNKNOWNCOMMAND | ERR_SUMMONDISABLED | ERR_USERSDISABLED | ERR_UNKNOWNMODE
+        | ERR_NOCHANMODES | ERR_UMODEUNKNOWNFLAG => ResponseErrorKind::Unsupported,
+        ERR_NOORIGIN
+        | ERR_NORECIPIENT
+        | ERR_NOTEXTTOSEND
+        | ERR_NOTOPLEVEL
+        | ERR_WILDTOPLEVEL
+        | ERR_BADMASK
+        | ERR_NONICKNAMEGIVEN
+        | ERR_ERRONEOUSNICKNAME
+        | ERR_NICKNAMEINUSE
+        | ERR_NICKCOLLISION
+        | ERR_UNAVAILRESOURCE
+        | ERR_USERONCHANNEL
+        | ERR_NEEDMOREPARAMS
+        | ERR_ALREADYREGISTRED
+        | ERR_KEYSET
+        | ERR_BADCHANMASK
+        | ERR_BANLISTFULL
+        | ERR_MONLISTFULL
+        | ERR_METADATALIMIT
+        | ERR_KEYINVALID
+        | ERR_SASLTOOLONG
+        | ERR_SASLALREADY => ResponseErrorKind::InvalidRequest,
+        ERR_NOTREGISTERED => ResponseErrorKind::BackendDisconnected,
+        response if response.is_error() => ResponseErrorKind::Internal,
+        _ => return None,
+    })
+}
+
+/// Converts a standard reply into a flubber error, classified by its code.
+pub fn reply_error(reply: &StandardReply) -> ResponseError {
+    let kind = match &reply.code[..] {
+        "INVALID_TARGET" | "UNKNOWN_MSGID" | "MULTILINE_INVALID_TARGET" => {
+            ResponseErrorKind::NotFound
+        }
+        "ACCOUNT_REQUIRED" | "CANNOT_CHANGE_REALNAME" | "REDACT_WINDOW_EXPIRED" => {
+            ResponseErrorKind::Forbidden
+        }
+        code if code.ends_with("_FORBIDDEN") => ResponseErrorKind::Forbidden,
+        "RATE_LIMITED" | "TEMPORARILY_UNAVAILABLE" => ResponseErrorKind::RateLimited(None),
+        "UNKNOWN_COMMAND" | "UNKNOWN_SUBCOMMAND" => ResponseErrorKind::Unsupported,
+        "INVALID_PARAMS" | "NEED_MORE_PARAMS" | "INVALID_UTF8" | "INVALID_REALNAME" => {
+            ResponseErrorKind::InvalidRequest
+        }
+        code if code.starts_with("MULTILINE_") => ResponseErrorKind::InvalidRequest,
+        _ => ResponseErrorKind::Internal,
+    };
+    ResponseError::new(
+        kind,
+        format!("{} ({} {})", reply.description, reply.command, reply.code),
+    )
+}
+
+/// Finds the first failure among the server's replies to a request, if any. Standard replies only
+/// count if they're about one of the commands the request sent, since others can't be told apart
+/// from failures of something else.
+pub fn replies_error(replies: &[IrcMessage], commands: &[&str]) -> Option<ResponseError> {
+    replies.iter().find_map(|reply| match reply.command {
+        Command::Response(response, ref args, ref last_arg) => {
+            let description = last_arg.as_ref().or_else(|| args.last());
+            error_kind(response)
+                .map(|kind| ResponseError::new(kind, description.cloned().unwrap_or_default()))
+        }
+        Command::StandardReply(ref reply)
+            if reply.is_failure() && commands.iter().any(|command| reply.is_about(command)) =>
+        {
+            Some(reply_error(reply))
+        }
+        _ => None,
+    })
+}
+
+/// Finds the ID the server gave a message we sent, from its echo among the replies. Messages sent
+/// as a `draft/multiline` batch are echoed as a batch, with the ID on the batch.
+pub fn echoed_msgid(replies: &[IrcMessage]) -> Option<MessageID> {
+    replies.iter().find_map(|reply| match reply.command {
+        Command::PRIVMSG(..) | Command::NOTICE(..) | Command::BATCH(..) => {
+            tag(&reply.tags, MSGID_TAG).map(|id| MessageID(id.to_owned()))
+        }
+        _ => None,
+    })
+}
+
+/// Guesses the MIME type of a file from the magic bytes it starts with, falling back to plain text
+/// if it's UTF-8, and to `application/octet-stream` otherwise.
+pub fn sniff_mime(data: &[u8]) -> Mime {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"ID3", "audio/mpeg"),
+        (b"\xff\xfb", "audio/mpeg"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+    ];
+
+    let mime = match MAGIC.iter().find(|(magic, _)| data.starts_with(magic)) {
+        Some((_, mime)) => mime,
+        None if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") => "image/webp",
+        None if data.get(4..8) == Some(b"ftyp") => "video/mp4",
+        None if !data.contains(&0) && std::str::from_utf8(data).is_ok() => {
+            return mime::TEXT_PLAIN_UTF_8
+        }
+        None => return mime::APPLICATION_OCTET_STREAM,
+    };
+    mime.parse().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use irc_async::proto::{Command, Message as IrcMessage};
+    use proto::backend::{MessageID, ResponseErrorKind, UserID};
+    use serde_json::{json, Value as JsonValue};
+
+    use super::{echoed_msgid, error_kind, message_extra, replies_error, reply_error, sender_id};
+
+    fn messages(lines: &[&str]) -> Vec<IrcMessage> {
+        lines.iter().map(|line| line.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn echoed_ids() {
+        let replies = messages(&["@msgid=abc :me!u@h PRIVMSG #rust :hello"]);
+        assert_eq!(echoed_msgid(&replies), Some(MessageID("abc".to_owned())));
+        let replies = messages(&[
+            "@label=1;msgid=xyz :me!u@h BATCH +b draft/multiline #rust",
+            "@batch=b :me!u@h PRIVMSG #rust :hello",
+        ]);
+        assert_eq!(echoed_msgid(&replies), Some(MessageID("xyz".to_owned())));
+        // without echo-message, there's nothing to go on
+        let replies = messages(&[":irc.test.net 404 me #rust :Cannot send to channel"]);
+        assert_eq!(echoed_msgid(&replies), None);
+    }
+
+    #[test]
+    fn senders() {
+        let logged_in: IrcMessage = "@account=acct :alice!u@h PRIVMSG me :hi".parse().unwrap();
+        let logged_out: IrcMessage = ":alice!u@h PRIVMSG me :hi".parse().unwrap();
+        // the same ID whether or not they're logged in, as for messages we send them
+        for message in &[&logged_in, &logged_out] {
+            let source = message.prefix.as_ref().unwrap();
+            assert_eq!(sender_id(source), UserID("alice".to_owned()));
+        }
+        assert_eq!(message_extra(&logged_in.tags), json!({"account": "acct"}));
+        assert_eq!(message_extra(&logged_out.tags), JsonValue::Null);
+    }
+
+    #[test]
+    fn numerics() {
+        use irc_async::proto::Response::*;
+
+        let table = &[
+            (ERR_NOSUCHNICK, Some(ResponseErrorKind::NotFound)),
+            (ERR_NOSUCHCHANNEL, Some(ResponseErrorKind::NotFound)),
+            (ERR_NOTONCHANNEL, Some(ResponseErrorKind::NotFound)),
+            (ERR_CANNOTSENDTOCHAN, Some(ResponseErrorKind::Forbidden)),
+            (ERR_BANNEDFROMCHAN, Some(ResponseErrorKind::Forbidden)),
+            (ERR_INVITEONLYCHAN, Some(ResponseErrorKind::Forbidden)),
+            (ERR_BADCHANNELKEY, Some(ResponseErrorKind::Forbidden)),
+            (ERR_CHANOPRIVSNEEDED, Some(ResponseErrorKind::Forbidden)),
+            (RPL_TRYAGAIN, Some(ResponseErrorKind::RateLimited(None))),
+            (
+                ERR_TOOMANYTARGETS,
+                Some(ResponseErrorKind::RateLimited(None)),
+            ),
+            (ERR_UNKNOWNCOMMAND, Some(ResponseErrorKind::Unsupported)),
+            (ERR_NOTEXTTOSEND, Some(ResponseErrorKind::InvalidRequest)),
+            (ERR_NEEDMOREPARAMS, Some(ResponseErrorKind::InvalidRequest)),
+            (ERR_NICKNAMEINUSE, Some(ResponseErrorKind::InvalidRequest)),
+            (
+                ERR_NOTREGISTERED,
+                Some(ResponseErrorKind::BackendDisconnected),
+            ),
+            (ERR_NOMOTD, Some(ResponseErrorKind::Internal)),
+            (RPL_WELCOME, None),
+            (RPL_TOPIC, None),
+            (RPL_ENDOFNAMES, None),
+        ];
+        for &(response, kind) in table.iter() {
+            assert_eq!(error_kind(response), kind, "{:?}", response);
+        }
+    }
+
+    #[test]
+    fn standard_replies() {
+        let reply = |line: &str| match line.parse::<IrcMessage>().unwrap().command {
+            Command::StandardReply(reply) => reply,
+            command => panic!("unexpected {:?}", command),
+        };
+        let table = &[
+            (
+                "FAIL PRIVMSG INVALID_TARGET #x :No such channel",
+                ResponseErrorKind::NotFound,
+            ),
+            (
+                "FAIL * ACCOUNT_REQUIRED :Log in first",
+                ResponseErrorKind::Forbidden,
+            ),
+            (
+                "FAIL REDACT REDACT_FORBIDDEN #x abc :No",
+                ResponseErrorKind::Forbidden,
+            ),
+            (
+                "FAIL * RATE_LIMITED :Slow down",
+                ResponseErrorKind::RateLimited(None),
+            ),
+            (
+                "FAIL CHATHISTORY UNKNOWN_COMMAND :What",
+                ResponseErrorKind::Unsupported,
+            ),
+            (
+                "FAIL BATCH MULTILINE_MAX_BYTES 4096 :Too long",
+                ResponseErrorKind::InvalidRequest,
+            ),
+            (
+                "FAIL SETNAME INVALID_REALNAME :Bad",
+                ResponseErrorKind::InvalidRequest,
+            ),
+            (
+                "FAIL PRIVMSG SOMETHING_ELSE :Huh",
+                ResponseErrorKind::Internal,
+            ),
+        ];
+        for &(line, kind) in table.iter() {
+            assert_eq!(reply_error(&reply(line)).kind, kind, "{}", line);
+        }
+    }
+
+    #[test]
+    fn replies_errors() {
+        let replies = messages(&[
+            ":me!u@h JOIN #rust",
+            ":irc.test.net 474 me #rust :Cannot join channel (+b)",
+        ]);
+        let err = replies_error(&replies, &["JOIN"]).unwrap();
+        assert_eq!(err.kind, ResponseErrorKind::Forbidden);
+        assert_eq!(err.message, "Cannot join channel (+b)");
+
+        let replies = messages(&["FAIL PRIVMSG INVALID_TARGET #x :No such channel"]);
+        assert!(replies_error(&replies, &["PRIVMSG"]).is_some());
+        // failures about other commands, or about no command, aren't this request's
+        assert!(replies_error(&replies, &["JOIN"]).is_none());
+        let replies = messages(&["FAIL * ACCOUNT_REQUIRED :Log in first"]);
+        assert!(replies_error(&replies, &["PRIVMSG", "BATCH"]).is_none());
+        // warnings and notes aren't failures
+        let replies = messages(&["WARN PRIVMSG SLOW :Lag"]);
+        assert!(replies_error(&replies, &["PRIVMSG"]).is_none());
+        let replies = messages(&[":irc.test.net 332 me #rust :Topic"]);
+        assert!(replies_error(&replies, &["JOIN"]).is_none());
+    }
+}
@@ -2,23 +2,35 @@
 extern crate anyhow;
 
 mod config;
+mod convert;
 
-use std::collections::BTreeSet;
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use anyhow::Result;
 use chrono::Utc;
+use futures::channel::mpsc;
+use futures::future::{self, Either, FutureExt};
 use futures::sink::SinkExt;
 use futures::stream::StreamExt;
 use irc_async::{
-    proto::{Command, Message as IrcMessage, Response},
-    Client, Config as IrcConfig,
+    dcc,
+    proto::{
+        CaseMap, ChannelExt, Command, Ctcp, Dcc, Message as IrcMessage, Response as IrcResponse,
+    },
+    Client, ClientError, Config as IrcConfig,
 };
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use proto::backend::{
-    InitInfo, Message, MessageContent, MessageID, Request, RequestBody, Room, RoomID,
-    RoomIDOrUserID, Update, UserID, Version,
+    AttachmentChunk, AttachmentID, Capabilities, InitInfo, Message, MessageAttachment,
+    MessageContent, MessageID, MessageKind, Request, RequestBody, Response, ResponseBody,
+    ResponseError, ResponseErrorKind, ResponseOrUpdate, Room, RoomID, RoomIDOrUserID, Update,
+    UserID, Version, ATTACHMENT_CHUNK_SIZE,
 };
 use serde_json::Value as JsonValue;
 use structopt::StructOpt;
@@ -32,11 +44,32 @@ use toml::Value as TomlValue;
 use uuid::Uuid;
 
 use crate::config::Config;
+use crate::convert::{
+    content_to_text, echoed_msgid, message_extra, replies_error, reply_tags, sender_id, sniff_mime,
+    tag, MSGID_TAG, REPLY_TAG,
+};
 
 lazy_static! {
-    static ref JOIN_REQUESTS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    /// The channels we're in, with the IDs flubber knows them by. Channel names are compared the
+    /// way the server does, so `#Rust` and `#rust` are the same room.
+    static ref ROOMS: Mutex<CaseMap<RoomID>> = Mutex::new(CaseMap::default());
+
+    /// Files received over DCC, for flubber to fetch.
+    static ref ATTACHMENTS: Mutex<Attachments> = Mutex::new(Attachments::default());
 }
 
+/// How many DCC transfers are running.
+static TRANSFERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The largest file we accept over DCC, since it's kept in memory for flubber to fetch.
+const MAX_DCC_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How many DCC transfers we run at once. Offers made while that many are running are ignored.
+const MAX_DCC_TRANSFERS: usize = 4;
+
+/// How many bytes of received files we keep for flubber. Past that, the oldest are dropped.
+const MAX_ATTACHMENTS_SIZE: u64 = 256 * 1024 * 1024;
+
 #[derive(Debug, StructOpt)]
 struct Args {
     #[structopt(long = "config", parse(from_os_str))]
@@ -71,6 +104,8 @@ async fn main() -> Result<()> {
                     env!("CARGO_PKG_VERSION_PATCH").parse().unwrap(),
                 ),
                 protocol_version: Version(0, 1, 0),
+                // edits, redactions and reactions all need IRCv3 extensions we don't negotiate
+                capabilities: Capabilities::default(),
             })
             .unwrap(),
         )
@@ -85,96 +120,445 @@ async fn main() -> Result<()> {
         .ok_or_else(|| anyhow!("Backend has no config"))?
         .clone()
         .try_into::<Config>()?;
-    let irc_config = IrcConfig::from(&backend_config);
+    let irc_config = IrcConfig::try_from(&backend_config)?;
 
-    let (mut client, fut, client_tx) = Client::with_config(irc_config).await?;
+    let (mut client, mut fut, _) = Client::with_config(irc_config).await?;
     client.register().await?;
-    tokio::spawn(fut);
+
+    let (out_tx, out_rx) = mpsc::unbounded::<ResponseOrUpdate>();
+    let writer = tokio::spawn(
+        out_rx
+            .map(|message| Ok(serde_json::to_value(message).unwrap()))
+            .forward(stdout)
+            .map(|_| ()),
+    );
 
     let mut stdin = Framed::<_, Request, (), _>::new(
         FramedRead::new(io::stdin(), BytesCodec::new()),
         Json::<Request, ()>::default(),
     );
-    let stdin_loop = async move {
-        let mut client_tx = client_tx.clone();
-        while let Some(Ok(request)) = stdin.next().await {
-            match request.body {
-                RequestBody::RoomJoin(room_id) => {
-                    JOIN_REQUESTS.lock().insert(room_id.0.clone());
-                    client_tx
-                        .send(IrcMessage {
-                            tags: None,
-                            prefix: None,
-                            command: Command::JOIN(room_id.0, None, None),
-                        })
-                        .await;
+
+    // main loop, until flubber asks us to shut down or goes away
+    let shutdown = loop {
+        let next = future::select(&mut fut, future::select(client.next(), stdin.next())).await;
+        match next {
+            // the connection is gone, such as after a ping timeout
+            Either::Left((result, _)) => return result.map_err(Into::into),
+            Either::Right((Either::Left((Some(Ok(message)), _)), _)) => {
+                handle_message(&client, &backend_config, &out_tx, message)
+            }
+            // a line that couldn't be parsed or sent doesn't end the connection
+            Either::Right((Either::Left((Some(Err(err)), _)), _)) if !err.is_fatal() => {
+                eprintln!("error: {}", err)
+            }
+            Either::Right((Either::Right((Some(Ok(request)), _)), _)) => {
+                if let RequestBody::Shutdown = request.body {
+                    break Some(request.sequence_number);
                 }
-                _ => (),
+                handle_request(&client, &out_tx, request)
+            }
+            // neither does a request that couldn't be read
+            Either::Right((Either::Right((Some(Err(err)), _)), _)) => {
+                eprintln!("error: bad request: {}", err)
             }
+            // flubber closed our stdin, or the server the connection
+            _ => break None,
         }
     };
-    tokio::spawn(stdin_loop);
-
-    // main loop
-    async {
-        while let Some(Ok(message)) = client.next().await {
-            let flubber_message = match message.command {
-                Command::PRIVMSG(target, content) => {
-                    let recipient = if target.starts_with('&') || target.starts_with('#') {
-                        RoomIDOrUserID::Room(RoomID(target))
-                    } else {
-                        RoomIDOrUserID::User(UserID(target))
-                    };
-                    let new_message = Message {
-                        attachments: Vec::new(),
-                        content: MessageContent::Text(content),
-                        create_time: Utc::now(),
-                        edit_time: Utc::now(),
-                        extra: JsonValue::Null,
-                        id: MessageID(Uuid::new_v4().to_string()),
-                        sender: UserID(message.prefix.unwrap()),
-                        recipient,
+
+    // leave the server, writing out anything still queued first
+    let quit = future::try_join(client.quit(backend_config.quit_message()), &mut fut).await;
+    let body = match quit {
+        Ok(_) => ResponseBody::Success,
+        Err(ref err) => ResponseBody::Error(ResponseError::new(
+            ResponseErrorKind::BackendDisconnected,
+            err.to_string(),
+        )),
+    };
+    if let Some(sequence_number) = shutdown {
+        let _ = out_tx.unbounded_send(ResponseOrUpdate::Response(Response {
+            sequence_number,
+            body,
+        }));
+    }
+    // let everything queued for flubber be written before exiting
+    out_tx.close_channel();
+    let _ = writer.await;
+    quit.map(|_| ()).map_err(Into::into)
+}
+
+/// Handles a request from flubber, answering it once the server replies.
+fn handle_request(
+    client: &Client,
+    out_tx: &mpsc::UnboundedSender<ResponseOrUpdate>,
+    request: Request,
+) {
+    let sequence_number = request.sequence_number;
+    let respond = move |out_tx: &mpsc::UnboundedSender<ResponseOrUpdate>, body| {
+        let _ = out_tx.unbounded_send(ResponseOrUpdate::Response(Response {
+            sequence_number,
+            body,
+        }));
+    };
+    let disconnected = |err: ClientError| {
+        ResponseBody::Error(ResponseError::new(
+            ResponseErrorKind::BackendDisconnected,
+            err.to_string(),
+        ))
+    };
+    match request.body {
+        RequestBody::RoomJoin(room_id) => {
+            let replies = client.request(IrcMessage {
+                tags: None,
+                prefix: None,
+                command: Command::JOIN(room_id.0.clone(), None, None),
+            });
+            let out_tx = out_tx.clone();
+            tokio::spawn(async move {
+                let error = match replies.await {
+                    Ok(replies) => replies_error(&replies, &["JOIN"]).map(ResponseBody::Error),
+                    Err(err) => Some(disconnected(err)),
+                };
+                if let Some(error) = error {
+                    respond(&out_tx, error);
+                } else {
+                    respond(&out_tx, ResponseBody::Success);
+                    // joining a room we're already in under another spelling keeps its ID
+                    let room_id = ROOMS
+                        .lock()
+                        .get_or_insert_with(room_id.0.as_str(), || room_id.clone())
+                        .clone();
+                    let new_room = Room {
+                        name: room_id.0.clone(),
+                        id: room_id,
+                        parent: None,
+                        sendable: true, // todo
                     };
-                    Some(Update::MessageUpsert(new_message))
+                    let _ = out_tx
+                        .unbounded_send(ResponseOrUpdate::Update(Update::RoomUpsert(new_room)));
                 }
-                Command::Response(response, mut args, last_arg) => {
-                    eprintln!("response: {:?} {:?} {:?}", response, args, last_arg);
-                    if let Some(arg) = last_arg {
-                        args.push(arg);
-                    }
-                    match response {
-                        Response::RPL_TOPIC => {
-                            let mut args = args.into_iter();
-                            // check if the channel we joined is actually in the list of channels we requested
-                            if let Some(channel_name) = args.next() {
-                                let mut join_requests = JOIN_REQUESTS.lock();
-                                if join_requests.contains(&channel_name) {
-                                    join_requests.remove(&channel_name);
-                                    let new_room = Room {
-                                        id: RoomID(channel_name.to_string()),
-                                        name: channel_name.to_string(),
-                                        parent: None,
-                                        sendable: true, // todo
-                                    };
-                                    Some(Update::RoomUpsert(new_room))
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
-                            }
+            });
+        }
+        RequestBody::MessageSend(new_message) => {
+            let target = match new_message.recipient {
+                RoomIDOrUserID::Room(room_id) => room_id.0,
+                RoomIDOrUserID::User(user_id) => user_id.0,
+            };
+            let text = content_to_text(&new_message.content);
+            let tags = reply_tags(new_message.reply_to.as_ref());
+            let messages = match new_message.kind {
+                MessageKind::Normal => client.split_message(Command::PRIVMSG, &target, &text, tags),
+                MessageKind::Action => client.split_message(
+                    |target, text| Ctcp::Action(text).query(&target),
+                    &target,
+                    &text,
+                    tags,
+                ),
+            };
+            let replies = client.request_all(messages);
+            let out_tx = out_tx.clone();
+            tokio::spawn(async move {
+                let body = match replies.await {
+                    Ok(replies) => match replies_error(&replies, &["PRIVMSG", "BATCH"]) {
+                        Some(err) => ResponseBody::Error(err),
+                        None => match echoed_msgid(&replies) {
+                            Some(id) => ResponseBody::MessageID(id),
+                            // without echo-message and message-tags, the server never says
+                            None => ResponseBody::Success,
+                        },
+                    },
+                    Err(err) => disconnected(err),
+                };
+                respond(&out_tx, body);
+            });
+        }
+        RequestBody::MessageEdit(_) => respond(
+            out_tx,
+            ResponseBody::Error(ResponseError::unsupported("IRC messages cannot be edited")),
+        ),
+        RequestBody::MessageDelete(_) => respond(
+            out_tx,
+            ResponseBody::Error(ResponseError::unsupported("IRC messages cannot be deleted")),
+        ),
+        RequestBody::ReactionAdd(_) | RequestBody::ReactionRemove(_) => respond(
+            out_tx,
+            ResponseBody::Error(ResponseError::unsupported("IRC has no reactions")),
+        ),
+        RequestBody::MessageHistory(_) => respond(
+            out_tx,
+            ResponseBody::Error(ResponseError::unsupported("IRC servers don't keep history")),
+        ),
+        RequestBody::AttachmentGet(range) => {
+            let attachments = ATTACHMENTS.lock();
+            let body = match attachments.get(&range.id) {
+                Some(data) => {
+                    let start = cmp::min(range.offset, data.len() as u64) as usize;
+                    let len = cmp::min(range.length, ATTACHMENT_CHUNK_SIZE) as usize;
+                    let end = cmp::min(start + len, data.len());
+                    ResponseBody::AttachmentChunk(AttachmentChunk {
+                        id: range.id.clone(),
+                        offset: range.offset,
+                        data: data[start..end].to_vec(),
+                        last: end == data.len(),
+                    })
+                }
+                None => ResponseBody::Error(ResponseError::new(
+                    ResponseErrorKind::NotFound,
+                    "no file was received with that ID",
+                )),
+            };
+            respond(out_tx, body);
+        }
+        RequestBody::AttachmentPut(_) => respond(
+            out_tx,
+            ResponseBody::Error(ResponseError::unsupported(
+                "IRC can only receive files, over DCC",
+            )),
+        ),
+        _ => (),
+    }
+}
+
+/// Handles a message from the server that isn't a reply to one of our requests.
+fn handle_message(
+    client: &Client,
+    config: &Config,
+    out_tx: &mpsc::UnboundedSender<ResponseOrUpdate>,
+    message: IrcMessage,
+) {
+    let mapping = ROOMS.lock().case_mapping();
+    let nick = client.nick();
+    let from_self = match message.source_nickname() {
+        Some(source) => mapping.eq(source, &nick),
+        None => false,
+    };
+    let flubber_message = match message.command {
+        Command::PRIVMSG(target, text) if message.prefix.is_some() => {
+            let recipient = if target.is_channel_name() {
+                let room_id = ROOMS.lock().get(&target).cloned();
+                RoomIDOrUserID::Room(room_id.unwrap_or(RoomID(target)))
+            } else {
+                RoomIDOrUserID::User(UserID(target))
+            };
+            let sender = sender_id(message.prefix.as_ref().unwrap());
+            let (kind, content) = match text.parse() {
+                Ok(Ctcp::Action(action)) => (MessageKind::Action, action),
+                Ok(ctcp) => {
+                    match Dcc::from_ctcp(&ctcp) {
+                        Some(offer) if config.accepts_dcc() && !from_self => {
+                            receive_file(client.config(), out_tx, offer, sender, recipient)
                         }
-                        _ => None,
+                        // other CTCP messages are queries meant for the client, not the user
+                        _ => (),
                     }
+                    return;
                 }
-                _ => None,
+                Err(_) => (MessageKind::Normal, text),
             };
-            if let Some(message) = flubber_message {
-                stdout.send(serde_json::to_value(message).unwrap()).await;
+            let id = tag(&message.tags, MSGID_TAG)
+                .map(str::to_owned)
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+            let reply_to = tag(&message.tags, REPLY_TAG).map(|id| MessageID(id.to_owned()));
+            let new_message = Message {
+                attachments: Vec::new(),
+                content: MessageContent::Text(content),
+                kind,
+                reply_to,
+                thread: None,
+                create_time: Utc::now(),
+                edit_time: Utc::now(),
+                extra: message_extra(&message.tags),
+                id: MessageID(id),
+                sender,
+                recipient,
+            };
+            Some(Update::MessageUpsert(new_message))
+        }
+        Command::StandardReply(reply) => {
+            eprintln!("standard reply: {:?}", reply);
+            None
+        }
+        Command::PART(channel, _) if from_self => {
+            let _ = ROOMS.lock().remove(&channel);
+            None
+        }
+        Command::KICK(channel, kicked, _) if mapping.eq(&kicked, &nick) => {
+            let _ = ROOMS.lock().remove(&channel);
+            None
+        }
+        Command::Response(IrcResponse::RPL_ISUPPORT, ..) => {
+            ROOMS
+                .lock()
+                .set_case_mapping(client.isupport().case_mapping());
+            None
+        }
+        Command::Response(response, args, last_arg) => {
+            eprintln!("response: {:?} {:?} {:?}", response, args, last_arg);
+            None
+        }
+        _ => None,
+    };
+    if let Some(update) = flubber_message {
+        let _ = out_tx.unbounded_send(ResponseOrUpdate::Update(update));
+    }
+}
+
+/// Downloads a file offered with DCC SEND, and once it's complete, sends a message with the file
+/// attached, as if the sender had sent it to the recipient.
+fn receive_file(
+    irc_config: &IrcConfig,
+    out_tx: &mpsc::UnboundedSender<ResponseOrUpdate>,
+    offer: Dcc,
+    sender: UserID,
+    recipient: RoomIDOrUserID,
+) {
+    let (filename, address, size) = match offer {
+        Dcc::Send {
+            ref filename,
+            address,
+            port,
+            size: Some(size),
+            ..
+        } if port != 0 && size <= MAX_DCC_SIZE && is_global(address) => {
+            (filename.clone(), SocketAddr::new(address, port), size)
+        }
+        // reverse offers would need us to be reachable, and we can't tell when others end
+        // and anyone could make us connect to machines only we can reach
+        _ => return eprintln!("ignoring DCC offer: {:?}", offer),
+    };
+    let transfer = match Transfer::start() {
+        Some(transfer) => transfer,
+        None => return eprintln!("ignoring DCC offer of {}: too many transfers", filename),
+    };
+    let irc_config = irc_config.clone();
+    let out_tx = out_tx.clone();
+    tokio::spawn(async move {
+        // the size is only what the sender claims, so the buffer grows as the data arrives
+        let mut data = Vec::new();
+        let received = dcc::receive(&irc_config, address, 0, Some(size), &mut data).await;
+        drop(transfer);
+        match received {
+            Ok(position) if position >= size => data.truncate(size as usize),
+            Ok(position) => {
+                return eprintln!(
+                    "DCC transfer of {} ended at {} of {} bytes",
+                    filename, position, size
+                )
             }
+            Err(err) => return eprintln!("DCC transfer of {} failed: {}", filename, err),
+        }
+        let attachment = MessageAttachment {
+            id: AttachmentID::of(&data),
+            mime: sniff_mime(&data),
+            size,
+            filename: Some(filename.clone()),
+        };
+        ATTACHMENTS.lock().insert(attachment.id.clone(), data);
+        let new_message = Message {
+            attachments: vec![attachment],
+            content: MessageContent::Text(filename),
+            kind: MessageKind::Normal,
+            reply_to: None,
+            thread: None,
+            create_time: Utc::now(),
+            edit_time: Utc::now(),
+            extra: JsonValue::Null,
+            id: MessageID(Uuid::new_v4().to_string()),
+            sender,
+            recipient,
+        };
+        let _ = out_tx.unbounded_send(ResponseOrUpdate::Update(Update::MessageUpsert(new_message)));
+    });
+}
+
+/// Returns whether an address is reachable from anywhere, rather than being loopback, private,
+/// link-local or otherwise local, so DCC offers can't have us connect to services on our network.
+fn is_global(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            let octets = address.octets();
+            !(address.is_unspecified()
+                || address.is_loopback()
+                || address.is_private()
+                || address.is_link_local()
+                || address.is_broadcast()
+                || address.is_multicast()
+                || address.is_documentation()
+                // 0.0.0.0/8, and the shared address space of carrier-grade NAT
+                || octets[0] == 0
+                || (octets[0] == 100 && octets[1] & 0xc0 == 64))
+        }
+        IpAddr::V6(address) => {
+            let first = address.segments()[0];
+            !(address.is_unspecified()
+                || address.is_loopback()
+                || address.is_multicast()
+                // unique local and link-local addresses
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                // IPv4 addresses written as IPv6 ones
+                || matches!(address.to_ipv4(), Some(v4) if !is_global(IpAddr::V4(v4))))
         }
     }
-        .await;
+}
 
-    Ok(())
+/// A DCC transfer that's running, counted in `TRANSFERS` until it's dropped.
+struct Transfer;
+
+impl Transfer {
+    /// Counts a new transfer, unless as many as we allow are already running.
+    fn start() -> Option<Self> {
+        if TRANSFERS.fetch_add(1, Ordering::SeqCst) < MAX_DCC_TRANSFERS {
+            Some(Transfer)
+        } else {
+            let _ = TRANSFERS.fetch_sub(1, Ordering::SeqCst);
+            None
+        }
+    }
+}
+
+impl Drop for Transfer {
+    fn drop(&mut self) {
+        let _ = TRANSFERS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Files received over DCC, kept in memory up to `MAX_ATTACHMENTS_SIZE` bytes in all. Files stay
+/// after flubber has read them, so it can fetch them again if storing its copy failed.
+#[derive(Default)]
+struct Attachments {
+    files: HashMap<AttachmentID, Vec<u8>>,
+    /// The IDs of the files, oldest first.
+    order: VecDeque<AttachmentID>,
+    /// How many bytes the files take up.
+    size: u64,
+}
+
+impl Attachments {
+    fn get(&self, id: &AttachmentID) -> Option<&Vec<u8>> {
+        self.files.get(id)
+    }
+
+    /// Keeps a file, dropping the oldest ones if there's no room for it.
+    fn insert(&mut self, id: AttachmentID, data: Vec<u8>) {
+        let _ = self.remove(&id);
+        self.size += data.len() as u64;
+        self.order.push_back(id.clone());
+        let _ = self.files.insert(id, data);
+        while self.size > MAX_ATTACHMENTS_SIZE {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    let _ = self.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, id: &AttachmentID) -> Option<Vec<u8>> {
+        let data = self.files.remove(id)?;
+        self.order.retain(|other| other != id);
+        self.size -= data.len() as u64;
+        Some(data)
+    }
 }
@@ -53,6 +53,7 @@ use chrono::{DateTime, Utc};
 use mime::Mime;
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
 use std::{
     error::Error,
     fmt::{Display, Formatter},
@@ -71,6 +72,28 @@ pub struct InitInfo {
 
     /// The version of the protocol. This is version `0.1.0`.
     pub protocol_version: Version,
+
+    /// The optional requests the backend supports.
+    #[serde(default)]
+    pub capabilities: Capabilities,
+}
+
+/// The optional requests a backend supports. Requests for unsupported features should be answered
+/// with a `ResponseErrorKind::Unsupported` error.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct Capabilities {
+    /// Whether `RequestBody::MessageEdit` is supported.
+    #[serde(default)]
+    pub message_edit: bool,
+
+    /// Whether `RequestBody::MessageDelete` is supported.
+    #[serde(default)]
+    pub message_delete: bool,
+
+    /// Whether `RequestBody::ReactionAdd` and `RequestBody::ReactionRemove` are supported.
+    #[serde(default)]
+    pub reactions: bool,
 }
 
 /// The version of the backend or protocol.
@@ -124,6 +147,18 @@ pub struct Message {
     /// The body of the message.
     pub content: MessageContent,
 
+    /// What kind of message this is.
+    #[serde(default)]
+    pub kind: MessageKind,
+
+    /// The message this one is a reply to, if any.
+    #[serde(default)]
+    pub reply_to: Option<MessageID>,
+
+    /// The root message of the thread this message is part of, if any.
+    #[serde(default)]
+    pub thread: Option<MessageID>,
+
     /// The time the message was created.
     #[serde(with = "crate::serde::unix_ms")]
     pub create_time: DateTime<Utc>,
@@ -150,22 +185,141 @@ pub struct NewMessage {
     /// The body of the message.
     pub content: MessageContent,
 
+    /// What kind of message this is.
+    #[serde(default)]
+    pub kind: MessageKind,
+
+    /// The message this one is a reply to, if any.
+    #[serde(default)]
+    pub reply_to: Option<MessageID>,
+
+    /// The root message of the thread to post this message in, if any.
+    #[serde(default)]
+    pub thread: Option<MessageID>,
+
     /// Extra backend-specific data.
     #[serde(default)]
     pub extra: Json,
 }
 
-/// MIME-typed data attached to a message.
+/// What kind of message a message is, which clients may display differently.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub enum MessageKind {
+    /// An ordinary message.
+    #[default]
+    Normal,
+
+    /// An action describing what the sender is doing, like `/me waves`.
+    Action,
+}
+
+/// A request to change the contents of an existing message.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct MessageEdit {
+    /// The ID of the message to edit.
+    pub id: MessageID,
+
+    /// The new body of the message.
+    pub content: MessageContent,
+
+    /// Extra backend-specific data.
+    #[serde(default)]
+    pub extra: Json,
+}
+
+/// A reaction left by a user on a message.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct Reaction {
+    /// The message reacted to.
+    pub message: MessageID,
+
+    /// The reacting User.
+    pub sender: UserID,
+
+    /// The emote used to react.
+    pub emote: String,
+}
+
+/// A request to add or remove a reaction on a message.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct NewReaction {
+    /// The message to react to.
+    pub message: MessageID,
+
+    /// The emote to react with.
+    pub emote: String,
+}
+
+/// The maximum number of bytes carried by a single `AttachmentChunk`.
+pub const ATTACHMENT_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// A name for the contents of an attachment: the lowercase hex SHA-256 hash of its data.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct AttachmentID(pub String);
+
+impl AttachmentID {
+    /// Computes the ID of the given attachment data.
+    pub fn of(data: &[u8]) -> AttachmentID {
+        AttachmentID(format!("{:x}", Sha256::digest(data)))
+    }
+}
+
+/// MIME-typed data attached to a message. The data itself is transferred separately with
+/// `RequestBody::AttachmentGet` and `RequestBody::AttachmentPut`.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
 #[sval(derive_from = "serde")]
 pub struct MessageAttachment {
+    /// The content hash of the attachment.
+    pub id: AttachmentID,
+
     /// The mime type of a message.
     #[serde(with = "crate::serde::mime")]
     pub mime: Mime,
 
-    /// The contents of the attachment.
+    /// The size of the attachment in bytes.
+    pub size: u64,
+
+    /// The name of the attached file, if it has one.
+    #[serde(default)]
+    pub filename: Option<String>,
+}
+
+/// A request for part of the data of an attachment.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct AttachmentRange {
+    /// The attachment to read from.
+    pub id: AttachmentID,
+
+    /// The byte offset to start reading at.
+    pub offset: u64,
+
+    /// The maximum number of bytes to read. Responders may return fewer bytes, but never more
+    /// than `ATTACHMENT_CHUNK_SIZE`.
+    pub length: u32,
+}
+
+/// Part of the data of an attachment.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct AttachmentChunk {
+    /// The attachment the data belongs to.
+    pub id: AttachmentID,
+
+    /// The byte offset of this chunk in the attachment.
+    pub offset: u64,
+
+    /// The data, at most `ATTACHMENT_CHUNK_SIZE` bytes.
     #[serde(with = "crate::serde::base64")]
     pub data: Vec<u8>,
+
+    /// Whether this chunk is the end of the attachment.
+    pub last: bool,
 }
 
 /// The contents of a message.
@@ -209,6 +363,79 @@ pub enum MessageContent {
     UserLink(UserID),
 }
 
+/// A query for a page of message history.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct HistoryQuery {
+    /// The Room, or the User whose direct messages, to read history from.
+    pub room: RoomIDOrUserID,
+
+    /// Where to start reading. The anchor itself is not included in the results.
+    pub anchor: HistoryAnchor,
+
+    /// Which way to read from the anchor.
+    pub direction: HistoryDirection,
+
+    /// Where to stop reading, if anywhere before the limit is reached. The end bound itself is not
+    /// included in the results.
+    #[serde(default)]
+    pub end: Option<HistoryAnchor>,
+
+    /// The maximum number of messages to return.
+    pub limit: u32,
+}
+
+/// A point in the history of a room.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[serde(tag = "type", content = "value")]
+#[sval(derive_from = "serde")]
+pub enum HistoryAnchor {
+    /// The newest end of history.
+    Latest,
+
+    /// The position of a message.
+    Message(MessageID),
+
+    /// A point in time. Messages created at exactly this time count as the anchor, so they're not
+    /// included whichever way it bounds the query.
+    Time {
+        /// The time, in milliseconds since the Unix epoch.
+        #[serde(with = "crate::serde::unix_ms")]
+        time: DateTime<Utc>,
+    },
+
+    /// Where a previous page of history left off.
+    Cursor(HistoryCursor),
+}
+
+/// The direction to read history in.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub enum HistoryDirection {
+    /// Towards older messages.
+    Before,
+
+    /// Towards newer messages.
+    After,
+}
+
+/// An opaque token, chosen by whoever served a page of history, for continuing where it left off.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct HistoryCursor(pub String);
+
+/// A page of message history.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, Value)]
+#[sval(derive_from = "serde")]
+pub struct MessagePage {
+    /// The messages, oldest first regardless of the direction of the query.
+    pub messages: Vec<Message>,
+
+    /// The cursor to anchor the next query in the same direction at, or `None` if there is no
+    /// more history in that direction.
+    pub cursor: Option<HistoryCursor>,
+}
+
 /// The information corresponding to a room.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
 #[sval(derive_from = "serde")]
@@ -256,6 +483,12 @@ pub enum Update {
 
     /// Notification that a message was deleted.
     MessageDelete(MessageID),
+
+    /// Notification that a reaction was added to a message.
+    ReactionUpsert(Reaction),
+
+    /// Notification that a reaction was removed from a message.
+    ReactionDelete(Reaction),
 }
 
 /// A request as sent to the backend.
@@ -298,10 +531,10 @@ pub enum ResponseOrUpdate {
 #[serde(tag = "type", content = "value")]
 #[sval(derive_from = "serde")]
 pub enum RequestBody {
-    /// A request to get some number of messages earlier in history than the given one.
+    /// A request to get a page of message history from a room or direct conversation.
     ///
-    /// The only valid non-error response is a `ResponseBody::Success`.
-    MessageGetBefore(MessageID),
+    /// The only valid non-error response is a `ResponseBody::Messages`.
+    MessageHistory(HistoryQuery),
 
     /// A request to get information about a message by ID.
     ///
@@ -310,9 +543,42 @@ pub enum RequestBody {
 
     /// A request to send a message.
     ///
-    /// The only valid non-error response is a `ResponseBody::MessageID`.
+    /// The only valid non-error responses are a `ResponseBody::MessageID`, or a
+    /// `ResponseBody::Success` if the message was sent but the service didn't say what its ID is.
     MessageSend(NewMessage),
 
+    /// A request to edit a message. Requires `Capabilities::message_edit`.
+    ///
+    /// The only valid non-error response is a `ResponseBody::Success`.
+    MessageEdit(MessageEdit),
+
+    /// A request to delete a message. Requires `Capabilities::message_delete`.
+    ///
+    /// The only valid non-error response is a `ResponseBody::Success`.
+    MessageDelete(MessageID),
+
+    /// A request to react to a message. Requires `Capabilities::reactions`.
+    ///
+    /// The only valid non-error response is a `ResponseBody::Success`.
+    ReactionAdd(NewReaction),
+
+    /// A request to remove a reaction from a message. Requires `Capabilities::reactions`.
+    ///
+    /// The only valid non-error response is a `ResponseBody::Success`.
+    ReactionRemove(NewReaction),
+
+    /// A request to read part of an attachment.
+    ///
+    /// The only valid non-error response is a `ResponseBody::AttachmentChunk`.
+    AttachmentGet(AttachmentRange),
+
+    /// A request to store part of an attachment, before it is referenced by a `MessageSend`.
+    /// Chunks must be sent in order, and the attachment is complete once a chunk with `last` set
+    /// has been stored.
+    ///
+    /// The only valid non-error response is a `ResponseBody::Success`.
+    AttachmentPut(AttachmentChunk),
+
     /// A request to get information about a room by ID.
     ///
     /// The only valid non-error response is a `ResponseBody::Room`.
@@ -337,6 +603,12 @@ pub enum RequestBody {
     ///
     /// The only valid non-error response is a `ResponseBody::Success`.
     RoomLeave(RoomID),
+
+    /// A request to disconnect cleanly and exit, because the server is stopping. The backend
+    /// should finish sending anything it has queued before it answers, and exit after.
+    ///
+    /// The only valid non-error response is a `ResponseBody::Success`.
+    Shutdown,
 }
 
 /// The response to a request.
@@ -350,6 +622,9 @@ pub enum ResponseBody {
     /// The request succeeded, resulting in a message.
     Message(Message),
 
+    /// The request succeeded, resulting in a page of messages.
+    Messages(MessagePage),
+
     /// The request succeeded, resulting in a room.
     Room(Room),
 
@@ -359,6 +634,9 @@ pub enum ResponseBody {
     /// The request succeeded, resulting in a room ID.
     RoomID(RoomID),
 
+    /// The request succeeded, resulting in part of an attachment.
+    AttachmentChunk(AttachmentChunk),
+
     /// The request failed.
     Error(ResponseError),
 }
@@ -367,6 +645,10 @@ pub enum ResponseBody {
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize, Value)]
 #[sval(derive_from = "serde")]
 pub struct ResponseError {
+    /// The kind of error.
+    #[serde(default)]
+    pub kind: ResponseErrorKind,
+
     /// An error message.
     pub message: String,
 
@@ -380,6 +662,52 @@ pub struct ResponseError {
     pub retry: bool,
 }
 
+impl ResponseError {
+    /// Creates an error of the given kind. Rate-limited errors are marked as retryable.
+    pub fn new(kind: ResponseErrorKind, message: impl Into<String>) -> ResponseError {
+        ResponseError {
+            kind,
+            message: message.into(),
+            debug_info: Json::Null,
+            retry: matches!(kind, ResponseErrorKind::RateLimited(_)),
+        }
+    }
+
+    /// Creates an error for a request the backend does not support.
+    pub fn unsupported(message: impl Into<String>) -> ResponseError {
+        ResponseError::new(ResponseErrorKind::Unsupported, message)
+    }
+}
+
+/// A machine-readable classification of a `ResponseError`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize, Value)]
+#[serde(tag = "type", content = "value")]
+#[sval(derive_from = "serde")]
+pub enum ResponseErrorKind {
+    /// The message, room or user referred to does not exist.
+    NotFound,
+
+    /// The user is not allowed to perform the request.
+    Forbidden,
+
+    /// The service is rate-limiting requests. Contains the number of milliseconds to wait before
+    /// retrying, if known.
+    RateLimited(Option<u64>),
+
+    /// The backend does not support this request; see `Capabilities`.
+    Unsupported,
+
+    /// The request was malformed or its arguments were rejected by the service.
+    InvalidRequest,
+
+    /// The backend has lost its connection to the service.
+    BackendDisconnected,
+
+    /// Any other error.
+    #[default]
+    Internal,
+}
+
 impl Display for ResponseError {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), std::fmt::Error> {
         fmt.write_str(&self.message)
@@ -391,3 +719,284 @@ impl Error for ResponseError {
         &self.message
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{from_value, json, to_value};
+    use std::fmt::Debug;
+
+    /// Checks that a value serializes to exactly the given JSON, and back.
+    fn round_trip<T>(value: T, json: Json)
+    where
+        T: Debug + DeserializeOwned + PartialEq + Serialize,
+    {
+        assert_eq!(to_value(&value).unwrap(), json);
+        assert_eq!(from_value::<T>(json).unwrap(), value);
+    }
+
+    fn time() -> DateTime<Utc> {
+        Utc.timestamp_millis(1_500_000_000_123)
+    }
+
+    fn message() -> Message {
+        Message {
+            id: MessageID("1".to_string()),
+            sender: UserID("alice".to_string()),
+            recipient: RoomIDOrUserID::Room(RoomID("#general".to_string())),
+            attachments: vec![MessageAttachment {
+                id: AttachmentID::of(b"hi"),
+                mime: mime::TEXT_PLAIN,
+                size: 2,
+                filename: Some("hi.txt".to_string()),
+            }],
+            content: MessageContent::Text("hello".to_string()),
+            kind: MessageKind::Normal,
+            reply_to: Some(MessageID("0".to_string())),
+            thread: None,
+            create_time: time(),
+            edit_time: time(),
+            extra: Json::Null,
+        }
+    }
+
+    fn message_json() -> Json {
+        json!({
+            "id": "1",
+            "sender": "alice",
+            "recipient": "#general",
+            "attachments": [{
+                "id": AttachmentID::of(b"hi").0,
+                "mime": "text/plain",
+                "size": 2,
+                "filename": "hi.txt",
+            }],
+            "content": {"type": "Text", "value": "hello"},
+            "kind": "Normal",
+            "reply_to": "0",
+            "thread": null,
+            "create_time": 1_500_000_000_123u64,
+            "edit_time": 1_500_000_000_123u64,
+            "extra": null,
+        })
+    }
+
+    fn chunk() -> AttachmentChunk {
+        AttachmentChunk {
+            id: AttachmentID::of(b"hi"),
+            offset: 0,
+            data: b"hi".to_vec(),
+            last: true,
+        }
+    }
+
+    fn chunk_json() -> Json {
+        json!({
+            "id": AttachmentID::of(b"hi").0,
+            "offset": 0,
+            "data": "aGk=",
+            "last": true,
+        })
+    }
+
+    #[test]
+    fn history_requests() {
+        round_trip(
+            RequestBody::MessageHistory(HistoryQuery {
+                room: RoomIDOrUserID::Room(RoomID("#general".to_string())),
+                anchor: HistoryAnchor::Cursor(HistoryCursor("abc".to_string())),
+                direction: HistoryDirection::Before,
+                end: Some(HistoryAnchor::Time { time: time() }),
+                limit: 50,
+            }),
+            json!({
+                "type": "MessageHistory",
+                "value": {
+                    "room": "#general",
+                    "anchor": {"type": "Cursor", "value": "abc"},
+                    "direction": "Before",
+                    "end": {"type": "Time", "value": {"time": 1_500_000_000_123u64}},
+                    "limit": 50,
+                },
+            }),
+        );
+        round_trip(
+            HistoryQuery {
+                room: RoomIDOrUserID::Room(RoomID("#rust".to_string())),
+                anchor: HistoryAnchor::Latest,
+                direction: HistoryDirection::After,
+                end: Some(HistoryAnchor::Message(MessageID("1".to_string()))),
+                limit: 1,
+            },
+            json!({
+                "room": "#rust",
+                "anchor": {"type": "Latest"},
+                "direction": "After",
+                "end": {"type": "Message", "value": "1"},
+                "limit": 1,
+            }),
+        );
+        round_trip(
+            ResponseBody::Messages(MessagePage {
+                messages: vec![message()],
+                cursor: Some(HistoryCursor("def".to_string())),
+            }),
+            json!({
+                "type": "Messages",
+                "value": {"messages": [message_json()], "cursor": "def"},
+            }),
+        );
+    }
+
+    #[test]
+    fn edit_requests() {
+        round_trip(
+            RequestBody::MessageEdit(MessageEdit {
+                id: MessageID("1".to_string()),
+                content: MessageContent::Text("edited".to_string()),
+                extra: Json::Null,
+            }),
+            json!({
+                "type": "MessageEdit",
+                "value": {"id": "1", "content": {"type": "Text", "value": "edited"}, "extra": null},
+            }),
+        );
+        round_trip(
+            RequestBody::MessageDelete(MessageID("1".to_string())),
+            json!({"type": "MessageDelete", "value": "1"}),
+        );
+    }
+
+    #[test]
+    fn shutdown() {
+        round_trip(RequestBody::Shutdown, json!({"type": "Shutdown"}));
+    }
+
+    #[test]
+    fn reactions() {
+        let reaction = NewReaction {
+            message: MessageID("1".to_string()),
+            emote: "+1".to_string(),
+        };
+        let reaction_json = json!({"message": "1", "emote": "+1"});
+        round_trip(
+            RequestBody::ReactionAdd(reaction.clone()),
+            json!({"type": "ReactionAdd", "value": reaction_json}),
+        );
+        round_trip(
+            RequestBody::ReactionRemove(reaction),
+            json!({"type": "ReactionRemove", "value": reaction_json}),
+        );
+
+        let reaction = Reaction {
+            message: MessageID("1".to_string()),
+            sender: UserID("alice".to_string()),
+            emote: "+1".to_string(),
+        };
+        let reaction_json = json!({"message": "1", "sender": "alice", "emote": "+1"});
+        round_trip(
+            Update::ReactionUpsert(reaction.clone()),
+            json!({"type": "ReactionUpsert", "value": reaction_json}),
+        );
+        round_trip(
+            Update::ReactionDelete(reaction),
+            json!({"type": "ReactionDelete", "value": reaction_json}),
+        );
+    }
+
+    #[test]
+    fn attachments() {
+        round_trip(
+            RequestBody::AttachmentGet(AttachmentRange {
+                id: AttachmentID::of(b"hi"),
+                offset: 2,
+                length: ATTACHMENT_CHUNK_SIZE,
+            }),
+            json!({
+                "type": "AttachmentGet",
+                "value": {"id": AttachmentID::of(b"hi").0, "offset": 2, "length": 65536},
+            }),
+        );
+        round_trip(
+            RequestBody::AttachmentPut(chunk()),
+            json!({"type": "AttachmentPut", "value": chunk_json()}),
+        );
+        round_trip(
+            ResponseBody::AttachmentChunk(chunk()),
+            json!({"type": "AttachmentChunk", "value": chunk_json()}),
+        );
+        assert_eq!(
+            AttachmentID::of(b"hi").0,
+            "8f434346648f6b96df89dda901c5176b10a6d83961dd3c1ac88b59b2dc327aa4"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        round_trip(
+            ResponseBody::Error(ResponseError::new(
+                ResponseErrorKind::RateLimited(Some(1000)),
+                "slow down",
+            )),
+            json!({
+                "type": "Error",
+                "value": {
+                    "kind": {"type": "RateLimited", "value": 1000},
+                    "message": "slow down",
+                    "debug_info": null,
+                    "retry": true,
+                },
+            }),
+        );
+        round_trip(
+            ResponseErrorKind::RateLimited(None),
+            json!({"type": "RateLimited", "value": null}),
+        );
+        round_trip(ResponseErrorKind::NotFound, json!({"type": "NotFound"}));
+
+        // errors from backends that don't classify them
+        let error: ResponseError = from_value(json!({"message": "oops"})).unwrap();
+        assert_eq!(error.kind, ResponseErrorKind::Internal);
+        assert!(!error.retry);
+    }
+
+    #[test]
+    fn init_info() {
+        // backends that don't list capabilities support none
+        let info: InitInfo = from_value(json!({
+            "backend_name": "Example",
+            "backend_version": [0, 0, 1],
+            "protocol_version": [0, 1, 0],
+        }))
+        .unwrap();
+        assert_eq!(info.capabilities, Capabilities::default());
+        round_trip(
+            Capabilities {
+                message_edit: true,
+                message_delete: false,
+                reactions: true,
+            },
+            json!({"message_edit": true, "message_delete": false, "reactions": true}),
+        );
+    }
+
+    #[test]
+    fn unix_ms() {
+        // positive timestamps arrive as unsigned numbers, and negative ones as signed
+        let at =
+            |ms: Json| from_value::<HistoryAnchor>(json!({"type": "Time", "value": {"time": ms}}));
+        assert_eq!(
+            at(json!(1_500_000_000_123u64)).unwrap(),
+            HistoryAnchor::Time { time: time() }
+        );
+        assert_eq!(
+            at(json!(-1)).unwrap(),
+            HistoryAnchor::Time {
+                time: Utc.timestamp_millis(-1)
+            }
+        );
+        assert!(at(json!(u64::MAX)).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::backend::{RoomID, NewMessage};
+use crate::backend::{MessageEdit, MessageID, NewMessage, NewReaction, RoomID};
 
 pub enum Update {}
 
@@ -10,4 +10,8 @@ pub struct Request {
 pub enum RequestBody {
     RoomJoin(RoomID),
     MessageSend(NewMessage),
+    MessageEdit(MessageEdit),
+    MessageDelete(MessageID),
+    ReactionAdd(NewReaction),
+    ReactionRemove(NewReaction),
 }
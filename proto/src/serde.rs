@@ -66,8 +66,8 @@ pub mod mime {
 /// Serde serialization of UTC datetimes as milliseconds since the Unix epoch.
 pub mod unix_ms {
     use chrono::{DateTime, TimeZone, Utc};
-    use serde::{Deserializer, Serializer};
-    use std::fmt::Formatter;
+    use serde::{de::Unexpected, Deserializer, Serializer};
+    use std::{convert::TryFrom, fmt::Formatter};
 
     struct Visitor;
 
@@ -81,6 +81,12 @@ pub mod unix_ms {
         fn visit_i64<E: serde::de::Error>(self, ts: i64) -> Result<DateTime<Utc>, E> {
             Ok(Utc.timestamp_millis(ts))
         }
+
+        fn visit_u64<E: serde::de::Error>(self, ts: u64) -> Result<DateTime<Utc>, E> {
+            i64::try_from(ts)
+                .map(|ts| Utc.timestamp_millis(ts))
+                .map_err(|_| E::invalid_value(Unexpected::Unsigned(ts), &self))
+        }
     }
 
     /// Deerializes from a timestamp in milliseconds since the Unix epoch.
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use futures::channel::{mpsc::UnboundedSender, oneshot};
+use proto::backend::{
+    Request, RequestBody, Response, ResponseBody, ResponseError, ResponseErrorKind,
+};
+
+use crate::store::Store;
+
+/// A handle to a running backend process, used to send it requests.
+pub struct Backend {
+    name: String,
+    requests: UnboundedSender<Request>,
+    store: Arc<Mutex<Store>>,
+    pending: Mutex<HashMap<u32, oneshot::Sender<ResponseBody>>>,
+    next_sequence_number: AtomicU32,
+}
+
+impl Backend {
+    /// Creates a handle that writes requests to the given channel, and serves history from the
+    /// given store.
+    pub fn new(name: String, requests: UnboundedSender<Request>, store: Arc<Mutex<Store>>) -> Self {
+        Backend {
+            name,
+            requests,
+            store,
+            pending: Mutex::new(HashMap::new()),
+            next_sequence_number: AtomicU32::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sends a request to the backend and waits for the matching response. History requests are
+    /// answered from the store instead.
+    pub async fn request(&self, body: RequestBody) -> Result<ResponseBody> {
+        if let RequestBody::MessageHistory(query) = body {
+            let page = self.store.lock().unwrap().history(&self.name, &query);
+            return Ok(match page {
+                Ok(page) => ResponseBody::Messages(page),
+                Err(err) => ResponseBody::Error(err.downcast().unwrap_or_else(|err| {
+                    ResponseError::new(ResponseErrorKind::Internal, err.to_string())
+                })),
+            });
+        }
+
+        let sequence_number = self.next_sequence_number.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(sequence_number, tx);
+
+        if let Err(err) = self.requests.unbounded_send(Request {
+            sequence_number,
+            body,
+        }) {
+            self.pending.lock().unwrap().remove(&sequence_number);
+            bail!("backend {} is gone: {}", self.name, err);
+        }
+
+        rx.await
+            .map_err(|_| anyhow!("backend {} dropped request {}", self.name, sequence_number))
+    }
+
+    /// Routes a response from the backend to whoever is waiting on it.
+    pub fn handle_response(&self, response: Response) {
+        let waiter = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&response.sequence_number);
+        match waiter {
+            Some(waiter) => {
+                let _ = waiter.send(response.body);
+            }
+            None => eprintln!(
+                "backend {} answered unknown request {}",
+                self.name, response.sequence_number
+            ),
+        }
+    }
+}
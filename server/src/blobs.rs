@@ -0,0 +1,277 @@
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Result;
+use proto::backend::{
+    AttachmentID, AttachmentRange, MessageAttachment, RequestBody, ResponseBody,
+    ATTACHMENT_CHUNK_SIZE,
+};
+use sha2::{Digest, Sha256};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::backend::Backend;
+
+/// A counter for naming the files of downloads in progress.
+static NEXT_PARTIAL: AtomicU64 = AtomicU64::new(0);
+
+/// An on-disk cache of attachment data, keyed by content hash.
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    /// Opens the store at the given directory, creating it if necessary.
+    pub async fn open(root: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&root).await?;
+        Ok(BlobStore { root })
+    }
+
+    fn path(&self, id: &AttachmentID) -> Result<PathBuf> {
+        // ids come from backends, so make sure they can't escape the store
+        ensure!(
+            !id.0.is_empty() && id.0.bytes().all(|b| b.is_ascii_hexdigit()),
+            "invalid attachment id {:?}",
+            id.0
+        );
+        Ok(self.root.join(&id.0))
+    }
+
+    /// Returns whether the data for the attachment is already cached.
+    pub async fn contains(&self, id: &AttachmentID) -> bool {
+        match self.path(id) {
+            Ok(path) => fs::metadata(path).await.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Downloads an attachment from a backend in chunks, unless it is already cached.
+    pub async fn fetch(&self, backend: &Backend, attachment: &MessageAttachment) -> Result<()> {
+        if self.contains(&attachment.id).await {
+            return Ok(());
+        }
+
+        let path = self.path(&attachment.id)?;
+        // each download gets its own file, so concurrent fetches of one blob don't clash
+        let partial_path = path.with_extension(format!(
+            "{}-{}.part",
+            process::id(),
+            NEXT_PARTIAL.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = match download(backend, attachment, &partial_path).await {
+            Ok(()) => fs::rename(&partial_path, &path).await.map_err(Into::into),
+            Err(err) => Err(err),
+        };
+        if result.is_err() {
+            let _ = fs::remove_file(&partial_path).await;
+        }
+        result
+    }
+}
+
+/// Downloads an attachment from a backend in chunks to a file, and checks it against its hash.
+async fn download(backend: &Backend, attachment: &MessageAttachment, path: &Path) -> Result<()> {
+    let mut file = fs::File::create(path).await?;
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+    loop {
+        let request = RequestBody::AttachmentGet(AttachmentRange {
+            id: attachment.id.clone(),
+            offset,
+            length: ATTACHMENT_CHUNK_SIZE,
+        });
+        let chunk = match backend.request(request).await? {
+            ResponseBody::AttachmentChunk(chunk) => chunk,
+            ResponseBody::Error(err) => return Err(err.into()),
+            other => bail!("unexpected response to AttachmentGet: {:?}", other),
+        };
+        ensure!(
+            chunk.offset == offset,
+            "backend {} sent chunk at {} instead of {}",
+            backend.name(),
+            chunk.offset,
+            offset
+        );
+        ensure!(
+            chunk.data.len() <= ATTACHMENT_CHUNK_SIZE as usize,
+            "backend {} sent an oversized chunk",
+            backend.name()
+        );
+        ensure!(
+            offset + chunk.data.len() as u64 <= attachment.size,
+            "backend {} sent more than the {} bytes of attachment {}",
+            backend.name(),
+            attachment.size,
+            attachment.id.0
+        );
+
+        file.write_all(&chunk.data).await?;
+        hasher.input(&chunk.data);
+        offset += chunk.data.len() as u64;
+        if chunk.last {
+            break;
+        }
+        ensure!(
+            !chunk.data.is_empty(),
+            "backend {} sent an empty chunk before the end of the attachment",
+            backend.name()
+        );
+    }
+    file.flush().await?;
+
+    let id = AttachmentID(format!("{:x}", hasher.result()));
+    ensure!(
+        id == attachment.id && offset == attachment.size,
+        "attachment {} from backend {} failed verification",
+        attachment.id.0,
+        backend.name()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    use futures::{channel::mpsc, future, stream::StreamExt};
+    use proto::backend::{
+        AttachmentChunk, AttachmentID, MessageAttachment, Request, RequestBody, Response,
+        ResponseBody, ResponseError, ResponseErrorKind, ATTACHMENT_CHUNK_SIZE,
+    };
+    use rusqlite::Connection;
+    use tempfile::TempDir;
+
+    use super::BlobStore;
+    use crate::backend::Backend;
+    use crate::store::Store;
+
+    /// Starts a backend that serves `data` for any attachment, failing the request for the chunk
+    /// at `fail_at`, if any.
+    fn backend(data: Vec<u8>, fail_at: Option<u64>) -> Arc<Backend> {
+        let (tx, mut rx) = mpsc::unbounded::<Request>();
+        let store = Store::new(Connection::open_in_memory().unwrap()).unwrap();
+        let backend = Arc::new(Backend::new(
+            "test".to_owned(),
+            tx,
+            Arc::new(Mutex::new(store)),
+        ));
+        let responder = backend.clone();
+        tokio::spawn(async move {
+            while let Some(request) = rx.next().await {
+                let range = match request.body {
+                    RequestBody::AttachmentGet(range) => range,
+                    body => panic!("unexpected {:?}", body),
+                };
+                let body = if Some(range.offset) == fail_at {
+                    ResponseBody::Error(ResponseError::new(ResponseErrorKind::Internal, "oops"))
+                } else {
+                    let start = cmp::min(range.offset as usize, data.len());
+                    let end = cmp::min(start + range.length as usize, data.len());
+                    ResponseBody::AttachmentChunk(AttachmentChunk {
+                        id: range.id,
+                        offset: range.offset,
+                        data: data[start..end].to_vec(),
+                        last: end == data.len(),
+                    })
+                };
+                responder.handle_response(Response {
+                    sequence_number: request.sequence_number,
+                    body,
+                });
+            }
+        });
+        backend
+    }
+
+    fn attachment(data: &[u8]) -> MessageAttachment {
+        MessageAttachment {
+            id: AttachmentID::of(data),
+            mime: "application/octet-stream".parse().unwrap(),
+            size: data.len() as u64,
+            filename: None,
+        }
+    }
+
+    /// Lists the files in a directory.
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn data() -> Vec<u8> {
+        let len = ATTACHMENT_CHUNK_SIZE as usize * 5 / 2;
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn chunked() {
+        let dir = TempDir::new().unwrap();
+        let store = BlobStore::open(dir.path().to_owned()).await.unwrap();
+        let data = data();
+        let attachment = attachment(&data);
+        let backend = backend(data.clone(), None);
+
+        // fetching the same blob twice at once is fine
+        let (first, second) = future::join(
+            store.fetch(&backend, &attachment),
+            store.fetch(&backend, &attachment),
+        )
+        .await;
+        first.unwrap();
+        second.unwrap();
+        assert!(store.contains(&attachment.id).await);
+        assert_eq!(files(dir.path()), vec![attachment.id.0.clone()]);
+        let stored = std::fs::read(dir.path().join(&attachment.id.0)).unwrap();
+        assert!(stored == data);
+    }
+
+    #[tokio::test]
+    async fn hash_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let store = BlobStore::open(dir.path().to_owned()).await.unwrap();
+        let data = data();
+        let mut tampered = data.clone();
+        tampered[1000] ^= 1;
+        let attachment = attachment(&data);
+
+        assert!(store
+            .fetch(&backend(tampered, None), &attachment)
+            .await
+            .is_err());
+        assert!(!store.contains(&attachment.id).await);
+        assert!(files(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn failure_midway() {
+        let dir = TempDir::new().unwrap();
+        let store = BlobStore::open(dir.path().to_owned()).await.unwrap();
+        let data = data();
+        let attachment = attachment(&data);
+        let backend = backend(data, Some(u64::from(ATTACHMENT_CHUNK_SIZE)));
+
+        assert!(store.fetch(&backend, &attachment).await.is_err());
+        assert!(files(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_ids() {
+        let dir = TempDir::new().unwrap();
+        let store = BlobStore::open(dir.path().join("blobs")).await.unwrap();
+        let backend = backend(Vec::new(), None);
+        for id in &["", "../escape", "/etc/passwd", "abc.part"] {
+            let mut attachment = attachment(b"");
+            attachment.id = AttachmentID(id.to_string());
+            assert!(!store.contains(&attachment.id).await);
+            assert!(store.fetch(&backend, &attachment).await.is_err());
+        }
+        assert_eq!(files(dir.path()), vec!["blobs"]);
+        assert!(files(&dir.path().join("blobs")).is_empty());
+    }
+}
@@ -5,6 +5,8 @@ use std::path::PathBuf;
 pub struct Config {
     pub bind_host: String,
     pub bind_port: u16,
+    #[serde(default = "default_blob_dir")]
+    pub blob_dir: PathBuf,
     pub backends: BTreeMap<String, BackendConfig>,
 }
 
@@ -12,3 +14,7 @@ pub struct Config {
 pub struct BackendConfig {
     pub path: PathBuf,
 }
+
+fn default_blob_dir() -> PathBuf {
+    PathBuf::from("blobs")
+}
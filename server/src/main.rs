@@ -1,29 +1,51 @@
 #[macro_use]
+extern crate anyhow;
+#[macro_use]
 extern crate serde;
 
+mod backend;
+mod blobs;
 mod client;
 mod config;
+mod store;
 
 use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::Error;
 use anyhow::Result;
 use futures::{
-    future::{self},
+    channel::mpsc,
+    future::{self, Either, FutureExt},
     stream::StreamExt,
 };
-use proto::backend::InitInfo;
+use proto::backend::{InitInfo, RequestBody, ResponseBody, ResponseOrUpdate, Update};
 use rusqlite::Connection;
 use serde_json::Value as JsonValue;
 use structopt::StructOpt;
-use tokio::{self, fs::File, io::AsyncReadExt, net::TcpListener, process::Command};
+use tokio::{
+    self,
+    fs::File,
+    io::AsyncReadExt,
+    net::TcpListener,
+    process::{Child, Command},
+    signal::{
+        self,
+        unix::{self as unix_signal, SignalKind},
+    },
+    time,
+};
 use tokio_serde::{formats::Json, Framed};
-use tokio_util::codec::{BytesCodec, FramedRead};
+use tokio_util::codec::{BytesCodec, FramedRead, FramedWrite};
 
+use crate::backend::Backend;
+use crate::blobs::BlobStore;
 use crate::client::Client;
 use crate::config::Config;
+use crate::store::Store;
 
 #[derive(Debug, StructOpt)]
 struct Args {
@@ -31,10 +53,47 @@ struct Args {
     config_path: PathBuf,
 }
 
-struct Backend {}
+/// How long to give backends to disconnect once asked to, before killing them.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 struct Server {
-    backends: BTreeMap<String, Backend>,
+    backends: BTreeMap<String, Arc<Backend>>,
+    children: Vec<Child>,
+}
+
+impl Server {
+    /// Asks every backend to disconnect and exit, and waits until they have. Any that are still
+    /// running after `SHUTDOWN_TIMEOUT` are killed.
+    async fn shutdown(self) {
+        let backends = &self.backends;
+        let requests = future::join_all(backends.values().map(|backend| async move {
+            match backend.request(RequestBody::Shutdown).await {
+                Ok(ResponseBody::Success) => (),
+                Ok(response) => eprintln!(
+                    "backend {} didn't shut down cleanly: {:?}",
+                    backend.name(),
+                    response
+                ),
+                Err(err) => eprintln!("error shutting down: {}", err),
+            }
+        }));
+        let exits = future::join_all(self.children);
+        let done = future::join(requests, exits);
+        if time::timeout(SHUTDOWN_TIMEOUT, done).await.is_err() {
+            // dropping the children kills them
+            eprintln!("backends took too long to shut down, killing them");
+        }
+    }
+}
+
+/// Waits for the server to be told to stop, with SIGINT or SIGTERM.
+async fn stop_signal() -> Result<()> {
+    let mut terminate = unix_signal::signal(SignalKind::terminate())?;
+    let stopped = future::select(signal::ctrl_c().boxed(), terminate.recv().boxed()).await;
+    match stopped {
+        Either::Left((result, _)) => Ok(result?),
+        Either::Right(_) => Ok(()),
+    }
 }
 
 #[tokio::main]
@@ -48,14 +107,18 @@ async fn main() -> Result<()> {
         toml::from_str(&contents)?
     };
 
-    let conn = Connection::open_in_memory()?;
+    let store = Arc::new(Mutex::new(Store::new(Connection::open_in_memory()?)?));
+    let blobs = Arc::new(BlobStore::open(config.blob_dir.clone()).await?);
     let mut server = Server {
         backends: BTreeMap::new(),
+        children: Vec::new(),
     };
 
     for (name, backend) in config.backends.iter() {
         let mut cmd = Command::new(&backend.path);
-        cmd.stdin(Stdio::piped()).stdout(Stdio::piped());
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true);
         cmd.arg("--config")
             .arg(args.config_path.as_os_str())
             .arg("--backend-name")
@@ -79,19 +142,70 @@ async fn main() -> Result<()> {
             }
         };
         eprintln!("backend init: {:?}", init);
-        tokio::spawn(stdout.for_each(|message| {
+
+        let (requests_tx, requests_rx) = mpsc::unbounded();
+        let stdin = Framed::<_, JsonValue, JsonValue, _>::new(
+            FramedWrite::new(input, BytesCodec::new()),
+            Json::<JsonValue, JsonValue>::default(),
+        );
+        tokio::spawn(
+            requests_rx
+                .map(|request| Ok(serde_json::to_value(request).unwrap()))
+                .forward(stdin)
+                .map(|_| ()),
+        );
+
+        let backend = Arc::new(Backend::new(name.clone(), requests_tx, store.clone()));
+        server.backends.insert(name.clone(), backend.clone());
+
+        let blobs = blobs.clone();
+        let store = store.clone();
+        tokio::spawn(stdout.for_each(move |message| {
+            let backend = backend.clone();
+            let blobs = blobs.clone();
+            let store = store.clone();
             async move {
+                let message = match message.map(serde_json::from_value::<ResponseOrUpdate>) {
+                    Ok(Ok(message)) => message,
+                    Ok(Err(err)) => return eprintln!("invalid message: {}", err),
+                    Err(err) => return eprintln!("error: {}", err),
+                };
                 match message {
-                    Ok(message) => println!("json: {}", message),
-                    Err(err) => eprintln!("error: {}", err),
+                    ResponseOrUpdate::Response(response) => backend.handle_response(response),
+                    ResponseOrUpdate::Update(update) => {
+                        println!("update: {:?}", update);
+                        match update {
+                            Update::MessageUpsert(message) => {
+                                let stored = store
+                                    .lock()
+                                    .unwrap()
+                                    .upsert_message(backend.name(), &message);
+                                if let Err(err) = stored {
+                                    eprintln!("error storing message: {}", err);
+                                }
+                                tokio::spawn(async move {
+                                    for attachment in message.attachments.iter() {
+                                        if let Err(err) = blobs.fetch(&backend, attachment).await {
+                                            eprintln!("error fetching attachment: {}", err);
+                                        }
+                                    }
+                                });
+                            }
+                            Update::MessageDelete(id) => {
+                                let deleted =
+                                    store.lock().unwrap().delete_message(backend.name(), &id);
+                                if let Err(err) = deleted {
+                                    eprintln!("error deleting message: {}", err);
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
                 }
             }
         }));
 
-        let backend = Backend {};
-        server.backends.insert(name.clone(), backend);
-
-        tokio::spawn(child);
+        server.children.push(child);
     }
 
     // listen for clients
@@ -107,5 +221,8 @@ async fn main() -> Result<()> {
     };
     tokio::spawn(client_loop);
 
-    Ok(future::pending().await)
+    stop_signal().await?;
+    println!("shutting down");
+    server.shutdown().await;
+    Ok(())
 }
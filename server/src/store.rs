@@ -0,0 +1,394 @@
+use anyhow::Result;
+use proto::backend::{
+    HistoryAnchor, HistoryCursor, HistoryDirection, HistoryQuery, Message, MessageID, MessagePage,
+    ResponseError, ResponseErrorKind, RoomIDOrUserID,
+};
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+
+/// A position in the history of a room: a creation time in milliseconds, and the ID of the message
+/// there to order messages created in the same millisecond, or `None` for the time itself, which
+/// excludes every message created at that time.
+type Position = (i64, Option<String>);
+
+/// Storage for messages received from backends, from which history can be served.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Creates the store's tables in the given database if they don't exist yet.
+    pub fn new(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                backend TEXT NOT NULL,
+                id TEXT NOT NULL,
+                room TEXT NOT NULL,
+                peer TEXT NOT NULL,
+                create_time INTEGER NOT NULL,
+                message TEXT NOT NULL,
+                PRIMARY KEY (backend, id)
+            );
+            CREATE INDEX IF NOT EXISTS messages_history
+                ON messages (backend, room, create_time, id);
+            CREATE INDEX IF NOT EXISTS messages_peer_history
+                ON messages (backend, peer, create_time, id);",
+        )?;
+        Ok(Store { conn })
+    }
+
+    /// Stores a new or edited message.
+    pub fn upsert_message(&self, backend: &str, message: &Message) -> Result<()> {
+        let _ = self.conn.execute(
+            "INSERT OR REPLACE INTO messages (backend, id, room, peer, create_time, message)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                backend,
+                message.id.0,
+                room_key(&message.recipient),
+                peer_key(message),
+                message.create_time.timestamp_millis(),
+                serde_json::to_string(message)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Removes a deleted message.
+    pub fn delete_message(&self, backend: &str, id: &MessageID) -> Result<()> {
+        let _ = self.conn.execute(
+            "DELETE FROM messages WHERE backend = ?1 AND id = ?2",
+            params![backend, id.0],
+        )?;
+        Ok(())
+    }
+
+    /// Reads a page of history.
+    pub fn history(&self, backend: &str, query: &HistoryQuery) -> Result<MessagePage> {
+        let room = room_key(&query.room);
+        let (cmp, end_cmp, order) = match query.direction {
+            HistoryDirection::Before => ("<", ">", "DESC"),
+            HistoryDirection::After => (">", "<", "ASC"),
+        };
+        if let (HistoryAnchor::Latest, HistoryDirection::After) = (&query.anchor, query.direction) {
+            return Ok(MessagePage {
+                messages: Vec::new(),
+                cursor: None,
+            });
+        }
+
+        let start = self.position(backend, &room, &query.anchor)?;
+        let end = match query.end {
+            Some(ref end) => self.position(backend, &room, end)?,
+            None => None,
+        };
+        // fetch one extra row to find out whether there's more history after this page
+        let limit = i64::from(query.limit) + 1;
+
+        let mut sql = String::from(
+            "SELECT create_time, id, message FROM messages
+                WHERE backend = ?1 AND (room = ?2 OR peer = ?2)",
+        );
+        let mut params: Vec<&dyn ToSql> = vec![&backend, &room];
+        for (position, cmp) in start
+            .iter()
+            .map(|p| (p, cmp))
+            .chain(end.iter().map(|p| (p, end_cmp)))
+        {
+            let t = params.len() + 1;
+            params.push(&position.0);
+            match position.1 {
+                Some(ref id) => {
+                    sql.push_str(&format!(
+                        " AND (create_time {cmp} ?{t} OR (create_time = ?{t} AND id {cmp} ?{id}))",
+                        cmp = cmp,
+                        t = t,
+                        id = t + 1,
+                    ));
+                    params.push(id);
+                }
+                None => sql.push_str(&format!(" AND create_time {} ?{}", cmp, t)),
+            }
+        }
+        sql.push_str(&format!(
+            " ORDER BY create_time {order}, id {order} LIMIT ?{}",
+            params.len() + 1,
+            order = order,
+        ));
+        params.push(&limit);
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok(((row.get(0)?, row.get(1)?), row.get::<_, String>(2)?))
+            })?
+            .collect::<Result<Vec<((i64, String), String)>, _>>()?;
+
+        let more = rows.len() > query.limit as usize;
+        let mut messages = Vec::new();
+        let mut last = None;
+        for (position, message) in rows.into_iter().take(query.limit as usize) {
+            messages.push(serde_json::from_str::<Message>(&message)?);
+            last = Some(position);
+        }
+        if query.direction == HistoryDirection::Before {
+            messages.reverse();
+        }
+
+        Ok(MessagePage {
+            messages,
+            cursor: last
+                .filter(|_| more)
+                .map(|(t, id)| HistoryCursor(format!("{}:{}", t, id))),
+        })
+    }
+
+    /// Finds the position of an anchor in a room, or `None` if it's the newest end of history.
+    ///
+    /// Fails with a `ResponseError` if the anchor isn't a message in the room or a valid cursor.
+    fn position(
+        &self,
+        backend: &str,
+        room: &str,
+        anchor: &HistoryAnchor,
+    ) -> Result<Option<Position>> {
+        Ok(match anchor {
+            HistoryAnchor::Latest => None,
+            HistoryAnchor::Message(id) => {
+                let create_time = self
+                    .conn
+                    .query_row(
+                        "SELECT create_time FROM messages
+                            WHERE backend = ?1 AND id = ?2 AND (room = ?3 OR peer = ?3)",
+                        params![backend, id.0, room],
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                    .ok_or_else(|| {
+                        ResponseError::new(
+                            ResponseErrorKind::NotFound,
+                            format!("no such message in this room: {}", id.0),
+                        )
+                    })?;
+                Some((create_time, Some(id.0.clone())))
+            }
+            HistoryAnchor::Time { time } => Some((time.timestamp_millis(), None)),
+            HistoryAnchor::Cursor(HistoryCursor(cursor)) => {
+                let mut parts = cursor.splitn(2, ':');
+                match (parts.next().map(str::parse), parts.next()) {
+                    (Some(Ok(t)), Some(id)) => Some((t, Some(id.to_owned()))),
+                    _ => {
+                        return Err(ResponseError::new(
+                            ResponseErrorKind::InvalidRequest,
+                            format!("invalid history cursor: {}", cursor),
+                        )
+                        .into())
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// The key messages are grouped under for history.
+fn room_key(recipient: &RoomIDOrUserID) -> String {
+    match recipient {
+        RoomIDOrUserID::Room(room) => format!("room:{}", room.0),
+        RoomIDOrUserID::User(user) => format!("user:{}", user.0),
+    }
+}
+
+/// The key a message is grouped under besides its recipient's. The store doesn't know which user
+/// is ours, so a direct message is also filed under its sender, and the conversation with a user is
+/// everything they were sent along with everything they sent. Messages to rooms only belong to the
+/// room.
+fn peer_key(message: &Message) -> String {
+    match message.recipient {
+        RoomIDOrUserID::Room(_) => room_key(&message.recipient),
+        RoomIDOrUserID::User(_) => format!("user:{}", message.sender.0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proto::backend::{
+        HistoryAnchor, HistoryCursor, HistoryDirection, HistoryQuery, Message, MessageID,
+        MessagePage, ResponseError, ResponseErrorKind, RoomID, RoomIDOrUserID, UserID,
+    };
+    use rusqlite::Connection;
+    use serde_json::json;
+
+    use super::Store;
+
+    fn room(name: &str) -> RoomIDOrUserID {
+        RoomIDOrUserID::Room(RoomID(name.to_owned()))
+    }
+
+    fn user(name: &str) -> RoomIDOrUserID {
+        RoomIDOrUserID::User(UserID(name.to_owned()))
+    }
+
+    fn time(ms: i64) -> HistoryAnchor {
+        serde_json::from_value(json!({ "type": "Time", "value": { "time": ms } })).unwrap()
+    }
+
+    fn message(id: &str) -> HistoryAnchor {
+        HistoryAnchor::Message(MessageID(id.to_owned()))
+    }
+
+    /// A store holding messages in two rooms, and direct messages between us and two users.
+    fn store() -> Store {
+        let store = Store::new(Connection::open_in_memory().unwrap()).unwrap();
+        let messages = vec![
+            ("a1", "alice", room("#a"), 1000),
+            ("a2", "bob", room("#a"), 2000),
+            ("a3", "alice", room("#a"), 2000),
+            ("a4", "me", room("#a"), 3000),
+            ("b1", "bob", room("#b"), 2000),
+            ("dm1", "alice", user("me"), 1500),
+            ("dm2", "me", user("alice"), 2500),
+            ("dm3", "bob", user("me"), 2600),
+        ];
+        for (id, sender, recipient, create_time) in messages {
+            let mut message: Message = serde_json::from_value(json!({
+                "id": id,
+                "sender": sender,
+                "recipient": "",
+                "attachments": [],
+                "content": { "type": "Text", "value": id },
+                "create_time": create_time,
+                "edit_time": create_time,
+            }))
+            .unwrap();
+            message.recipient = recipient;
+            store.upsert_message("irc", &message).unwrap();
+        }
+        store
+    }
+
+    fn history(
+        store: &Store,
+        room: RoomIDOrUserID,
+        anchor: HistoryAnchor,
+        direction: HistoryDirection,
+        end: Option<HistoryAnchor>,
+        limit: u32,
+    ) -> MessagePage {
+        let query = HistoryQuery {
+            room,
+            anchor,
+            direction,
+            end,
+            limit,
+        };
+        store.history("irc", &query).unwrap()
+    }
+
+    fn ids(page: &MessagePage) -> Vec<&str> {
+        page.messages.iter().map(|m| &m.id.0[..]).collect()
+    }
+
+    #[test]
+    fn pages() {
+        use HistoryDirection::*;
+        let store = store();
+
+        let page = history(&store, room("#a"), HistoryAnchor::Latest, Before, None, 2);
+        assert_eq!(ids(&page), vec!["a3", "a4"]);
+        let cursor = HistoryAnchor::Cursor(page.cursor.unwrap());
+        let page = history(&store, room("#a"), cursor, Before, None, 2);
+        assert_eq!(ids(&page), vec!["a1", "a2"]);
+        assert_eq!(page.cursor, None);
+
+        let page = history(&store, room("#a"), message("a1"), After, None, 2);
+        assert_eq!(ids(&page), vec!["a2", "a3"]);
+        let cursor = HistoryAnchor::Cursor(page.cursor.unwrap());
+        let page = history(&store, room("#a"), cursor, After, None, 2);
+        assert_eq!(ids(&page), vec!["a4"]);
+        assert_eq!(page.cursor, None);
+
+        let page = history(&store, room("#a"), HistoryAnchor::Latest, After, None, 2);
+        assert!(page.messages.is_empty());
+    }
+
+    #[test]
+    fn bounds() {
+        use HistoryDirection::*;
+        let store = store();
+
+        // messages at exactly a time are excluded, whether it's the anchor or the end
+        let page = history(&store, room("#a"), time(2000), Before, None, 10);
+        assert_eq!(ids(&page), vec!["a1"]);
+        let page = history(&store, room("#a"), time(2000), After, None, 10);
+        assert_eq!(ids(&page), vec!["a4"]);
+        let end = Some(time(2000));
+        let page = history(&store, room("#a"), HistoryAnchor::Latest, Before, end, 10);
+        assert_eq!(ids(&page), vec!["a4"]);
+        let page = history(&store, room("#a"), time(0), After, Some(time(2000)), 10);
+        assert_eq!(ids(&page), vec!["a1"]);
+
+        let page = history(
+            &store,
+            room("#a"),
+            message("a4"),
+            Before,
+            Some(message("a2")),
+            10,
+        );
+        assert_eq!(ids(&page), vec!["a3"]);
+        let page = history(
+            &store,
+            room("#a"),
+            message("a2"),
+            After,
+            Some(time(3000)),
+            10,
+        );
+        assert_eq!(ids(&page), vec!["a3"]);
+    }
+
+    #[test]
+    fn direct_messages() {
+        use HistoryDirection::*;
+        let store = store();
+
+        let page = history(
+            &store,
+            user("alice"),
+            HistoryAnchor::Latest,
+            Before,
+            None,
+            10,
+        );
+        assert_eq!(ids(&page), vec!["dm1", "dm2"]);
+        let page = history(&store, user("alice"), message("dm2"), Before, None, 10);
+        assert_eq!(ids(&page), vec!["dm1"]);
+        let page = history(&store, user("bob"), time(2000), After, None, 10);
+        assert_eq!(ids(&page), vec!["dm3"]);
+    }
+
+    #[test]
+    fn invalid_anchors() {
+        let store = store();
+        let error_kind = |anchor| {
+            let query = HistoryQuery {
+                room: room("#a"),
+                anchor,
+                direction: HistoryDirection::Before,
+                end: None,
+                limit: 10,
+            };
+            store
+                .history("irc", &query)
+                .unwrap_err()
+                .downcast::<ResponseError>()
+                .unwrap()
+                .kind
+        };
+
+        // messages in other rooms or from other backends aren't anchors
+        assert_eq!(error_kind(message("b1")), ResponseErrorKind::NotFound);
+        assert_eq!(error_kind(message("x1")), ResponseErrorKind::NotFound);
+        let cursor = HistoryAnchor::Cursor(HistoryCursor("later".to_owned()));
+        assert_eq!(error_kind(cursor), ResponseErrorKind::InvalidRequest);
+    }
+}